@@ -25,11 +25,13 @@ export default function SettingsItem({
   useEffect(() => {
     invoke("get_configuration_option", {
       option_name: settingsConfigString,
-    }).then((data) => {
-      if (data) {
-        setSettingsItemStatus(data);
-      }
-    });
+    })
+      .then((data) => {
+        if (data) {
+          setSettingsItemStatus(data);
+        }
+      })
+      .catch((error) => console.error(error.message));
   }, []);
 
   return settingsItemStatus != "" ? (
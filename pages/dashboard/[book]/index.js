@@ -62,21 +62,25 @@ export default function Book() {
 
     await invoke("get_configuration_option", {
       option_name: settingsEnums.current.ENDLESS_SCROLL,
-    }).then((data) => {
-      console.log(data);
-      if (data) {
-        setScrollStyle(data === "true");
-        scrollStyleState.current = data === "true";
-      }
-    });
+    })
+      .then((data) => {
+        console.log(data);
+        if (data) {
+          setScrollStyle(data === "true");
+          scrollStyleState.current = data === "true";
+        }
+      })
+      .catch((error) => console.error(error.message));
 
     await invoke("get_configuration_option", {
       option_name: settingsEnums.current.COVER_BACKGROUND,
-    }).then((data) => {
-      if (data) {
-        coverBackgroundState.current = data === "true";
-      }
-    });
+    })
+      .then((data) => {
+        if (data) {
+          coverBackgroundState.current = data === "true";
+        }
+      })
+      .catch((error) => console.error(error.message));
   }
 
   useEffect(() => {
@@ -94,54 +98,56 @@ export default function Book() {
       if (book && !isBookLoaded.current) {
         isBookLoaded.current = true;
 
-        invoke("load_book", { title: book }).then(async (bookInfo) => {
-          if (bookInfo) {
-            bookEpub.current = ePub();
-
-            if (!bookEpub.current.isOpen) {
-              bookEpub.current.open(convertFileSrc(bookInfo.book_location));
-
-              if (
-                bookBackgroundUrl.current &&
-                coverBackgroundState.current === true
-              ) {
-                bookBackgroundUrl.current.style.backgroundImage = `url(${convertFileSrc(
-                  bookInfo.cover_location,
-                )})`;
-              }
-              try {
-                await bookEpub.current.ready;
-
-                let bookWidth = getWidth();
-                const scrollValue = scrollStyleState.current;
-
-                let settings = {
-                  width: bookWidth,
-                  height: getHeight(),
-                  spread: "none",
-                };
-
-                if (scrollValue) {
-                  settings.manager = "continuous";
-                  settings.flow = "scrolled";
-                } else {
-                  settings.manager = "default";
+        invoke("load_book", { title: book })
+          .then(async (bookInfo) => {
+            if (bookInfo) {
+              bookEpub.current = ePub();
+
+              if (!bookEpub.current.isOpen) {
+                bookEpub.current.open(convertFileSrc(bookInfo.book_location));
+
+                if (
+                  bookBackgroundUrl.current &&
+                  coverBackgroundState.current === true
+                ) {
+                  bookBackgroundUrl.current.style.backgroundImage = `url(${convertFileSrc(
+                    bookInfo.cover_location,
+                  )})`;
+                }
+                try {
+                  await bookEpub.current.ready;
+
+                  let bookWidth = getWidth();
+                  const scrollValue = scrollStyleState.current;
+
+                  let settings = {
+                    width: bookWidth,
+                    height: getHeight(),
+                    spread: "none",
+                  };
+
+                  if (scrollValue) {
+                    settings.manager = "continuous";
+                    settings.flow = "scrolled";
+                  } else {
+                    settings.manager = "default";
+                  }
+
+                  //duplicated?
+                  bookRender.current = bookEpub.current.renderTo(
+                    document.getElementById("viewer"),
+                    settings,
+                  );
+
+                  bookRender.current.display();
+                } catch {
+                  //handle this
+                  //no :P
                 }
-
-                //duplicated?
-                bookRender.current = bookEpub.current.renderTo(
-                  document.getElementById("viewer"),
-                  settings,
-                );
-
-                bookRender.current.display();
-              } catch {
-                //handle this
-                //no :P
               }
             }
-          }
-        });
+          })
+          .catch((error) => console.error(error.message));
       }
     }
     loadBook();
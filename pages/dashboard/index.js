@@ -1,5 +1,6 @@
 /* eslint-disable camelcase */
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 import { useEffect, useState } from "react";
 import BookCover from "@/components/book/book-cover";
 import { isValidDirectoryPath } from "@/lib/regex";
@@ -14,7 +15,12 @@ export default function BookDashboard() {
   useEffect(() => {
     async function loadImages() {
       const start = performance.now();
-      setUsersBooks(await invoke("initialize_books"));
+      try {
+        setUsersBooks(await invoke("initialize_books"));
+      } catch (error) {
+        console.error(error.message);
+        setUsersBooks(null);
+      }
 
       const executionTime = performance.now() - start;
 
@@ -22,15 +28,28 @@ export default function BookDashboard() {
       setImagesStatus(true);
     }
 
-    invoke("get_configuration_option", {
-      option_name: "book_location",
-    }).then((data) => {
-      if (isValidDirectoryPath(data)) {
-        setDirectoryStatus(data);
-        loadImages();
+    // The library scan runs in the background, the shelf is refreshed once it has added books
+    const unlisten = listen("scan-finished", async (event) => {
+      if (event.payload.added > 0) {
+        setUsersBooks(await invoke("get_library_books"));
       }
-      setDirectoryChecked(true);
     });
+
+    invoke("get_configuration_option", {
+      option_name: "book_location",
+    })
+      .then((data) => {
+        if (isValidDirectoryPath(data)) {
+          setDirectoryStatus(data);
+          loadImages();
+        }
+      })
+      .catch((error) => console.error(error.message))
+      .finally(() => setDirectoryChecked(true));
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
   }, []);
 
   if (!directoryChecked) {
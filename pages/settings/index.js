@@ -34,7 +34,10 @@ export default function Settings() {
         );
       })
       .catch((error) => {
-        notify(notificationState.ERROR, "An error occurred when resetting.");
+        notify(
+          notificationState.ERROR,
+          `An error occurred when resetting. ${error.message}`,
+        );
       });
   };
   const importOldHandler = (data) => {
@@ -45,7 +48,7 @@ export default function Settings() {
       .catch((error) => {
         notify(
           notificationState.ERROR,
-          `An error occurred while importing the old books.  ${error}`,
+          `An error occurred while importing the old books. ${error.message}`,
         );
       });
   };
@@ -60,7 +63,22 @@ export default function Settings() {
       .catch((error) => {
         notify(
           notificationState.ERROR,
-          `An error occurred while exporting the current books. ${error}`,
+          `An error occurred while exporting the current books. ${error.message}`,
+        );
+      });
+  };
+  const exportLogsHandler = (data) => {
+    invoke("export_logs", { path: data })
+      .then((archivePath) => {
+        notify(
+          notificationState.SUCCESS,
+          `Exported the logs to ${archivePath}`,
+        );
+      })
+      .catch((error) => {
+        notify(
+          notificationState.ERROR,
+          `An error occurred while exporting the logs. ${error.message}`,
         );
       });
   };
@@ -127,6 +145,24 @@ export default function Settings() {
                   Export current books
                 </button>
               </div>
+              <div className="mt-2 flex h-16 w-44 items-center justify-center rounded-xl border bg-white p-4">
+                <button
+                  className="font-sm rounded-lg border-4 border-white bg-yellow-700 px-5 py-1 text-sm font-bold text-white transition-colors duration-300 ease-in-out hover:border-yellow-500 hover:bg-yellow-800"
+                  type="button"
+                  onClick={() => {
+                    open({
+                      directory: true,
+                      multiple: false,
+                    }).then((data) => {
+                      if (data) {
+                        exportLogsHandler(data);
+                      }
+                    });
+                  }}
+                >
+                  Export logs
+                </button>
+              </div>
             </div>
             <div className="mt-2 flex h-16 w-44 items-center justify-center rounded-xl border bg-white p-4">
               <button
@@ -1,5 +1,5 @@
 -- Add migration script here
-CREATE TABLE books (
+CREATE TABLE IF NOT EXISTS books (
     id INTEGER PRIMARY KEY AUTOINCREMENT,  -- Unique identifier for each book
     cover_location TEXT NOT NULL,           -- Path or URL to the book cover
     book_location TEXT NOT NULL,            -- Path or URL to the book content
@@ -0,0 +1,19 @@
+-- Metadata carried over from other libraries (calibre) or read from the epub
+ALTER TABLE books ADD COLUMN authors TEXT;          -- Display names, joined with ' & '
+ALTER TABLE books ADD COLUMN series TEXT;           -- Name of the series the book belongs to
+ALTER TABLE books ADD COLUMN series_index REAL;     -- Position within the series
+ALTER TABLE books ADD COLUMN description TEXT;      -- Blurb/comments, may contain html
+ALTER TABLE books ADD COLUMN rating INTEGER;        -- 0 to 5
+
+CREATE TABLE IF NOT EXISTS book_tags (
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (book_id, tag)
+);
+
+CREATE TABLE IF NOT EXISTS book_identifiers (
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,                     -- isbn, goodreads, amazon...
+    value TEXT NOT NULL,
+    PRIMARY KEY (book_id, kind)
+);
@@ -0,0 +1,21 @@
+-- People credited on books, several spellings of a name resolve to one row through the aliases
+CREATE TABLE IF NOT EXISTS authors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,                     -- Display name, "J. R. R. Tolkien"
+    sort_name TEXT NOT NULL,                -- "Tolkien, J. R. R."
+    name_key TEXT NOT NULL UNIQUE           -- Normalized form used for matching, "j r r tolkien"
+);
+
+CREATE TABLE IF NOT EXISTS author_aliases (
+    alias_key TEXT PRIMARY KEY,             -- Normalized form of the alternate spelling
+    author_id INTEGER NOT NULL REFERENCES authors(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS book_authors (
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    author_id INTEGER NOT NULL REFERENCES authors(id) ON DELETE CASCADE,
+    role TEXT NOT NULL DEFAULT 'aut',       -- MARC relator code taken from opf:role
+    PRIMARY KEY (book_id, author_id, role)
+);
+
+CREATE INDEX IF NOT EXISTS book_authors_author ON book_authors (author_id);
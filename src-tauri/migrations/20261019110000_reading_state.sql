@@ -0,0 +1,18 @@
+-- Used by the dashboard to filter and sort the library
+ALTER TABLE books ADD COLUMN date_added TEXT;                           -- UTC, filled in by the trigger below
+ALTER TABLE books ADD COLUMN last_read TEXT;                            -- UTC, when the book was last opened
+ALTER TABLE books ADD COLUMN progress REAL;                             -- 0 to 1
+ALTER TABLE books ADD COLUMN read_status TEXT NOT NULL DEFAULT 'unread'; -- unread, reading or finished
+
+-- Existing books don't know when they were added, the upgrade is the best guess we have
+UPDATE books SET date_added = CURRENT_TIMESTAMP WHERE date_added IS NULL;
+
+-- Sqlite can't default an added column to the current time, so inserts get it from here
+CREATE TRIGGER IF NOT EXISTS books_date_added AFTER INSERT ON books
+WHEN NEW.date_added IS NULL
+BEGIN
+    UPDATE books SET date_added = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE INDEX IF NOT EXISTS books_date_added_index ON books (date_added);
+CREATE INDEX IF NOT EXISTS books_series_index ON books (series);
@@ -0,0 +1,13 @@
+-- One row per stretch of reading, sent by the reader when a book is closed or put down
+CREATE TABLE IF NOT EXISTS reading_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    started_at TEXT NOT NULL,               -- UTC, same format as CURRENT_TIMESTAMP
+    ended_at TEXT NOT NULL,
+    start_progress REAL,                    -- 0 to 1
+    end_progress REAL,
+    pages_read INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS reading_sessions_book ON reading_sessions (book_id);
+CREATE INDEX IF NOT EXISTS reading_sessions_started_at ON reading_sessions (started_at);
@@ -0,0 +1,11 @@
+-- read_status can now also be 'abandoned'
+ALTER TABLE books ADD COLUMN date_started TEXT;     -- UTC, first time the book was opened or marked as reading
+ALTER TABLE books ADD COLUMN date_finished TEXT;    -- UTC
+ALTER TABLE books ADD COLUMN review TEXT;
+
+-- Books read before this upgrade get their dates from their sessions
+UPDATE books SET date_started = (SELECT MIN(started_at) FROM reading_sessions s WHERE s.book_id = books.id)
+WHERE date_started IS NULL;
+
+UPDATE books SET date_finished = (SELECT MIN(ended_at) FROM reading_sessions s WHERE s.book_id = books.id AND s.end_progress >= 0.99)
+WHERE read_status = 'finished' AND date_finished IS NULL;
@@ -0,0 +1,5 @@
+-- Filled in lazily by the duplicate finder, the size tells when a file changed and needs hashing again
+ALTER TABLE books ADD COLUMN content_hash TEXT;     -- sha256 of the whole file, hex
+ALTER TABLE books ADD COLUMN file_size INTEGER;     -- Bytes, at the time the hash was taken
+
+CREATE INDEX IF NOT EXISTS books_content_hash ON books (content_hash);
@@ -0,0 +1,12 @@
+-- Books taken out of the library, kept so the removal can be undone
+CREATE TABLE IF NOT EXISTS trash (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_location TEXT NOT NULL,            -- Where the file was, restoring puts it back there
+    title TEXT NOT NULL,
+    trash_location TEXT,                    -- The file in the trash folder, null when only the library entry was removed
+    cover_location TEXT,                    -- The cover, moved to the trash folder when no other book uses it
+    snapshot TEXT NOT NULL,                 -- JSON of the book row with its tags, identifiers and reading sessions
+    removed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS trash_book_location ON trash (book_location);
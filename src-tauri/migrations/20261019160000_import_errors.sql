@@ -0,0 +1,10 @@
+-- Files in the library folder that couldn't be added, so they can be looked at, retried or ignored
+CREATE TABLE IF NOT EXISTS import_errors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_location TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,                         -- The kind of error, like parse or cover
+    reason TEXT NOT NULL,                       -- The message shown to the user
+    attempts INTEGER NOT NULL DEFAULT 1,
+    ignored BOOLEAN NOT NULL DEFAULT FALSE,     -- Ignored files are skipped by the library scan
+    failed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP -- UTC, the latest failure
+);
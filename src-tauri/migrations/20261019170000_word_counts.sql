@@ -0,0 +1,14 @@
+-- Lengths counted from the spine when a book is imported, books from before are counted by the next library scan
+ALTER TABLE books ADD COLUMN word_count INTEGER;
+ALTER TABLE books ADD COLUMN character_count INTEGER;    -- Whitespace isn't counted
+
+CREATE TABLE IF NOT EXISTS book_chapters (
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    spine_index INTEGER NOT NULL,           -- Position in the spine, counting every item
+    href TEXT NOT NULL,                     -- The file inside the epub
+    word_count INTEGER NOT NULL,
+    character_count INTEGER NOT NULL,
+    PRIMARY KEY (book_id, spine_index)
+);
+
+CREATE INDEX IF NOT EXISTS books_word_count ON books (word_count);
@@ -0,0 +1,12 @@
+-- Words looked up in the dictionaries while reading, kept for reviewing vocabulary
+CREATE TABLE IF NOT EXISTS dictionary_lookups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    word TEXT NOT NULL,                         -- As it was selected, like "running"
+    headword TEXT NOT NULL,                     -- The entry it was found under, like "run"
+    book_id INTEGER REFERENCES books(id) ON DELETE SET NULL,
+    context TEXT,                               -- The sentence it was read in
+    looked_up_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP -- UTC
+);
+
+CREATE INDEX IF NOT EXISTS dictionary_lookups_headword ON dictionary_lookups (headword);
+CREATE INDEX IF NOT EXISTS dictionary_lookups_looked_up_at ON dictionary_lookups (looked_up_at);
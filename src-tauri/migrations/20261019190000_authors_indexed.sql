@@ -0,0 +1,4 @@
+-- Books whose creators were read into book_authors, books crediting nobody are marked too so they aren't read again
+ALTER TABLE books ADD COLUMN authors_indexed BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE books SET authors_indexed = TRUE WHERE id IN (SELECT book_id FROM book_authors);
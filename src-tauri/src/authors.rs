@@ -0,0 +1,693 @@
+use std::{collections::HashMap, fs::File, io::BufReader, sync::Mutex};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use sqlx::{FromRow, Sqlite, Transaction};
+use tauri::State;
+use xmltree::Element;
+use zip::ZipArchive;
+
+use crate::{
+    book::metadata::{find_opf_path, read_zip_entry},
+    book_item::Book,
+    book_worker::BookWorker,
+    database::get_db,
+    error::ShelfError,
+};
+
+// Relator codes that don't credit a person, calibre tags itself as the book producer
+static IGNORED_ROLES: [&str; 1] = ["bkp"];
+static NAME_SUFFIXES: [&str; 7] = ["jr", "sr", "ii", "iii", "iv", "phd", "md"];
+// Kept with the surname when sorting, "Le Guin, Ursula K."
+static SURNAME_PARTICLES: [&str; 11] = [
+    "le", "la", "de", "da", "di", "du", "del", "der", "van", "von", "st",
+];
+
+/// The three forms of a name, the key is what spellings are matched on
+#[derive(Debug, PartialEq)]
+pub struct AuthorName {
+    pub display: String,
+    pub sort: String,
+    pub key: String,
+}
+
+/// An author with the number of books credited to them
+#[derive(Serialize, FromRow, Debug)]
+pub struct AuthorSummary {
+    id: i64,
+    name: String,
+    sort_name: String,
+    book_count: i64,
+}
+
+fn is_suffix(word: &str) -> bool {
+    let word = word.trim().trim_end_matches('.').to_lowercase();
+    NAME_SUFFIXES.contains(&word.as_str())
+}
+
+/// Splits a name into words, initials are broken up so "J.R.R." and "JRR" both become "J." "R." "R."
+fn name_words(name: &str) -> Vec<String> {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let mut name_words = Vec::new();
+
+    for (position, word) in words.iter().enumerate() {
+        let is_last = position + 1 == words.len();
+        let letters: Vec<&str> = word.split('.').filter(|part| !part.is_empty()).collect();
+
+        let is_dotted_initials = word.contains('.')
+            && !is_suffix(word)
+            && !letters.is_empty()
+            && letters.iter().all(|part| part.chars().count() == 1);
+        let is_packed_initials = !is_last
+            && words.len() > 1
+            && (2..=3).contains(&word.chars().count())
+            && word.chars().all(|c| c.is_uppercase());
+
+        if is_dotted_initials {
+            name_words.extend(
+                letters
+                    .iter()
+                    .map(|letter| format!("{}.", letter.to_uppercase())),
+            );
+        } else if is_packed_initials {
+            name_words.extend(word.chars().map(|letter| format!("{}.", letter)));
+        } else {
+            name_words.push(word.to_string());
+        }
+    }
+
+    name_words
+}
+
+/// Normalizes how a name is written, "Tolkien, J.R.R.", "J. R. R. Tolkien" and "JRR Tolkien" all give the same key
+///
+/// # Arguments
+///
+/// * `raw_name` - The name as it appears in the metadata
+///
+pub fn normalize_author(raw_name: &str) -> Option<AuthorName> {
+    let raw_name = raw_name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // "Last, First" is flipped, but not "King, Jr."
+    let ordered_name = match raw_name.split_once(',') {
+        Some((last, first)) if !first.trim().is_empty() && !is_suffix(first) => {
+            format!("{} {}", first.trim(), last.trim())
+        }
+        _ => raw_name.replace(',', ""),
+    };
+
+    let words = name_words(&ordered_name);
+    if words.is_empty() {
+        return None;
+    }
+
+    let key = words
+        .iter()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if key.is_empty() {
+        return None;
+    }
+
+    let surname_end = words
+        .iter()
+        .rposition(|word| !is_suffix(word))
+        .unwrap_or(words.len() - 1);
+    let mut surname_start = surname_end;
+    while surname_start > 1
+        && SURNAME_PARTICLES.contains(
+            &words[surname_start - 1]
+                .trim_end_matches('.')
+                .to_lowercase()
+                .as_str(),
+        )
+    {
+        surname_start -= 1;
+    }
+
+    let surname = words[surname_start..=surname_end].join(" ");
+    let given_names: Vec<&str> = words[..surname_start]
+        .iter()
+        .chain(&words[surname_end + 1..])
+        .map(String::as_str)
+        .collect();
+
+    let sort = if given_names.is_empty() {
+        surname
+    } else {
+        format!("{}, {}", surname, given_names.join(" "))
+    };
+
+    Some(AuthorName {
+        display: words.join(" "),
+        sort,
+        key,
+    })
+}
+
+/// Reads the creators and contributors of an epub along with their roles
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to read
+///
+pub fn read_creators(book_location: &str) -> Vec<(String, String)> {
+    let Ok(file) = File::open(book_location) else {
+        return Vec::new();
+    };
+    let Ok(mut archive) = ZipArchive::new(BufReader::new(file)) else {
+        return Vec::new();
+    };
+    let Some(package) = find_opf_path(&mut archive)
+        .and_then(|opf_path| read_zip_entry(&mut archive, &opf_path))
+        .ok()
+        .and_then(|opf| Element::parse(opf.as_slice()).ok())
+    else {
+        return Vec::new();
+    };
+    let Some(metadata) = package.get_child("metadata") else {
+        return Vec::new();
+    };
+
+    let elements: Vec<&Element> = metadata
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .collect();
+
+    // Epub3 moved the role into <meta refines="#id" property="role">
+    let refined_roles: HashMap<&str, String> = elements
+        .iter()
+        .filter(|meta| {
+            meta.name == "meta"
+                && meta.attributes.get("property").map(String::as_str) == Some("role")
+        })
+        .filter_map(|meta| {
+            Some((
+                meta.attributes.get("refines")?.trim_start_matches('#'),
+                meta.get_text()?.trim().to_string(),
+            ))
+        })
+        .collect();
+
+    elements
+        .iter()
+        .filter(|element| element.name == "creator" || element.name == "contributor")
+        .filter_map(|element| {
+            let name = element.get_text()?.trim().to_string();
+            let role = element
+                .attributes
+                .get("role")
+                .cloned()
+                .or_else(|| {
+                    element
+                        .attributes
+                        .get("id")
+                        .and_then(|id| refined_roles.get(id.as_str()).cloned())
+                })
+                .unwrap_or_else(|| match element.name.as_str() {
+                    "creator" => "aut".to_string(),
+                    _ => "ctb".to_string(),
+                })
+                .to_lowercase();
+
+            (!name.is_empty() && !IGNORED_ROLES.contains(&role.as_str())).then_some((name, role))
+        })
+        .collect()
+}
+
+/// The people credited on a book. The authors field wins over the epub, it may have been edited or imported
+fn book_creators(book: &Book) -> Vec<(String, String)> {
+    let mut creators = read_creators(book.get_book_location());
+
+    if let Some(authors) = book.get_authors() {
+        creators.retain(|(_, role)| role != "aut");
+
+        // Illustrators and the like are creators too, they keep the role the epub gives them
+        let authors: Vec<(String, String)> = authors
+            .split(" & ")
+            .map(str::trim)
+            .filter(|author| !author.is_empty())
+            .filter(|author| !creators.iter().any(|(name, _)| name == author))
+            .map(|author| (author.to_string(), "aut".to_string()))
+            .collect();
+        creators.extend(authors);
+    }
+
+    creators
+}
+
+/// Finds the author a name belongs to, going through the aliases first
+async fn find_author(
+    transaction: &mut Transaction<'_, Sqlite>,
+    name: &AuthorName,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "SELECT author_id FROM author_aliases WHERE alias_key = $1
+         UNION ALL SELECT id FROM authors WHERE name_key = $1 LIMIT 1",
+    )
+    .bind(&name.key)
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+/// Finds the author a name belongs to, unknown names get a new author
+async fn resolve_author(
+    transaction: &mut Transaction<'_, Sqlite>,
+    name: &AuthorName,
+) -> Result<i64, sqlx::Error> {
+    match find_author(transaction, name).await? {
+        Some(author_id) => Ok(author_id),
+        None => Ok(sqlx::query(
+            "INSERT INTO authors (name, sort_name, name_key) VALUES ($1, $2, $3)",
+        )
+        .bind(&name.display)
+        .bind(&name.sort)
+        .bind(&name.key)
+        .execute(&mut **transaction)
+        .await?
+        .last_insert_rowid()),
+    }
+}
+
+/// Replaces the author links of a book
+async fn link_book_authors(
+    transaction: &mut Transaction<'_, Sqlite>,
+    book_id: i64,
+    creators: &[(String, String)],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM book_authors WHERE book_id = $1")
+        .bind(book_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    for (creator, role) in creators {
+        let Some(name) = normalize_author(creator) else {
+            continue;
+        };
+        let author_id = resolve_author(transaction, &name).await?;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO book_authors (book_id, author_id, role) VALUES ($1, $2, $3)",
+        )
+        .bind(book_id)
+        .bind(author_id)
+        .bind(role)
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Drops authors that no longer have any books or aliases pointing at them
+async fn remove_orphaned_authors(
+    transaction: &mut Transaction<'_, Sqlite>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM authors WHERE id NOT IN (SELECT author_id FROM book_authors)
+         AND id NOT IN (SELECT author_id FROM author_aliases)",
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Links books to their authors, any existing links of these books are replaced.
+/// The books are marked as indexed, including the ones crediting nobody
+///
+/// # Arguments
+///
+/// * `books` - The books to index, they have to be in the books table already
+///
+pub async fn index_book_authors(books: &[Book]) -> Result<(), sqlx::Error> {
+    let books = books.to_vec();
+    let creators: Vec<(String, Vec<(String, String)>)> =
+        tauri::async_runtime::spawn_blocking(move || {
+            books
+                .par_iter()
+                .map(|book| (book.get_book_location().clone(), book_creators(book)))
+                .collect()
+        })
+        .await
+        .map_err(|err| sqlx::Error::Protocol(err.to_string()))?;
+
+    let mut transaction = get_db().begin().await?;
+
+    for (book_location, creators) in &creators {
+        let book_id = sqlx::query_scalar::<_, i64>(
+            "UPDATE books SET authors_indexed = TRUE WHERE book_location = $1 RETURNING id",
+        )
+        .bind(book_location)
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        if let Some(book_id) = book_id {
+            link_book_authors(&mut transaction, book_id, creators).await?;
+        }
+    }
+
+    remove_orphaned_authors(&mut transaction).await?;
+    transaction.commit().await
+}
+
+/// Indexes the books that weren't indexed yet, covers libraries from before authors were tracked
+pub async fn index_unindexed_books() -> Result<(), sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE NOT authors_indexed")
+        .fetch_all(get_db())
+        .await?;
+
+    if books.is_empty() {
+        return Ok(());
+    }
+
+    index_book_authors(&books).await
+}
+
+/// Lists every author with how many books they're credited on, sorted by surname
+#[tauri::command]
+pub async fn get_authors() -> Result<Vec<AuthorSummary>, ShelfError> {
+    sqlx::query_as::<_, AuthorSummary>(
+        "SELECT a.id, a.name, a.sort_name, COUNT(DISTINCT ba.book_id) AS book_count
+         FROM authors a JOIN book_authors ba ON ba.author_id = a.id
+         GROUP BY a.id ORDER BY a.sort_name COLLATE NOCASE",
+    )
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load authors", err))
+}
+
+/// Returns the books credited to an author, series are kept together in reading order
+///
+/// # Arguments
+///
+/// * `author_id` - The id of the author
+/// * `role` - Only include books where the author has this role, "aut" for books they wrote
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_books_by_author(
+    author_id: i64,
+    role: Option<String>,
+) -> Result<Vec<Book>, ShelfError> {
+    sqlx::query_as::<_, Book>(
+        "SELECT DISTINCT b.* FROM books b JOIN book_authors ba ON ba.book_id = b.id
+         WHERE ba.author_id = $1 AND ($2 IS NULL OR ba.role = $2)
+         ORDER BY b.series IS NULL, b.series COLLATE NOCASE, b.series_index, b.title COLLATE NOCASE",
+    )
+    .bind(author_id)
+    .bind(role)
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database(format!("load books by author {}", author_id), err))
+}
+
+/// Writes an author's name into the authors text of their books. The OPDS catalog, search, the library
+/// filters and organizing read the text rather than the author links, so it has to follow renames and merges
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction the author was changed in
+/// * `author_id` - The author whose spellings are replaced
+///
+async fn rewrite_book_authors(
+    transaction: &mut Transaction<'_, Sqlite>,
+    author_id: i64,
+) -> Result<Vec<Book>, sqlx::Error> {
+    let author_name = sqlx::query_scalar::<_, String>("SELECT name FROM authors WHERE id = $1")
+        .bind(author_id)
+        .fetch_one(&mut **transaction)
+        .await?;
+    let books = sqlx::query_as::<_, Book>(
+        "SELECT * FROM books WHERE authors IS NOT NULL
+         AND id IN (SELECT book_id FROM book_authors WHERE author_id = $1)",
+    )
+    .bind(author_id)
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    let mut rewritten_books = Vec::new();
+    for book in books {
+        let authors = book.get_authors().unwrap_or_default().to_string();
+        let mut names: Vec<String> = Vec::new();
+
+        for name in authors
+            .split(" & ")
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let is_author = match normalize_author(name) {
+                Some(name) => find_author(transaction, &name).await? == Some(author_id),
+                None => false,
+            };
+            let name = if is_author { &author_name } else { name };
+
+            // A merge can leave both spellings on one book
+            if !names.iter().any(|existing| existing == name) {
+                names.push(name.to_string());
+            }
+        }
+
+        let rewritten = names.join(" & ");
+        if rewritten != authors {
+            sqlx::query("UPDATE books SET authors = $1 WHERE id = $2")
+                .bind(&rewritten)
+                .bind(book.get_id())
+                .execute(&mut **transaction)
+                .await?;
+            rewritten_books.push(book.with_authors(Some(rewritten)));
+        }
+    }
+
+    Ok(rewritten_books)
+}
+
+/// Moves the books and spellings of one author over to another and removes the first
+async fn move_author(
+    transaction: &mut Transaction<'_, Sqlite>,
+    source_id: i64,
+    target_id: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO book_authors (book_id, author_id, role)
+         SELECT book_id, $2, role FROM book_authors WHERE author_id = $1",
+    )
+    .bind(source_id)
+    .bind(target_id)
+    .execute(&mut **transaction)
+    .await?;
+
+    sqlx::query("UPDATE author_aliases SET author_id = $2 WHERE author_id = $1")
+        .bind(source_id)
+        .bind(target_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    // Future books using the old spelling land on the kept author
+    sqlx::query(
+        "INSERT OR REPLACE INTO author_aliases (alias_key, author_id)
+         SELECT name_key, $2 FROM authors WHERE id = $1",
+    )
+    .bind(source_id)
+    .bind(target_id)
+    .execute(&mut **transaction)
+    .await?;
+
+    sqlx::query("DELETE FROM authors WHERE id = $1")
+        .bind(source_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+async fn merge_authors_db(source_id: i64, target_id: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    move_author(&mut transaction, source_id, target_id).await?;
+    let rewritten_books = rewrite_book_authors(&mut transaction, target_id).await?;
+
+    transaction.commit().await?;
+    Ok(rewritten_books)
+}
+
+/// Merges one author into another, their books move over and the old name becomes an alias
+///
+/// # Arguments
+///
+/// * `source_id` - The duplicate author, removed afterwards
+/// * `target_id` - The author to keep
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn merge_authors(
+    source_id: i64,
+    target_id: i64,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<(), ShelfError> {
+    if source_id == target_id {
+        return Ok(());
+    }
+
+    let rewritten_books = merge_authors_db(source_id, target_id)
+        .await
+        .map_err(|err| ShelfError::database("merge authors", err))?;
+    replace_cached_books(&state, rewritten_books);
+
+    Ok(())
+}
+
+fn replace_cached_books(state: &State<'_, Mutex<BookWorker>>, books: Vec<Book>) {
+    let mut book_worker = state.lock().unwrap();
+    for book in books {
+        book_worker.replace_book(book);
+    }
+}
+
+async fn add_alias_db(name: &AuthorName, author_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query("INSERT OR REPLACE INTO author_aliases (alias_key, author_id) VALUES ($1, $2)")
+        .bind(&name.key)
+        .bind(author_id)
+        .execute(get_db())
+        .await?;
+
+    sqlx::query_scalar::<_, i64>("SELECT id FROM authors WHERE name_key = $1")
+        .bind(&name.key)
+        .fetch_optional(get_db())
+        .await
+}
+
+/// Makes another spelling of a name resolve to an author, an existing author with that spelling is merged in
+///
+/// # Arguments
+///
+/// * `alias` - The alternate spelling
+/// * `author_id` - The author it belongs to
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn add_author_alias(
+    alias: String,
+    author_id: i64,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<(), ShelfError> {
+    let name = normalize_author(&alias)
+        .ok_or_else(|| ShelfError::invalid(format!("{:?} is not a valid name", alias)))?;
+
+    let existing_author = add_alias_db(&name, author_id)
+        .await
+        .map_err(|err| ShelfError::database(format!("add the alias {}", alias), err))?;
+
+    match existing_author {
+        Some(source_id) => merge_authors(source_id, author_id, state).await,
+        None => Ok(()),
+    }
+}
+
+/// Stops a spelling from resolving to an author, books already linked stay linked
+///
+/// # Arguments
+///
+/// * `alias` - The alternate spelling to remove
+///
+#[tauri::command]
+pub async fn remove_author_alias(alias: String) -> Result<(), ShelfError> {
+    let name = normalize_author(&alias)
+        .ok_or_else(|| ShelfError::invalid(format!("{:?} is not a valid name", alias)))?;
+
+    sqlx::query("DELETE FROM author_aliases WHERE alias_key = $1")
+        .bind(&name.key)
+        .execute(get_db())
+        .await
+        .map(|_| ())
+        .map_err(|err| ShelfError::database(format!("remove the alias {}", alias), err))
+}
+
+/// Lists the alternate spellings that resolve to an author
+///
+/// # Arguments
+///
+/// * `author_id` - The id of the author
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_author_aliases(author_id: i64) -> Result<Vec<String>, ShelfError> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT alias_key FROM author_aliases WHERE author_id = $1 ORDER BY alias_key",
+    )
+    .bind(author_id)
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load aliases", err))
+}
+
+async fn rename_author_db(author_id: i64, new_name: &AuthorName) -> Result<Vec<Book>, sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    let existing_author =
+        sqlx::query_scalar::<_, i64>("SELECT id FROM authors WHERE name_key = $1 AND id != $2")
+            .bind(&new_name.key)
+            .bind(author_id)
+            .fetch_optional(&mut *transaction)
+            .await?;
+
+    // Renaming to a name another author already has makes them one author
+    let author_id = match existing_author {
+        Some(existing_id) => {
+            move_author(&mut transaction, author_id, existing_id).await?;
+            existing_id
+        }
+        None => {
+            // The previous spelling keeps working as an alias
+            sqlx::query(
+                "INSERT OR IGNORE INTO author_aliases (alias_key, author_id)
+                 SELECT name_key, id FROM authors WHERE id = $1",
+            )
+            .bind(author_id)
+            .execute(&mut *transaction)
+            .await?;
+            author_id
+        }
+    };
+
+    sqlx::query("UPDATE authors SET name = $1, sort_name = $2, name_key = $3 WHERE id = $4")
+        .bind(&new_name.display)
+        .bind(&new_name.sort)
+        .bind(&new_name.key)
+        .bind(author_id)
+        .execute(&mut *transaction)
+        .await?;
+    let rewritten_books = rewrite_book_authors(&mut transaction, author_id).await?;
+
+    transaction.commit().await?;
+    Ok(rewritten_books)
+}
+
+/// Renames an author, the sort name is worked out again from the new name. When another author already
+/// has the new name the two are merged
+///
+/// # Arguments
+///
+/// * `author_id` - The id of the author
+/// * `name` - The new display name
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn rename_author(
+    author_id: i64,
+    name: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<(), ShelfError> {
+    let new_name = normalize_author(&name)
+        .ok_or_else(|| ShelfError::invalid(format!("{:?} is not a valid name", name)))?;
+
+    let rewritten_books = rename_author_db(author_id, &new_name)
+        .await
+        .map_err(|err| ShelfError::database("rename the author", err))?;
+    replace_cached_books(&state, rewritten_books);
+
+    Ok(())
+}
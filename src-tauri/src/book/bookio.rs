@@ -3,16 +3,22 @@ use epub::doc::EpubDoc;
 use std::{
     fs::File,
     io::{BufReader, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Mutex,
 };
-use tauri::State;
+use tauri::{State, Window};
+use tracing::{debug, warn};
 
 use crate::{
+    book::{length::count_chapters, scan::start_scan, series::detect_series},
     book_item::{unique_find_cover, Book},
     book_worker::BookWorker,
+    error::ShelfError,
+};
+use rayon::{
+    iter::Either,
+    prelude::{IntoParallelRefIterator, ParallelIterator},
 };
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
 /// Writes the cover image to the specified path
 ///
@@ -21,14 +27,14 @@ use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 /// * `data` - A vector containing the image data
 /// * `path` - A string representing the path to write to
 ///
-pub fn write_cover_image(data: (Vec<u8>, String), path: &PathBuf) -> Result<&PathBuf, ()> {
+pub fn write_cover_image(data: (Vec<u8>, String), path: &PathBuf) -> Result<&PathBuf, BookError> {
     let (bytes, _) = data;
 
     match File::create(path) {
-        Err(..) => return Err(()),
+        Err(..) => return Err(BookError::IOError),
         Ok(mut file) => {
             if file.write_all(&bytes).is_err() {
-                return Err(());
+                return Err(BookError::IOError);
             }
         }
     }
@@ -36,53 +42,98 @@ pub fn write_cover_image(data: (Vec<u8>, String), path: &PathBuf) -> Result<&Pat
     Ok(path)
 }
 
+/// Reads a single epub into a book, writing its cover to the cover cache
+///
+/// # Arguments
+///
+/// * `item` - The location of the epub
+///
+pub fn read_book(item: &str) -> Result<Book, ShelfError> {
+    let item_normalized = item.replace('\\', "/");
+
+    let mut ebook = EpubDoc::new(&item_normalized)
+        .map_err(|err| ShelfError::parse(&item_normalized, err.to_string()))?;
+    let book_title = ebook
+        .mdata("title")
+        .ok_or_else(|| ShelfError::parse(&item_normalized, "the epub has no title"))?;
+    let (series, series_index) = detect_series(&mut ebook, &item_normalized)
+        .map_or((None, None), |(series, index)| (Some(series), index));
+
+    let authors = ebook
+        .metadata
+        .get("creator")
+        .map(|creators| creators.join(" & "))
+        .filter(|authors| !authors.trim().is_empty());
+
+    // A book that can't be counted is still added, the next scan tries counting it again
+    let chapter_lengths = count_chapters(&item_normalized)
+        .map_err(|err| {
+            warn!(
+                book_location = item_normalized,
+                "Failed to count words: {}", err
+            )
+        })
+        .ok();
+
+    Ok(Book::new(None, item_normalized, book_title)
+        .with_authors(authors)
+        .with_series(series, series_index)
+        .with_chapter_lengths(chapter_lengths))
+}
+
 /// Creates a vector containing all the books and returns a a vector of book objects, here we also create the covers
-/// The returned json is sorted alphabetically so we can use binary sort when there are a large number of books
+/// The books are returned in no particular order, the book cache keeps the shelf in title order
+/// Files that couldn't be read come back with why, so they can be recorded as import errors
 ///
 /// # Arguments
 ///
 /// * `items` - A vector containing the book directories
-/// * `write_directory` - A string representing the path to write to
 ///
-pub fn create_book_vec(items: &Vec<String>) -> Vec<Book> {
-    println!("{:?} items handed to create new", items.len());
-    let books: Vec<Book> = items
+pub fn create_book_vec(items: &Vec<String>) -> (Vec<Book>, Vec<(String, ShelfError)>) {
+    debug!(count = items.len(), "Reading new books");
+    items
         .par_iter()
-        .filter_map(|item| {
-            let item_normalized = item.replace('\\', "/");
+        .partition_map(|item| match read_book(item) {
+            Ok(book) => Either::Left(book),
+            Err(err) => {
+                warn!("Book creation failed with: {}", err);
 
-            match EpubDoc::new(&item_normalized) {
-                Ok(ebook) => {
-                    let book_title = ebook.mdata("title")?;
-
-                    let new_book = Book::new(None, item_normalized, book_title);
-
-                    Some(new_book)
-                }
-                Err(e) => {
-                    println!("Book creation failed with: {}", e);
-
-                    None
-                }
+                Either::Right((item.replace('\\', "/"), err))
             }
         })
-        .collect();
-
-    let mut sorted_books = books;
-
-    // TODO this might cause issues, only some data is sorted on the front end
-    sorted_books.sort_by(|a, b| a.get_title().cmp(&b.get_title()));
-
-    sorted_books
 }
 
-/// Initializes the books and loading them from the users provided directory, if the book_cache file is missing the all epubs will be read
-/// Otherwise only books missing from the Static vector will be initialized
+/// Returns the books already on the shelf and starts a background scan of the users provided directory for new ones
+/// The scan sends scan-progress and scan-finished events, get_library_books fetches the shelf again once it's done
 #[tauri::command]
-pub fn initialize_books(state: State<'_, Mutex<BookWorker>>) -> Option<Vec<Book>> {
-    let mut book_worker = state.lock().unwrap();
+pub fn initialize_books(
+    window: Window,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Vec<Book>, ShelfError> {
+    let books = {
+        let book_worker = state.lock().unwrap();
+        let dir_exists = book_worker
+            .get_application_settings()
+            .get("book_location")
+            .is_some_and(|dir| Path::new(dir).is_dir());
+
+        if !dir_exists {
+            return Err(ShelfError::settings(
+                "library folder",
+                "isn't set or doesn't exist",
+            ));
+        }
+
+        book_worker
+            .get_book_cache()
+            .get_books()
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    start_scan(window);
 
-    book_worker.initialize_books()
+    Ok(books)
 }
 
 #[derive(Debug)]
@@ -0,0 +1,592 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader},
+    path::Path,
+    sync::Mutex,
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use tauri::State;
+use tracing::warn;
+use xmltree::Element;
+use zip::ZipArchive;
+
+use crate::{
+    authors::normalize_author,
+    book::{
+        metadata::{find_opf_path, read_zip_entry},
+        search::{edit_distance, normalize_text},
+        trash::{cover_shared, retention_days, trash_book, TrashedFiles},
+    },
+    book_item::{get_all_books, Book},
+    book_worker::BookWorker,
+    database::get_db,
+    error::ShelfError,
+};
+
+// Titles this close are treated as the same, one letter in ten may differ and shorter titles get one typo
+static TITLE_DIFFERENCE_RATIO: usize = 10;
+static MIN_TITLE_LENGTH_FOR_TYPOS: usize = 5;
+
+/// Why books were grouped together, from the most to the least certain
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    ContentHash,
+    Identifier,
+    TitleAuthor,
+}
+
+/// A book in a duplicate group along with what's needed to pick which copy to keep
+#[derive(Serialize, Debug)]
+pub struct DuplicateCandidate {
+    book: Book,
+    /// Bytes, none when the file is missing
+    file_size: Option<i64>,
+    /// File extension, "epub"
+    format: Option<String>,
+}
+
+/// Books that look like copies of each other
+#[derive(Serialize, Debug)]
+pub struct DuplicateGroup {
+    reason: DuplicateReason,
+    /// The hash, identifier or normalized title the books share
+    key: String,
+    books: Vec<DuplicateCandidate>,
+}
+
+#[derive(FromRow, Debug)]
+struct FileRow {
+    id: i64,
+    book_location: String,
+    content_hash: Option<String>,
+    file_size: Option<i64>,
+}
+
+/// A freshly hashed file, along with the identifiers found in it
+struct HashedFile {
+    id: i64,
+    content_hash: String,
+    file_size: i64,
+    identifiers: Vec<(String, String)>,
+}
+
+/// Hashes a whole file with sha256
+pub(crate) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Brings an isbn down to its 13 digit form, so the 10 and 13 digit versions of a book match
+fn normalize_isbn(raw: &str) -> Option<String> {
+    let digits: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match digits.len() {
+        13 if digits.starts_with("978") || digits.starts_with("979") => Some(digits),
+        10 => {
+            let body = format!("978{}", &digits[..9]);
+            let sum: u32 = body
+                .chars()
+                .enumerate()
+                .map(|(position, digit)| {
+                    let digit = digit.to_digit(10).unwrap_or(0);
+                    if position % 2 == 0 {
+                        digit
+                    } else {
+                        digit * 3
+                    }
+                })
+                .sum();
+
+            Some(format!("{}{}", body, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the isbn and uuid an epub declares in its dc:identifier elements
+fn read_epub_identifiers(book_location: &str) -> Vec<(String, String)> {
+    let Ok(file) = File::open(book_location) else {
+        return Vec::new();
+    };
+    let Ok(mut archive) = ZipArchive::new(BufReader::new(file)) else {
+        return Vec::new();
+    };
+    let Some(metadata) = find_opf_path(&mut archive)
+        .and_then(|opf_path| read_zip_entry(&mut archive, &opf_path))
+        .ok()
+        .and_then(|opf| Element::parse(opf.as_slice()).ok())
+        .and_then(|package| package.get_child("metadata").cloned())
+    else {
+        return Vec::new();
+    };
+
+    let mut identifiers = Vec::new();
+    for identifier in metadata
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .filter(|element| element.name == "identifier")
+    {
+        let Some(value) = identifier.get_text() else {
+            continue;
+        };
+        let value = value.trim();
+        let lowercase = value.to_lowercase();
+        let scheme = identifier
+            .attributes
+            .get("scheme")
+            .map(|scheme| scheme.to_lowercase());
+
+        if scheme.as_deref() == Some("isbn") || lowercase.starts_with("urn:isbn:") {
+            if let Some(isbn) = normalize_isbn(value) {
+                identifiers.push(("isbn".to_string(), isbn));
+            }
+        } else if let Some(uuid) = lowercase
+            .strip_prefix("urn:uuid:")
+            .or_else(|| (scheme.as_deref() == Some("uuid")).then_some(lowercase.as_str()))
+        {
+            identifiers.push(("uuid".to_string(), uuid.to_string()));
+        }
+    }
+
+    identifiers
+}
+
+/// Hashes every book that hasn't been hashed yet or whose file changed size since, and stores the result
+/// Epub identifiers are read while the file is being looked at anyway
+pub(crate) async fn refresh_content_hashes(
+) -> Result<HashMap<i64, (Option<String>, Option<i64>)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, FileRow>(
+        "SELECT id, book_location, content_hash, file_size FROM books",
+    )
+    .fetch_all(get_db())
+    .await?;
+
+    // Hashing reads every changed file, it runs on the rayon pool instead of the async runtime
+    let (rows, hashed) = tauri::async_runtime::spawn_blocking(move || {
+        let hashed: Vec<HashedFile> = rows
+            .par_iter()
+            .filter_map(|row| {
+                let file_size = fs::metadata(&row.book_location).ok()?.len() as i64;
+                if row.content_hash.is_some() && row.file_size == Some(file_size) {
+                    return None;
+                }
+
+                let identifiers = if row.book_location.to_lowercase().ends_with(".epub") {
+                    read_epub_identifiers(&row.book_location)
+                } else {
+                    Vec::new()
+                };
+
+                Some(HashedFile {
+                    id: row.id,
+                    content_hash: hash_file(Path::new(&row.book_location)).ok()?,
+                    file_size,
+                    identifiers,
+                })
+            })
+            .collect();
+
+        (rows, hashed)
+    })
+    .await
+    .map_err(|err| sqlx::Error::Io(io::Error::new(io::ErrorKind::Other, err.to_string())))?;
+
+    let mut transaction = get_db().begin().await?;
+    for file in &hashed {
+        sqlx::query("UPDATE books SET content_hash = $1, file_size = $2 WHERE id = $3")
+            .bind(&file.content_hash)
+            .bind(file.file_size)
+            .bind(file.id)
+            .execute(&mut *transaction)
+            .await?;
+
+        // Identifiers that came from calibre win over the ones in the file
+        for (kind, value) in &file.identifiers {
+            sqlx::query(
+                "INSERT OR IGNORE INTO book_identifiers (book_id, kind, value) VALUES ($1, $2, $3)",
+            )
+            .bind(file.id)
+            .bind(kind)
+            .bind(value)
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+
+    let mut files: HashMap<i64, (Option<String>, Option<i64>)> = rows
+        .into_iter()
+        .map(|row| (row.id, (row.content_hash, row.file_size)))
+        .collect();
+    for file in hashed {
+        files.insert(file.id, (Some(file.content_hash), Some(file.file_size)));
+    }
+
+    Ok(files)
+}
+
+/// The title a book is compared on, "The Hobbit: Or There and Back Again" becomes "hobbit"
+fn title_key(title: &str) -> String {
+    let title = normalize_text(title.split(':').next().unwrap_or(title));
+
+    ["the ", "an ", "a "]
+        .iter()
+        .find_map(|article| title.strip_prefix(article))
+        .map(str::to_string)
+        .unwrap_or(title)
+}
+
+/// The first credited author, in the form spellings are matched on
+fn author_key(book: &Book) -> String {
+    book.get_authors()
+        .and_then(|authors| authors.split(" & ").next())
+        .and_then(normalize_author)
+        .map(|author| author.key)
+        .unwrap_or_default()
+}
+
+fn titles_match(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest < MIN_TITLE_LENGTH_FOR_TYPOS {
+        return false;
+    }
+
+    edit_distance(&a, &b) <= (longest / TITLE_DIFFERENCE_RATIO).max(1)
+}
+
+/// Groups books by the same first author whose titles are close enough to be the same book
+fn group_by_title_author(books: &[Book]) -> Vec<(String, Vec<i64>)> {
+    let mut by_author: HashMap<String, Vec<(i64, String)>> = HashMap::new();
+    for book in books {
+        let Some(id) = book.get_id() else {
+            continue;
+        };
+        let title = title_key(book.get_title());
+        if !title.is_empty() {
+            by_author
+                .entry(author_key(book))
+                .or_default()
+                .push((id, title));
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (author, mut titles) in by_author {
+        titles.sort_by(|a, b| a.1.cmp(&b.1));
+
+        // Each book joins the first group holding a title it matches
+        let mut author_groups: Vec<(String, Vec<i64>)> = Vec::new();
+        for (id, title) in titles {
+            match author_groups
+                .iter_mut()
+                .find(|(group_title, _)| titles_match(group_title, &title))
+            {
+                Some((_, ids)) => ids.push(id),
+                None => author_groups.push((title, vec![id])),
+            }
+        }
+
+        groups.extend(
+            author_groups
+                .into_iter()
+                .filter(|(_, ids)| ids.len() > 1)
+                .map(|(title, ids)| {
+                    let key = if author.is_empty() {
+                        title
+                    } else {
+                        format!("{} / {}", title, author)
+                    };
+                    (key, ids)
+                }),
+        );
+    }
+
+    groups.sort();
+    groups
+}
+
+/// Looks through the library for copies of the same book. Books are grouped by identical files, by a shared
+/// isbn or other identifier, and by close titles from the same author. A group is only reported once,
+/// under the most certain reason that found it
+#[tauri::command]
+pub async fn find_duplicate_books() -> Result<Vec<DuplicateGroup>, ShelfError> {
+    let books = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load books", err))?;
+
+    let (files, identifier_groups) = async {
+        let files = refresh_content_hashes().await?;
+        let identifier_groups = sqlx::query_as::<_, (String, String, String)>(
+            "SELECT kind, value, group_concat(book_id) FROM book_identifiers
+                 WHERE value != '' GROUP BY kind, value HAVING COUNT(*) > 1 ORDER BY kind, value",
+        )
+        .fetch_all(get_db())
+        .await?;
+
+        Ok((files, identifier_groups))
+    }
+    .await
+    .map_err(|err| ShelfError::database("look for duplicates", err))?;
+
+    let mut hash_groups: HashMap<&str, Vec<i64>> = HashMap::new();
+    for (id, (content_hash, _)) in &files {
+        if let Some(content_hash) = content_hash {
+            hash_groups.entry(content_hash).or_default().push(*id);
+        }
+    }
+    let mut hash_groups: Vec<(String, Vec<i64>)> = hash_groups
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(content_hash, ids)| (content_hash.to_string(), ids))
+        .collect();
+    hash_groups.sort();
+
+    let identifier_groups = identifier_groups
+        .into_iter()
+        .map(|(kind, value, ids)| {
+            let ids = ids.split(',').filter_map(|id| id.parse().ok()).collect();
+            (format!("{}:{}", kind, value), ids)
+        })
+        .collect::<Vec<(String, Vec<i64>)>>();
+
+    let books_by_id: HashMap<i64, &Book> = books
+        .iter()
+        .filter_map(|book| Some((book.get_id()?, book)))
+        .collect();
+    let mut reported: Vec<HashSet<i64>> = Vec::new();
+    let mut groups = Vec::new();
+
+    for (reason, candidates) in [
+        (DuplicateReason::ContentHash, hash_groups),
+        (DuplicateReason::Identifier, identifier_groups),
+        (DuplicateReason::TitleAuthor, group_by_title_author(&books)),
+    ] {
+        for (key, ids) in candidates {
+            let ids: HashSet<i64> = ids.into_iter().collect();
+            if reported.iter().any(|seen| ids.is_subset(seen)) {
+                continue;
+            }
+
+            let mut group_books: Vec<DuplicateCandidate> = ids
+                .iter()
+                .filter_map(|id| {
+                    let book = books_by_id.get(id)?;
+                    Some(DuplicateCandidate {
+                        book: (*book).clone(),
+                        file_size: files.get(id).and_then(|(_, file_size)| *file_size),
+                        format: Path::new(book.get_book_location())
+                            .extension()
+                            .map(|extension| extension.to_string_lossy().to_lowercase()),
+                    })
+                })
+                .collect();
+            group_books.sort_by(|a, b| a.book.get_book_location().cmp(b.book.get_book_location()));
+
+            reported.push(ids);
+            groups.push(DuplicateGroup {
+                reason,
+                key,
+                books: group_books,
+            });
+        }
+    }
+
+    Ok(groups)
+}
+
+/// The book duplicates were merged into, along with what went wrong once the merge had gone through
+#[derive(Serialize, Debug)]
+pub struct MergeResult {
+    book: Book,
+    /// Files that couldn't be moved to the trash, their duplicates are in the trash all the same
+    warnings: Vec<String>,
+}
+
+async fn find_book(book_location: &str) -> Result<Book, sqlx::Error> {
+    sqlx::query_as::<_, Book>("SELECT * FROM books WHERE book_location = $1")
+        .bind(book_location)
+        .fetch_optional(get_db())
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Folds duplicate books into the one being kept. The kept book takes the furthest progress and most advanced
+/// read status, the earliest start and finish dates, and any metadata it's missing. Tags, identifiers, authors
+/// and reading sessions are moved over, then the duplicates are moved to the trash so the merge can be undone
+/// one book at a time. Annotations are stored by the reader, the library has none to merge
+///
+/// # Arguments
+///
+/// * `keep_location` - The book to keep
+/// * `duplicate_locations` - The books to fold into it
+/// * `trash_files` - Moves the duplicates files to the trash folder, otherwise they stay where they are and the
+///   trash keeps them out of future scans
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn merge_duplicate_books(
+    keep_location: String,
+    duplicate_locations: Vec<String>,
+    trash_files: bool,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<MergeResult, ShelfError> {
+    let duplicate_locations: Vec<String> = duplicate_locations
+        .into_iter()
+        .filter(|location| *location != keep_location)
+        .collect();
+    if duplicate_locations.is_empty() {
+        return Err(ShelfError::invalid("There are no other books to merge"));
+    }
+
+    let retention_days = retention_days(&state);
+    let database_error = |err: sqlx::Error| match err {
+        sqlx::Error::RowNotFound => ShelfError::not_found("One of the books being merged"),
+        err => ShelfError::database("merge books", err),
+    };
+
+    let kept = find_book(&keep_location).await.map_err(database_error)?;
+    let keep_id = kept
+        .get_id()
+        .ok_or_else(|| database_error(sqlx::Error::RowNotFound))?;
+    let mut duplicates = Vec::new();
+    let mut shared_covers = Vec::new();
+    for location in &duplicate_locations {
+        let duplicate = find_book(location).await.map_err(database_error)?;
+        // The kept book or any other may be showing the same cover
+        shared_covers.push(
+            cover_shared(duplicate.get_cover_filename(), duplicate.get_id())
+                .await
+                .map_err(database_error)?,
+        );
+        duplicates.push(duplicate);
+    }
+
+    // The files are moved first and moved back if the merge doesn't commit
+    let mut warnings = Vec::new();
+    let mut trashed = Vec::new();
+    for (duplicate, cover_shared) in duplicates.iter().zip(shared_covers) {
+        let mut files = TrashedFiles::new(duplicate);
+        if trash_files {
+            if let Err(err) = files.trash_file() {
+                let err = ShelfError::io(
+                    "move the merged duplicate to the trash",
+                    duplicate.get_book_location(),
+                    err,
+                );
+                warn!("{}", err);
+                warnings.push(err.to_string());
+            }
+        }
+        if !cover_shared {
+            files.trash_cover();
+        }
+        trashed.push(files);
+    }
+
+    let merged = async {
+        let mut transaction = get_db().begin().await?;
+
+        for (duplicate, files) in duplicates.iter().zip(&trashed) {
+            let duplicate_id = duplicate.get_id().ok_or(sqlx::Error::RowNotFound)?;
+
+            // min/max give null if either side is null, coalescing both ways keeps whichever is set
+            sqlx::query(
+                "UPDATE books SET
+                 authors = coalesce(books.authors, d.authors),
+                 series = coalesce(books.series, d.series),
+                 series_index = coalesce(books.series_index, d.series_index),
+                 description = coalesce(books.description, d.description),
+                 rating = coalesce(books.rating, d.rating),
+                 review = coalesce(books.review, d.review),
+                 date_added = min(coalesce(books.date_added, d.date_added),
+                     coalesce(d.date_added, books.date_added)),
+                 date_started = min(coalesce(books.date_started, d.date_started),
+                     coalesce(d.date_started, books.date_started)),
+                 date_finished = min(coalesce(books.date_finished, d.date_finished),
+                     coalesce(d.date_finished, books.date_finished)),
+                 last_read = max(coalesce(books.last_read, d.last_read),
+                     coalesce(d.last_read, books.last_read)),
+                 progress = max(coalesce(books.progress, d.progress),
+                     coalesce(d.progress, books.progress)),
+                 read_status = CASE
+                     WHEN 'finished' IN (books.read_status, d.read_status) THEN 'finished'
+                     WHEN 'reading' IN (books.read_status, d.read_status) THEN 'reading'
+                     WHEN 'abandoned' IN (books.read_status, d.read_status) THEN 'abandoned'
+                     ELSE 'unread' END
+                 FROM (SELECT * FROM books WHERE id = $2) AS d
+                 WHERE books.id = $1",
+            )
+            .bind(keep_id)
+            .bind(duplicate_id)
+            .execute(&mut *transaction)
+            .await?;
+
+            for statement in [
+                "INSERT OR IGNORE INTO book_tags (book_id, tag)
+                 SELECT $1, tag FROM book_tags WHERE book_id = $2",
+                "INSERT OR IGNORE INTO book_identifiers (book_id, kind, value)
+                 SELECT $1, kind, value FROM book_identifiers WHERE book_id = $2",
+                "INSERT OR IGNORE INTO book_authors (book_id, author_id, role)
+                 SELECT $1, author_id, role FROM book_authors WHERE book_id = $2",
+                "UPDATE reading_sessions SET book_id = $1 WHERE book_id = $2",
+            ] {
+                sqlx::query(statement)
+                    .bind(keep_id)
+                    .bind(duplicate_id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+
+            // The sessions now belong to the kept book, restoring the duplicate brings back its own progress only
+            trash_book(&mut transaction, duplicate, files, retention_days).await?;
+        }
+
+        let kept = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
+            .bind(keep_id)
+            .fetch_one(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(kept)
+    }
+    .await;
+
+    let kept = merged.map_err(|err| {
+        // Nothing was merged, so the files go back to where they were
+        for files in &trashed {
+            files.restore();
+        }
+        database_error(err)
+    })?;
+
+    {
+        let mut book_worker = state.lock().unwrap();
+        book_worker.replace_book(kept.clone());
+        for duplicate in &duplicates {
+            book_worker.remove_book(duplicate.get_book_location());
+        }
+    }
+
+    Ok(MergeResult {
+        book: kept,
+        warnings,
+    })
+}
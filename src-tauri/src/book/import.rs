@@ -0,0 +1,311 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use epub::doc::EpubDoc;
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, Window};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    book::{
+        bookio::read_book,
+        duplicates::{hash_file, refresh_content_hashes},
+        organize::deduplicate_target,
+        util::{find_epub_paths, move_file},
+    },
+    book_item::{get_book_on_name, Book},
+    book_worker::{add_new_books, BookWorker},
+    database::get_db,
+    error::ShelfError,
+};
+
+static PROGRESS_EVENT: &str = "import-progress";
+
+/// Whether imported files are copied into the library or moved there
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    #[default]
+    Copy,
+    Move,
+}
+
+/// What happened to a single file
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Imported {
+        book: Box<Book>,
+    },
+    /// Already in the library
+    Skipped {
+        reason: String,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+/// Sent as an import-progress event after each file, and collected in the summary
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportProgress {
+    path: String,
+    /// Counts from 1
+    index: usize,
+    total: usize,
+    #[serde(flatten)]
+    outcome: ImportOutcome,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportSummary {
+    imported: usize,
+    skipped: usize,
+    failed: usize,
+    files: Vec<ImportProgress>,
+}
+
+/// The epubs to import, folders that were dropped are searched for the epubs inside them
+fn expand_paths(paths: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    paths
+        .into_iter()
+        .flat_map(|path| {
+            if Path::new(&path).is_dir() {
+                find_epub_paths(Path::new(&path))
+            } else {
+                vec![path]
+            }
+        })
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+/// Runs file work off the async runtime, hashing and unzipping big epubs takes a while
+async fn run_blocking<T, F>(work: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|err| format!("The import stopped unexpectedly: {}", err))?
+}
+
+/// Imports a single file, the hash is remembered so the same file dropped twice only goes in once
+#[instrument(skip_all, fields(source = %source.display()))]
+async fn import_file(
+    source: &Path,
+    library_dir: &Path,
+    mode: ImportMode,
+    known_hashes: &mut HashSet<String>,
+    state: &Mutex<BookWorker>,
+) -> ImportOutcome {
+    let failed = |reason: String| ImportOutcome::Failed { reason };
+
+    if !source.is_file() {
+        return failed("The file doesn't exist".to_string());
+    }
+    if !source
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+    {
+        return failed("Only epub files can be imported".to_string());
+    }
+
+    let path = source.to_path_buf();
+    let content_hash = match run_blocking(move || {
+        hash_file(&path).map_err(|err| format!("Failed to read the file: {}", err))
+    })
+    .await
+    {
+        Ok(content_hash) => content_hash,
+        Err(reason) => return failed(reason),
+    };
+    if known_hashes.contains(&content_hash) {
+        return ImportOutcome::Skipped {
+            reason: "The same file is already in the library".to_string(),
+        };
+    }
+
+    // The title is checked before anything is written, the cover cache is named after it
+    let path = source.to_path_buf();
+    let title = match run_blocking(move || {
+        EpubDoc::new(&path)
+            .map(|ebook| ebook.mdata("title"))
+            .map_err(|err| format!("Not a readable epub: {}", err))
+    })
+    .await
+    {
+        Ok(title) => title,
+        Err(reason) => return failed(reason),
+    };
+    let Some(title) = title else {
+        return failed("The epub has no title".to_string());
+    };
+    match get_book_on_name(&title).await {
+        Ok(Some(_)) => {
+            return ImportOutcome::Skipped {
+                reason: format!("A book called {} is already in the library", title),
+            }
+        }
+        Ok(None) => {}
+        Err(err) => return failed(format!("Failed to check the library: {}", err)),
+    }
+
+    // Files already in the library folder only need adding
+    let destination = if source.starts_with(library_dir) {
+        source.to_path_buf()
+    } else {
+        let file_name = source.file_name().map(PathBuf::from).unwrap_or_default();
+        let destination = deduplicate_target(library_dir.join(file_name), source, &HashSet::new());
+
+        let (from, to) = (source.to_path_buf(), destination.clone());
+        let placed = run_blocking(move || {
+            match mode {
+                ImportMode::Copy => fs::copy(&from, &to).map(|_| ()),
+                ImportMode::Move => move_file(&from, &to),
+            }
+            .map_err(|err| format!("Failed to put it in the library folder: {}", err))
+        })
+        .await;
+        if let Err(reason) = placed {
+            return failed(reason);
+        }
+        destination
+    };
+
+    // Undoes the copy or move when the book can't be added after all
+    let undo = || {
+        if destination != source {
+            _ = match mode {
+                ImportMode::Copy => fs::remove_file(&destination),
+                ImportMode::Move => move_file(&destination, source),
+            };
+        }
+    };
+
+    let book_location = destination.to_string_lossy().to_string();
+    let book = match run_blocking(move || read_book(&book_location).map_err(|err| err.to_string()))
+        .await
+    {
+        Ok(book) => book,
+        Err(reason) => {
+            undo();
+            return failed(reason);
+        }
+    };
+
+    match add_new_books(state, vec![book.clone()]).await {
+        Ok(0) => {
+            undo();
+            return failed("The book couldn't be added to the library".to_string());
+        }
+        Ok(_) => {}
+        Err(err) => {
+            undo();
+            return failed(format!("Failed to add it to the library: {}", err));
+        }
+    }
+
+    let stored = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE book_location = $1")
+        .bind(book.get_book_location())
+        .fetch_one(get_db())
+        .await;
+
+    match stored {
+        Ok(stored) => {
+            known_hashes.insert(content_hash);
+            ImportOutcome::Imported {
+                book: Box::new(stored),
+            }
+        }
+        Err(err) => failed(format!("Failed to check the library: {}", err)),
+    }
+}
+
+/// Imports files picked in the dialog or dropped on the window. Epubs are copied or moved into the library folder,
+/// read for their cover and metadata, and added to the shelf. Files already in the library are skipped.
+/// An import-progress event is sent after each file so large imports can show how far along they are
+///
+/// # Arguments
+///
+/// * `paths` - Files or folders to import, folders are searched for epubs
+/// * `mode` - copy or move, defaults to copy
+///
+#[tauri::command]
+pub async fn import_files(
+    paths: Vec<String>,
+    mode: Option<ImportMode>,
+    window: Window,
+) -> Result<ImportSummary, ShelfError> {
+    let state = window.state::<Mutex<BookWorker>>();
+    let library_dir = state
+        .lock()
+        .unwrap()
+        .get_application_settings()
+        .get("book_location")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .ok_or_else(|| {
+            ShelfError::settings("book location", "has to be set before importing books")
+        })?;
+
+    let mut known_hashes: HashSet<String> = refresh_content_hashes()
+        .await
+        .map_err(|err| ShelfError::database("load the library", err))?
+        .into_values()
+        .filter_map(|(content_hash, _)| content_hash)
+        .collect();
+
+    let paths = tauri::async_runtime::spawn_blocking(move || expand_paths(paths))
+        .await
+        .map_err(|err| ShelfError::interrupted("import", err))?;
+    let total = paths.len();
+    let mut summary = ImportSummary {
+        imported: 0,
+        skipped: 0,
+        failed: 0,
+        files: Vec::with_capacity(total),
+    };
+
+    for (position, path) in paths.into_iter().enumerate() {
+        let outcome = import_file(
+            Path::new(&path),
+            &library_dir,
+            mode.unwrap_or_default(),
+            &mut known_hashes,
+            &state,
+        )
+        .await;
+        match outcome {
+            ImportOutcome::Imported { .. } => summary.imported += 1,
+            ImportOutcome::Skipped { .. } => summary.skipped += 1,
+            ImportOutcome::Failed { .. } => summary.failed += 1,
+        }
+
+        let progress = ImportProgress {
+            path,
+            index: position + 1,
+            total,
+            outcome,
+        };
+        if let Err(err) = window.emit(PROGRESS_EVENT, &progress) {
+            warn!("Failed to send import progress: {}", err);
+        }
+        summary.files.push(progress);
+    }
+    info!(
+        imported = summary.imported,
+        skipped = summary.skipped,
+        failed = summary.failed,
+        "Import finished"
+    );
+
+    Ok(summary)
+}
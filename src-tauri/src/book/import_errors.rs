@@ -0,0 +1,190 @@
+use std::{collections::HashSet, path::Path, sync::Mutex};
+
+use serde::Serialize;
+use sqlx::FromRow;
+use tauri::State;
+
+use crate::{
+    book::bookio::read_book,
+    book_item::Book,
+    book_worker::{add_new_books, BookWorker},
+    database::get_db,
+    error::ShelfError,
+};
+
+/// A file in the library folder that couldn't be added to the shelf
+#[derive(Serialize, FromRow, Debug)]
+pub struct ImportError {
+    id: i64,
+    book_location: String,
+    /// The kind of the error that stopped it, see `ShelfError::kind`
+    kind: String,
+    reason: String,
+    /// How many scans or retries failed on it
+    attempts: i64,
+    /// Ignored files are left out of library scans
+    ignored: bool,
+    /// UTC, when it last failed
+    failed_at: String,
+}
+
+/// Records the files that failed to import, files that failed before have their attempts counted up
+///
+/// # Arguments
+///
+/// * `failures` - The file locations with what went wrong
+///
+pub async fn record_import_errors(failures: &[(String, ShelfError)]) -> Result<(), sqlx::Error> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    let mut transaction = get_db().begin().await?;
+    for (book_location, err) in failures {
+        sqlx::query(
+            "INSERT INTO import_errors (book_location, kind, reason) VALUES ($1, $2, $3)
+             ON CONFLICT (book_location) DO UPDATE SET
+                 kind = excluded.kind,
+                 reason = excluded.reason,
+                 attempts = attempts + 1,
+                 failed_at = CURRENT_TIMESTAMP",
+        )
+        .bind(book_location)
+        .bind(err.kind())
+        .bind(err.to_string())
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Forgets the errors of files that have since made it into the library
+///
+/// # Arguments
+///
+/// * `book_locations` - The files that were added
+///
+pub async fn clear_import_errors(book_locations: &[String]) -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+    for book_location in book_locations {
+        sqlx::query("DELETE FROM import_errors WHERE book_location = $1")
+            .bind(book_location)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// The files the user chose to ignore, the scan leaves these out
+pub async fn get_ignored_locations() -> Result<HashSet<String>, sqlx::Error> {
+    let locations =
+        sqlx::query_scalar::<_, String>("SELECT book_location FROM import_errors WHERE ignored")
+            .fetch_all(get_db())
+            .await?;
+
+    Ok(locations.into_iter().collect())
+}
+
+/// Lists the files that couldn't be imported, most recent failure first
+/// Entries of files that were deleted or moved since are dropped
+#[tauri::command]
+pub async fn get_import_errors() -> Result<Vec<ImportError>, ShelfError> {
+    async {
+        let errors = sqlx::query_as::<_, ImportError>(
+            "SELECT * FROM import_errors ORDER BY failed_at DESC, id DESC",
+        )
+        .fetch_all(get_db())
+        .await?;
+
+        let (errors, gone): (Vec<ImportError>, Vec<ImportError>) = errors
+            .into_iter()
+            .partition(|error| Path::new(&error.book_location).is_file());
+        let gone: Vec<String> = gone.into_iter().map(|error| error.book_location).collect();
+        if !gone.is_empty() {
+            clear_import_errors(&gone).await?;
+        }
+
+        Ok(errors)
+    }
+    .await
+    .map_err(|err: sqlx::Error| ShelfError::database("load the import errors", err))
+}
+
+/// Tries to add a file that failed before, for when the file was fixed or the failure was a fluke
+/// The entry is dropped once the book is in the library, otherwise it's updated with the new reason
+///
+/// # Arguments
+///
+/// * `book_location` - The file to try again
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn retry_import(
+    book_location: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    let location = book_location.clone();
+    let read = tauri::async_runtime::spawn_blocking(move || read_book(&location))
+        .await
+        .map_err(|err| ShelfError::interrupted("import", err))?;
+
+    let book = match read {
+        Ok(book) => book,
+        Err(err) => {
+            let failures = [(book_location.clone(), err)];
+            record_import_errors(&failures)
+                .await
+                .map_err(|err| ShelfError::database("record the import error", err))?;
+
+            let [(_, err)] = failures;
+            return Err(err);
+        }
+    };
+
+    let added = add_new_books(&state, vec![book.clone()])
+        .await
+        .map_err(|err| {
+            ShelfError::database(format!("add {} to the library", book_location), err)
+        })?;
+    if added == 0 {
+        return Err(ShelfError::invalid(format!(
+            "A book called {} is already in the library",
+            book.get_title()
+        )));
+    }
+
+    async {
+        clear_import_errors(std::slice::from_ref(&book_location)).await?;
+
+        sqlx::query_as::<_, Book>("SELECT * FROM books WHERE book_location = $1")
+            .bind(book.get_book_location())
+            .fetch_one(get_db())
+            .await
+    }
+    .await
+    .map_err(|err| ShelfError::database(format!("add {} to the library", book_location), err))
+}
+
+/// Adds a failed file to the ignore list so scans stop trying it, or takes it off so the next scan tries again
+///
+/// # Arguments
+///
+/// * `book_location` - The file that failed
+/// * `ignored` - Whether scans should skip it
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn ignore_import_error(
+    book_location: String,
+    ignored: bool,
+) -> Result<ImportError, ShelfError> {
+    sqlx::query_as::<_, ImportError>(
+        "UPDATE import_errors SET ignored = $1 WHERE book_location = $2 RETURNING *",
+    )
+    .bind(ignored)
+    .bind(&book_location)
+    .fetch_optional(get_db())
+    .await
+    .map_err(|err| ShelfError::database("update the import errors", err))?
+    .ok_or_else(|| ShelfError::not_found_in(&book_location, "the import errors"))
+}
@@ -0,0 +1,329 @@
+use std::{fs::File, io::BufReader, sync::Mutex};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tauri::State;
+use tracing::{debug, instrument};
+use zip::ZipArchive;
+
+use crate::{
+    book::{
+        metadata::read_zip_entry, navigation::spine_documents, validate::replace_html_entities,
+    },
+    book_item::Book,
+    book_worker::BookWorker,
+    database::get_db,
+    error::ShelfError,
+    stats::measured_words_per_minute,
+    xml::{parse_preserving_whitespace, visible_text},
+};
+
+// A common adult reading speed, used until enough reading has been measured
+static DEFAULT_WORDS_PER_MINUTE: f64 = 250.0;
+
+/// How long one file of the spine is
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+pub struct ChapterLength {
+    /// Where the file is in the spine, matches the spine_index of the navigation commands
+    spine_index: i64,
+    /// The file inside the epub
+    href: String,
+    word_count: i64,
+    character_count: i64,
+}
+
+impl ChapterLength {
+    pub fn get_word_count(&self) -> i64 {
+        self.word_count
+    }
+
+    pub fn get_character_count(&self) -> i64 {
+        self.character_count
+    }
+}
+
+/// Where the reading speed came from
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedSource {
+    /// Worked out from the reading sessions
+    Measured,
+    /// The words_per_minute setting
+    Configured,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChapterReadingTime {
+    #[serde(flatten)]
+    length: ChapterLength,
+    seconds: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadingTime {
+    word_count: i64,
+    character_count: i64,
+    words_per_minute: f64,
+    speed_source: SpeedSource,
+    total_seconds: i64,
+    /// What's left going by the books progress
+    remaining_seconds: i64,
+    chapters: Vec<ChapterReadingTime>,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana and katakana
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}')
+}
+
+/// Counts the words and characters in a piece of text, whitespace isn't counted as characters.
+/// Chinese and Japanese are written without spaces so each of their characters counts as a word,
+/// runs of punctuation like a lone dash aren't words
+///
+/// # Arguments
+///
+/// * `text` - The text to count
+///
+pub fn count_text(text: &str) -> (i64, i64) {
+    let (mut words, mut characters) = (0, 0);
+    let mut in_word = false;
+    let mut counted = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            in_word = false;
+            continue;
+        }
+        characters += 1;
+
+        if is_cjk(c) {
+            words += 1;
+            in_word = false;
+            continue;
+        }
+        if !in_word {
+            in_word = true;
+            counted = false;
+        }
+        if !counted && c.is_alphanumeric() {
+            words += 1;
+            counted = true;
+        }
+    }
+
+    (words, characters)
+}
+
+/// Counts every chapter of the spine, chapters are counted in parallel once they're read out of the zip
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to count
+///
+pub fn count_chapters(book_location: &str) -> Result<Vec<ChapterLength>, String> {
+    let file = File::open(book_location).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+
+    let chapters: Vec<(usize, String, Vec<u8>)> = spine_documents(&mut archive)?
+        .into_iter()
+        .filter_map(|(spine_index, href)| {
+            let content = read_zip_entry(&mut archive, &href).ok()?;
+            Some((spine_index, href, content))
+        })
+        .collect();
+
+    Ok(chapters
+        .into_par_iter()
+        .map(|(spine_index, href, content)| {
+            let (word_count, character_count) =
+                match parse_preserving_whitespace(replace_html_entities(&content).as_bytes()) {
+                    Ok(document) => count_text(&visible_text(&document)),
+                    Err(err) => {
+                        debug!(
+                            book_location,
+                            href, "Skipped a chapter that isn't xhtml: {}", err
+                        );
+                        (0, 0)
+                    }
+                };
+
+            ChapterLength {
+                spine_index: spine_index as i64,
+                href,
+                word_count,
+                character_count,
+            }
+        })
+        .collect())
+}
+
+/// Saves the counted lengths of books, replacing any chapters stored before
+///
+/// # Arguments
+///
+/// * `books` - Books already in the books table, the ones that weren't counted are skipped
+///
+#[instrument(skip_all, fields(count = books.len()))]
+pub async fn store_chapter_lengths(books: &[Book]) -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    for book in books {
+        let (Some(word_count), Some(character_count)) =
+            (book.get_word_count(), book.get_character_count())
+        else {
+            continue;
+        };
+        let book_id = sqlx::query_scalar::<_, i64>(
+            "UPDATE books SET word_count = $1, character_count = $2 WHERE book_location = $3 RETURNING id",
+        )
+        .bind(word_count)
+        .bind(character_count)
+        .bind(book.get_book_location())
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(book_id) = book_id else {
+            continue;
+        };
+
+        sqlx::query("DELETE FROM book_chapters WHERE book_id = $1")
+            .bind(book_id)
+            .execute(&mut *transaction)
+            .await?;
+        for chapter in book.get_chapter_lengths() {
+            sqlx::query(
+                "INSERT INTO book_chapters (book_id, spine_index, href, word_count, character_count)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(book_id)
+            .bind(chapter.spine_index)
+            .bind(&chapter.href)
+            .bind(chapter.word_count)
+            .bind(chapter.character_count)
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+
+    transaction.commit().await
+}
+
+/// Counts the books that were added before lengths were tracked, returns them with their counts
+/// Books whose file can't be read are left for the next time
+pub async fn count_uncounted_books() -> Result<Vec<Book>, sqlx::Error> {
+    let books = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE word_count IS NULL")
+        .fetch_all(get_db())
+        .await?;
+    if books.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let counted: Vec<Book> = tauri::async_runtime::spawn_blocking(move || {
+        books
+            .into_par_iter()
+            .filter_map(|book| {
+                let chapters = count_chapters(book.get_book_location()).ok()?;
+                Some(book.with_chapter_lengths(Some(chapters)))
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default();
+    store_chapter_lengths(&counted).await?;
+
+    Ok(counted)
+}
+
+/// The words_per_minute setting
+fn configured_words_per_minute(state: &State<'_, Mutex<BookWorker>>) -> f64 {
+    state
+        .lock()
+        .unwrap()
+        .get_application_settings()
+        .get("words_per_minute")
+        .and_then(|words| words.parse().ok())
+        .filter(|words: &f64| *words > 0.0)
+        .unwrap_or(DEFAULT_WORDS_PER_MINUTE)
+}
+
+async fn load_chapters(book_id: i64) -> Result<Vec<ChapterLength>, sqlx::Error> {
+    sqlx::query_as::<_, ChapterLength>(
+        "SELECT spine_index, href, word_count, character_count FROM book_chapters
+         WHERE book_id = $1 ORDER BY spine_index",
+    )
+    .bind(book_id)
+    .fetch_all(get_db())
+    .await
+}
+
+/// Estimates how long a book takes to read, in total, per chapter and for what's left.
+/// The speed measured from reading sessions is used once there's enough of it, the words_per_minute setting before that.
+/// Books that weren't counted yet are counted first
+///
+/// # Arguments
+///
+/// * `book_location` - The book to estimate
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_reading_time(
+    book_location: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<ReadingTime, ShelfError> {
+    let book = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE book_location = $1")
+        .bind(&book_location)
+        .fetch_optional(get_db())
+        .await
+        .map_err(|err| ShelfError::database("load the book", err))?
+        .ok_or_else(|| ShelfError::not_found(&book_location))?;
+    let book_id = book.get_id().unwrap_or_default();
+
+    let mut chapters = load_chapters(book_id)
+        .await
+        .map_err(|err| ShelfError::database("load the chapters", err))?;
+    // Books restored from a backup keep their totals but not the chapters
+    if chapters.is_empty() {
+        let location = book_location.clone();
+        chapters = tauri::async_runtime::spawn_blocking(move || count_chapters(&location))
+            .await
+            .map_err(|err| ShelfError::interrupted("word count", err))?
+            .map_err(|err| ShelfError::parse(&book_location, err))?;
+
+        let book = book.clone().with_chapter_lengths(Some(chapters.clone()));
+        store_chapter_lengths(std::slice::from_ref(&book))
+            .await
+            .map_err(|err| ShelfError::database("save the word count", err))?;
+        state.lock().unwrap().replace_book(book);
+    }
+
+    let (words_per_minute, speed_source) = match measured_words_per_minute()
+        .await
+        .map_err(|err| ShelfError::database("load reading speed", err))?
+    {
+        Some(measured) => (measured, SpeedSource::Measured),
+        None => (configured_words_per_minute(&state), SpeedSource::Configured),
+    };
+    let seconds = |words: i64| (words as f64 / words_per_minute * 60.0).round() as i64;
+
+    let word_count: i64 = chapters.iter().map(|chapter| chapter.word_count).sum();
+    let character_count = chapters.iter().map(|chapter| chapter.character_count).sum();
+    let progress = book.get_progress().unwrap_or(0.0).clamp(0.0, 1.0);
+
+    Ok(ReadingTime {
+        word_count,
+        character_count,
+        words_per_minute,
+        speed_source,
+        total_seconds: seconds(word_count),
+        remaining_seconds: (seconds(word_count) as f64 * (1.0 - progress)).round() as i64,
+        chapters: chapters
+            .into_iter()
+            .map(|length| ChapterReadingTime {
+                seconds: seconds(length.word_count),
+                length,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,938 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use tauri::State;
+use xml::{
+    attribute::{Attribute, OwnedAttribute},
+    name::{Name, OwnedName},
+    namespace::Namespace,
+    reader::{EventReader, ParserConfig, XmlEvent as ReaderEvent},
+    writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent},
+};
+use xmltree::Element;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    authors::index_book_authors,
+    book::{bookio::BookError, trash::cover_shared, util::get_cover_dir},
+    book_item::{update_book_db, Book},
+    book_worker::BookWorker,
+    error::ShelfError,
+};
+
+static DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+pub(crate) static CONTAINER_PATH: &str = "META-INF/container.xml";
+static SERIES_COLLECTION_ID: &str = "shelf-series";
+static NEW_COVER_ID: &str = "shelf-cover";
+
+/// Changes made to a books metadata, fields left out stay as they are and empty strings clear them
+#[derive(Deserialize, Debug, Default)]
+pub struct MetadataEdit {
+    title: Option<String>,
+    authors: Option<String>,
+    series: Option<String>,
+    series_index: Option<f64>,
+    description: Option<String>,
+    cover_path: Option<String>,
+}
+
+impl MetadataEdit {
+    fn title(&self) -> Option<&str> {
+        self.title
+            .as_deref()
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+    }
+
+    /// Authors are stored joined with " & ", the same way calibre shows them
+    fn authors(&self) -> Option<Vec<&str>> {
+        self.authors.as_deref().map(|authors| {
+            authors
+                .split('&')
+                .map(str::trim)
+                .filter(|author| !author.is_empty())
+                .collect()
+        })
+    }
+
+    fn series_index(&self) -> Option<String> {
+        self.series_index.map(|index| {
+            if index.fract() == 0.0 {
+                format!("{:.0}", index)
+            } else {
+                index.to_string()
+            }
+        })
+    }
+
+    /// Applies the edit to the stored copy of a book
+    fn apply(&self, book: Book) -> Book {
+        let empty_to_none = |value: &str| {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+
+        let mut book = match self.title() {
+            Some(title) => book.with_title(title.to_string()),
+            None => book,
+        };
+        if let Some(authors) = self.authors() {
+            book = book.with_authors(Some(authors.join(" & ")).filter(|a| !a.is_empty()));
+        }
+        if let Some(series) = &self.series {
+            let series = empty_to_none(series);
+            let series_index = series.as_ref().and(self.series_index);
+            book = book.with_series(series, series_index);
+        }
+        if let Some(description) = &self.description {
+            book = book.with_description(empty_to_none(description));
+        }
+
+        book
+    }
+}
+
+/// The image replacing a books cover and where it lives inside the epub
+struct CoverItem {
+    id: String,
+    zip_path: String,
+    href: String,
+    media_type: &'static str,
+    is_new: bool,
+    data: Vec<u8>,
+}
+
+fn image_media_type(path: &Path) -> Result<&'static str, BookError> {
+    match path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("jpg") | Some("jpeg") => Ok("image/jpeg"),
+        Some("png") => Ok("image/png"),
+        Some("gif") => Ok("image/gif"),
+        Some("webp") => Ok("image/webp"),
+        _ => Err(BookError::BadCoverData),
+    }
+}
+
+/// Resolves a manifest href against the folder holding the opf, giving the name of the entry in the zip
+pub(crate) fn resolve_zip_path(opf_dir: &str, href: &str) -> String {
+    let href = percent_decode_str(href.split('#').next().unwrap_or(href)).decode_utf8_lossy();
+    let mut parts: Vec<&str> = opf_dir.split('/').filter(|part| !part.is_empty()).collect();
+
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+pub(crate) fn read_zip_entry<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, BookError> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| BookError::ResourceNotFound)?;
+    let mut data = Vec::new();
+    entry
+        .read_to_end(&mut data)
+        .map_err(|_| BookError::IOError)?;
+
+    Ok(data)
+}
+
+/// Finds the package document through the container file
+pub(crate) fn find_opf_path<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<String, BookError> {
+    let container = read_zip_entry(archive, CONTAINER_PATH)?;
+    let container = Element::parse(container.as_slice()).map_err(|_| BookError::XmlParseError)?;
+
+    container
+        .get_child("rootfiles")
+        .and_then(|rootfiles| rootfiles.get_child("rootfile"))
+        .and_then(|rootfile| rootfile.attributes.get("full-path"))
+        .cloned()
+        .ok_or(BookError::ResourceNotFound)
+}
+
+/// Looks up the id and href of the current cover, either through the epub2 cover meta or the epub3 cover-image property
+fn find_cover_item(package: &Element) -> Option<(String, String)> {
+    let cover_id = package.get_child("metadata").and_then(|metadata| {
+        metadata
+            .children
+            .iter()
+            .filter_map(|child| child.as_element())
+            .find(|meta| {
+                meta.name == "meta"
+                    && meta.attributes.get("name").map(String::as_str) == Some("cover")
+            })
+            .and_then(|meta| meta.attributes.get("content"))
+    });
+
+    package
+        .get_child("manifest")?
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .find(|item| {
+            item.attributes.get("id") == cover_id
+                || item
+                    .attributes
+                    .get("properties")
+                    .is_some_and(|properties| properties.split(' ').any(|p| p == "cover-image"))
+        })
+        .and_then(|item| {
+            Some((
+                item.attributes.get("id")?.clone(),
+                item.attributes.get("href")?.clone(),
+            ))
+        })
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], local_name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attr| attr.name.local_name == local_name)
+        .map(|attr| attr.value.as_str())
+}
+
+/// Streams the package document through, swapping the edited metadata. Everything else is written back untouched
+struct OpfRewriter<'a, W: Write> {
+    writer: EventWriter<W>,
+    edit: &'a MetadataEdit,
+    cover: Option<&'a CoverItem>,
+    is_epub3: bool,
+    // Names of the metadata/manifest elements, new children reuse their prefix
+    metadata_name: Option<OwnedName>,
+    // "#id" of every replaced element, the EPUB3 metas refining them go with them
+    dropped_ids: Vec<String>,
+}
+
+impl<'a, W: Write> OpfRewriter<'a, W> {
+    fn write(&mut self, event: WriterEvent) -> Result<(), BookError> {
+        self.writer
+            .write(event)
+            .map_err(|_| BookError::XmlParseError)
+    }
+
+    fn write_element(
+        &mut self,
+        name: Name,
+        namespace: Namespace,
+        attributes: &[(&str, &str)],
+        text: Option<&str>,
+    ) -> Result<(), BookError> {
+        let attributes: Vec<Attribute> = attributes
+            .iter()
+            .map(|(name, value)| Attribute::new(Name::local(name), value))
+            .collect();
+
+        self.write(WriterEvent::StartElement {
+            name,
+            attributes: Cow::Owned(attributes),
+            namespace: Cow::Owned(namespace),
+        })?;
+        if let Some(text) = text {
+            self.write(WriterEvent::Characters(text))?;
+        }
+        self.write(WriterEvent::EndElement { name: Some(name) })
+    }
+
+    fn write_dc(&mut self, local_name: &str, text: &str) -> Result<(), BookError> {
+        let mut namespace = Namespace::empty();
+        namespace.put("dc", DC_NAMESPACE);
+
+        self.write_element(
+            Name {
+                local_name,
+                namespace: Some(DC_NAMESPACE),
+                prefix: Some("dc"),
+            },
+            namespace,
+            &[],
+            Some(text),
+        )
+    }
+
+    fn write_meta(
+        &mut self,
+        attributes: &[(&str, &str)],
+        text: Option<&str>,
+    ) -> Result<(), BookError> {
+        let metadata_name = self
+            .metadata_name
+            .clone()
+            .unwrap_or(OwnedName::local("metadata"));
+        let name = Name {
+            local_name: "meta",
+            namespace: metadata_name.namespace.as_deref(),
+            prefix: metadata_name.prefix.as_deref(),
+        };
+
+        self.write_element(name, Namespace::empty(), attributes, text)
+    }
+
+    /// Whether a child of metadata is replaced by the edit
+    fn is_replaced(&self, name: &OwnedName, attributes: &[OwnedAttribute]) -> bool {
+        let edit = self.edit;
+        let is_dc = name
+            .namespace
+            .as_deref()
+            .is_some_and(|namespace| namespace.starts_with("http://purl.org/dc/elements/"));
+
+        if is_dc {
+            return match name.local_name.as_str() {
+                "title" => edit.title().is_some(),
+                "creator" => edit.authors.is_some(),
+                "description" => edit.description.is_some(),
+                _ => false,
+            };
+        }
+
+        if name.local_name != "meta" {
+            return false;
+        }
+
+        let meta_name = attribute(attributes, "name");
+        let property = attribute(attributes, "property");
+
+        if edit.series.is_some()
+            && (matches!(
+                meta_name,
+                Some("calibre:series") | Some("calibre:series_index")
+            ) || property == Some("belongs-to-collection"))
+        {
+            return true;
+        }
+
+        self.cover.is_some_and(|cover| cover.is_new) && meta_name == Some("cover")
+    }
+
+    /// Whether a child of metadata is left out, either replaced or refining something that was
+    fn is_dropped(&self, name: &OwnedName, attributes: &[OwnedAttribute]) -> bool {
+        self.is_replaced(name, attributes)
+            || attribute(attributes, "refines")
+                .is_some_and(|refines| self.dropped_ids.iter().any(|id| id == refines))
+    }
+
+    /// Collects the ids of the replaced elements. Metas can refine an element before or after it,
+    /// so this is a pass of its own before anything is written
+    fn collect_dropped_ids(&mut self, opf: &[u8]) -> Result<(), BookError> {
+        let mut depth = 0;
+        let mut in_metadata = false;
+
+        for event in EventReader::new(opf) {
+            match event.map_err(|_| BookError::XmlParseError)? {
+                ReaderEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    depth += 1;
+                    if depth == 2 && name.local_name == "metadata" {
+                        in_metadata = true;
+                    } else if depth == 3 && in_metadata && self.is_replaced(&name, &attributes) {
+                        if let Some(id) = attribute(&attributes, "id") {
+                            self.dropped_ids.push(format!("#{}", id));
+                        }
+                    }
+                }
+                ReaderEvent::EndElement { name } => {
+                    if depth == 2 && name.local_name == "metadata" {
+                        in_metadata = false;
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the edited fields at the end of the metadata element
+    fn write_new_metadata(&mut self) -> Result<(), BookError> {
+        let edit = self.edit;
+
+        if let Some(title) = edit.title() {
+            self.write_dc("title", title)?;
+        }
+        for author in edit.authors().unwrap_or_default() {
+            self.write_dc("creator", author)?;
+        }
+        if let Some(description) = edit
+            .description
+            .as_deref()
+            .map(str::trim)
+            .filter(|description| !description.is_empty())
+        {
+            self.write_dc("description", description)?;
+        }
+
+        if let Some(series) = edit
+            .series
+            .as_deref()
+            .map(str::trim)
+            .filter(|series| !series.is_empty())
+        {
+            let series_index = edit.series_index();
+
+            // Calibre style metas are what most readers understand, epub3 readers look for the collection
+            self.write_meta(&[("name", "calibre:series"), ("content", series)], None)?;
+            if let Some(series_index) = &series_index {
+                self.write_meta(
+                    &[("name", "calibre:series_index"), ("content", series_index)],
+                    None,
+                )?;
+            }
+
+            if self.is_epub3 {
+                let refines = format!("#{}", SERIES_COLLECTION_ID);
+                self.write_meta(
+                    &[
+                        ("property", "belongs-to-collection"),
+                        ("id", SERIES_COLLECTION_ID),
+                    ],
+                    Some(series),
+                )?;
+                self.write_meta(
+                    &[("refines", &refines), ("property", "collection-type")],
+                    Some("series"),
+                )?;
+                if let Some(series_index) = &series_index {
+                    self.write_meta(
+                        &[("refines", &refines), ("property", "group-position")],
+                        Some(series_index),
+                    )?;
+                }
+            }
+        }
+
+        if self.cover.is_some_and(|cover| cover.is_new) {
+            self.write_meta(&[("name", "cover"), ("content", NEW_COVER_ID)], None)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_new_cover_item(&mut self, manifest_name: &OwnedName) -> Result<(), BookError> {
+        let Some(cover) = self.cover.filter(|cover| cover.is_new) else {
+            return Ok(());
+        };
+
+        let mut attributes = vec![
+            ("id", cover.id.as_str()),
+            ("href", cover.href.as_str()),
+            ("media-type", cover.media_type),
+        ];
+        if self.is_epub3 {
+            attributes.push(("properties", "cover-image"));
+        }
+
+        let name = Name {
+            local_name: "item",
+            namespace: manifest_name.namespace.as_deref(),
+            prefix: manifest_name.prefix.as_deref(),
+        };
+        self.write_element(name, Namespace::empty(), &attributes, None)
+    }
+
+    fn rewrite(mut self, opf: &[u8]) -> Result<W, BookError> {
+        self.collect_dropped_ids(opf)?;
+
+        let config = ParserConfig::new()
+            .ignore_comments(false)
+            .cdata_to_characters(false);
+        let reader = EventReader::new_with_config(opf, config);
+
+        let mut depth = 0;
+        // Depth of a replaced element, its children are skipped along with it
+        let mut skip_depth: Option<usize> = None;
+
+        for event in reader {
+            let event = event.map_err(|_| BookError::XmlParseError)?;
+
+            match &event {
+                ReaderEvent::StartElement {
+                    name,
+                    attributes,
+                    namespace,
+                } => {
+                    depth += 1;
+                    if skip_depth.is_some() {
+                        continue;
+                    }
+
+                    if depth == 2 && name.local_name == "metadata" {
+                        self.metadata_name = Some(name.clone());
+                    } else if depth == 3
+                        && self.metadata_name.is_some()
+                        && self.is_dropped(name, attributes)
+                    {
+                        skip_depth = Some(depth);
+                        continue;
+                    }
+
+                    // An existing cover keeps its id and href, only the type might change
+                    let replaced_cover = self.cover.filter(|cover| {
+                        !cover.is_new
+                            && name.local_name == "item"
+                            && attribute(attributes, "id") == Some(cover.id.as_str())
+                    });
+                    if let Some(cover) = replaced_cover {
+                        let attributes: Vec<Attribute> = attributes
+                            .iter()
+                            .map(|attr| match attr.name.local_name.as_str() {
+                                "media-type" => {
+                                    Attribute::new(attr.name.borrow(), cover.media_type)
+                                }
+                                _ => attr.borrow(),
+                            })
+                            .collect();
+                        self.write(WriterEvent::StartElement {
+                            name: name.borrow(),
+                            attributes: Cow::Owned(attributes),
+                            namespace: Cow::Borrowed(namespace),
+                        })?;
+                        continue;
+                    }
+                }
+                ReaderEvent::EndElement { name } => {
+                    let current_depth = depth;
+                    depth -= 1;
+
+                    if let Some(skipped) = skip_depth {
+                        if skipped == current_depth {
+                            skip_depth = None;
+                        }
+                        continue;
+                    }
+
+                    if current_depth == 2 && name.local_name == "metadata" {
+                        self.write_new_metadata()?;
+                        self.metadata_name = None;
+                    } else if current_depth == 2 && name.local_name == "manifest" {
+                        self.write_new_cover_item(name)?;
+                    }
+                }
+                _ if skip_depth.is_some() => continue,
+                _ => {}
+            }
+
+            if let Some(event) = event.as_writer_event() {
+                self.write(event)?;
+            }
+        }
+
+        Ok(self.writer.into_inner())
+    }
+}
+
+/// Rewrites the package document with the edit applied
+///
+/// # Arguments
+///
+/// * `opf` - The original package document
+/// * `edit` - The changes to make
+/// * `cover` - The cover being swapped in, if any
+///
+fn rewrite_opf(
+    opf: &[u8],
+    edit: &MetadataEdit,
+    cover: Option<&CoverItem>,
+) -> Result<Vec<u8>, BookError> {
+    let package = Element::parse(opf).map_err(|_| BookError::XmlParseError)?;
+    let is_epub3 = package
+        .attributes
+        .get("version")
+        .is_some_and(|version| version.starts_with('3'));
+
+    let rewriter = OpfRewriter {
+        writer: EventWriter::new_with_config(Vec::new(), EmitterConfig::new()),
+        edit,
+        cover,
+        is_epub3,
+        metadata_name: None,
+        dropped_ids: Vec::new(),
+    };
+
+    rewriter.rewrite(opf)
+}
+
+/// Writes the edited metadata into a copy of the epub next to the book and returns where it is.
+/// The caller renames it over the book once the change is saved, so a failed save leaves the original alone
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to edit
+/// * `edit` - The changes to make
+///
+pub fn write_epub_metadata(
+    book_location: &Path,
+    edit: &MetadataEdit,
+) -> Result<PathBuf, BookError> {
+    let file = File::open(book_location).map_err(|_| BookError::IOError)?;
+    let mut archive =
+        ZipArchive::new(BufReader::new(file)).map_err(|_| BookError::ResourceNotFound)?;
+
+    let opf_path = find_opf_path(&mut archive)?;
+    let opf_dir = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let opf = read_zip_entry(&mut archive, &opf_path)?;
+
+    let cover = match &edit.cover_path {
+        Some(cover_path) => {
+            let cover_path = PathBuf::from(cover_path);
+            let media_type = image_media_type(&cover_path)?;
+            let data = fs::read(&cover_path).map_err(|_| BookError::BadCoverData)?;
+            let package = Element::parse(opf.as_slice()).map_err(|_| BookError::XmlParseError)?;
+
+            Some(match find_cover_item(&package) {
+                Some((id, href)) => CoverItem {
+                    id,
+                    zip_path: resolve_zip_path(opf_dir, &href),
+                    href,
+                    media_type,
+                    is_new: false,
+                    data,
+                },
+                None => {
+                    let extension = cover_path
+                        .extension()
+                        .map(|ext| ext.to_string_lossy().to_lowercase())
+                        .unwrap_or_default();
+                    let href = format!("{}.{}", NEW_COVER_ID, extension);
+
+                    CoverItem {
+                        id: NEW_COVER_ID.to_string(),
+                        zip_path: resolve_zip_path(opf_dir, &href),
+                        href,
+                        media_type,
+                        is_new: true,
+                        data,
+                    }
+                }
+            })
+        }
+        None => None,
+    };
+
+    let new_opf = rewrite_opf(&opf, edit, cover.as_ref())?;
+
+    let temp_location = book_location.with_extension("epub.tmp");
+    let written = write_edited_archive(
+        &mut archive,
+        &temp_location,
+        &opf_path,
+        &new_opf,
+        cover.as_ref(),
+    );
+
+    if let Err(err) = written {
+        _ = fs::remove_file(&temp_location);
+        return Err(err);
+    }
+
+    Ok(temp_location)
+}
+
+/// Copies every entry into a new archive, the changed ones are written fresh and the rest are copied without recompressing
+fn write_edited_archive<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    temp_location: &Path,
+    opf_path: &str,
+    new_opf: &[u8],
+    cover: Option<&CoverItem>,
+) -> Result<(), BookError> {
+    let temp_file = File::create(temp_location).map_err(|_| BookError::IOError)?;
+    let mut writer = ZipWriter::new(BufWriter::new(temp_file));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // Readers expect the mimetype first and uncompressed
+    if let Ok(mimetype) = archive.by_name("mimetype") {
+        writer
+            .raw_copy_file(mimetype)
+            .map_err(|_| BookError::IOError)?;
+    }
+
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|_| BookError::ResourceNotFound)?;
+        let name = entry.name().to_string();
+
+        let replacement = if name == "mimetype" {
+            continue;
+        } else if name == opf_path {
+            Some((new_opf, deflated))
+        } else {
+            cover
+                .filter(|cover| !cover.is_new && cover.zip_path == name)
+                .map(|cover| (cover.data.as_slice(), stored))
+        };
+
+        match replacement {
+            Some((data, options)) => {
+                drop(entry);
+                writer
+                    .start_file(name, options)
+                    .and_then(|_| writer.write_all(data).map_err(Into::into))
+                    .map_err(|_| BookError::IOError)?;
+            }
+            None => writer
+                .raw_copy_file(entry)
+                .map_err(|_| BookError::IOError)?,
+        }
+    }
+
+    if let Some(cover) = cover.filter(|cover| cover.is_new) {
+        writer
+            .start_file(cover.zip_path.as_str(), stored)
+            .and_then(|_| writer.write_all(&cover.data).map_err(Into::into))
+            .map_err(|_| BookError::IOError)?;
+    }
+
+    writer
+        .finish()
+        .and_then(|mut file| file.flush().map_err(Into::into))
+        .map_err(|_| BookError::IOError)
+}
+
+/// Copies a new cover into the cover cache, returning the cached file name
+///
+/// # Arguments
+///
+/// * `cover_path` - The image picked by the user
+/// * `title` - The books title, used to name the cached cover
+///
+fn cache_cover(cover_path: &Path, title: &str) -> Option<String> {
+    let cover_name = Book::sanitize_windows_filename(format!("{}.jpg", title));
+
+    fs::copy(cover_path, get_cover_dir().join(&cover_name))
+        .ok()
+        .map(|_| cover_name)
+}
+
+/// Edits a books metadata, optionally writing it back into the epub so other readers see it too
+///
+/// # Arguments
+///
+/// * `book_location` - The location of the book being edited
+/// * `edit` - The changed fields, fields left out are kept and empty strings clear them
+/// * `write_to_epub` - Whether the epub file itself should be updated
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn edit_book_metadata(
+    book_location: String,
+    edit: MetadataEdit,
+    write_to_epub: bool,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    let book = state
+        .lock()
+        .unwrap()
+        .get_book_cache()
+        .find_by_location(&book_location)
+        .cloned()
+        .ok_or_else(|| ShelfError::not_found(&book_location))?;
+
+    // The lock isn't held while the epub is rewritten, big files take a moment
+    let (edit, edited_epub) = if write_to_epub {
+        let epub_location = PathBuf::from(&book_location);
+        tauri::async_runtime::spawn_blocking(move || {
+            write_epub_metadata(&epub_location, &edit).map(|edited| (edit, Some(edited)))
+        })
+        .await
+        .map_err(|err| ShelfError::interrupted("metadata update", err))?
+        .map_err(|err| ShelfError::epub(&book_location, err))?
+    } else {
+        (edit, None)
+    };
+    // Until the database has the new metadata the original epub stays in place
+    let discard_edited_epub = || {
+        if let Some(edited_epub) = &edited_epub {
+            _ = fs::remove_file(edited_epub);
+        }
+    };
+
+    let old_cover = book.get_cover_filename().to_string();
+    let mut edited_book = edit.apply(book.clone());
+
+    if let Some(cover_path) = &edit.cover_path {
+        let cover_name =
+            cache_cover(Path::new(cover_path), edited_book.get_title()).ok_or_else(|| {
+                discard_edited_epub();
+                ShelfError::Cover {
+                    path: cover_path.clone(),
+                    source: BookError::BadCoverData,
+                }
+            })?;
+        edited_book = edited_book.with_cover_location(Some(cover_name));
+    }
+
+    update_book_db(&edited_book).await.map_err(|err| {
+        discard_edited_epub();
+        ShelfError::database(format!("save the metadata of {}", book_location), err)
+    })?;
+    if let Some(edited_epub) = &edited_epub {
+        if let Err(err) = fs::rename(edited_epub, &book_location) {
+            // The epub still has the old metadata, so the library goes back to matching it
+            discard_edited_epub();
+            _ = update_book_db(&book).await;
+            return Err(ShelfError::io("replace", &book_location, err));
+        }
+    }
+
+    // Renamed books would otherwise leave their old cover behind, unless another book still uses it
+    if edited_book.get_cover_filename() != old_cover && old_cover != env!("DEFAULT_COVER_NAME") {
+        // When in doubt the cover stays, a stray file is better than a missing one
+        if !cover_shared(&old_cover, edited_book.get_id())
+            .await
+            .unwrap_or(true)
+        {
+            _ = fs::remove_file(get_cover_dir().join(&old_cover));
+        }
+    }
+    if edit.authors.is_some() {
+        index_book_authors(std::slice::from_ref(&edited_book))
+            .await
+            .map_err(|err| {
+                ShelfError::database(format!("index the authors of {}", book_location), err)
+            })?;
+    }
+    state.lock().unwrap().replace_book(edited_book.clone());
+
+    Ok(edited_book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xmltree::XMLNode;
+
+    // The title refine comes before the title, epub3 allows refines anywhere in metadata
+    static OPF: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <meta refines="#title" property="title-type">main</meta>
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:title id="title">Old Title</dc:title>
+    <dc:creator id="creator" opf:role="aut">Old Author</dc:creator>
+    <meta refines="#creator" property="file-as">Author, Old</meta>
+    <meta refines="#creator" property="role" scheme="marc:relators">aut</meta>
+    <dc:language>en</dc:language>
+    <meta property="belongs-to-collection" id="collection">Old Series</meta>
+    <meta refines="#collection" property="collection-type">series</meta>
+    <meta refines="#uid" property="identifier-type">uuid</meta>
+  </metadata>
+  <manifest><item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="c1"/></spine>
+</package>"##;
+
+    fn rewritten_metadata(edit: &MetadataEdit) -> Element {
+        let opf = rewrite_opf(OPF.as_bytes(), edit, None).unwrap();
+        Element::parse(opf.as_slice())
+            .unwrap()
+            .take_child("metadata")
+            .unwrap()
+    }
+
+    fn elements<'a>(metadata: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        metadata
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .filter(move |element| element.name == name)
+    }
+
+    fn texts(metadata: &Element, name: &str) -> Vec<String> {
+        elements(metadata, name)
+            .filter_map(|element| Some(element.get_text()?.into_owned()))
+            .collect()
+    }
+
+    fn refines(metadata: &Element) -> Vec<&str> {
+        elements(metadata, "meta")
+            .filter_map(|meta| meta.attributes.get("refines"))
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn replacing_title_and_authors_drops_their_refines() {
+        let metadata = rewritten_metadata(&MetadataEdit {
+            title: Some("New Title".to_string()),
+            authors: Some("First & Second".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(texts(&metadata, "title"), ["New Title"]);
+        assert_eq!(texts(&metadata, "creator"), ["First", "Second"]);
+        assert_eq!(refines(&metadata), ["#collection", "#uid"]);
+        assert_eq!(texts(&metadata, "language"), ["en"]);
+        assert_eq!(texts(&metadata, "identifier"), ["urn:uuid:1234"]);
+    }
+
+    #[test]
+    fn replacing_the_series_drops_the_old_collection() {
+        let metadata = rewritten_metadata(&MetadataEdit {
+            series: Some("New Series".to_string()),
+            series_index: Some(2.0),
+            ..Default::default()
+        });
+
+        let collections: Vec<String> = elements(&metadata, "meta")
+            .filter(|meta| {
+                meta.attributes.get("property").map(String::as_str) == Some("belongs-to-collection")
+            })
+            .filter_map(|meta| Some(meta.get_text()?.into_owned()))
+            .collect();
+        assert_eq!(collections, ["New Series"]);
+        assert_eq!(
+            refines(&metadata),
+            [
+                "#title",
+                "#creator",
+                "#creator",
+                "#uid",
+                "#shelf-series",
+                "#shelf-series"
+            ]
+        );
+        assert_eq!(texts(&metadata, "title"), ["Old Title"]);
+    }
+
+    #[test]
+    fn fields_left_out_of_the_edit_are_untouched() {
+        let metadata = rewritten_metadata(&MetadataEdit {
+            description: Some("A description".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            refines(&metadata),
+            ["#title", "#creator", "#creator", "#collection", "#uid"]
+        );
+        assert_eq!(texts(&metadata, "description"), ["A description"]);
+        let creator = elements(&metadata, "creator").next().unwrap();
+        assert_eq!(
+            creator.attributes.get("role").map(String::as_str),
+            Some("aut")
+        );
+        assert_eq!(creator.get_text().as_deref(), Some("Old Author"));
+    }
+}
@@ -1,2 +1,17 @@
 pub mod bookio;
+pub mod duplicates;
+pub mod import;
+pub mod import_errors;
+pub mod length;
+pub mod metadata;
+pub mod navigation;
+pub mod organize;
+pub mod query;
+pub mod scan;
+pub mod search;
+pub mod series;
+pub mod speech;
+pub mod status;
+pub mod trash;
 pub mod util;
+pub mod validate;
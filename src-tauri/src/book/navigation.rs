@@ -0,0 +1,507 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use serde::Serialize;
+use xmltree::Element;
+use zip::ZipArchive;
+
+use crate::{
+    book::{
+        metadata::{find_opf_path, read_zip_entry, resolve_zip_path},
+        validate::replace_html_entities,
+    },
+    error::ShelfError,
+    xml::{child_elements, element_text},
+};
+
+static NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+static XHTML_MEDIA_TYPE: &str = "application/xhtml+xml";
+
+#[derive(Serialize, Debug)]
+pub struct ManifestItem {
+    id: String,
+    /// The file inside the epub
+    href: String,
+    media_type: String,
+    /// Like nav, cover-image or scripted
+    properties: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SpineItem {
+    idref: String,
+    /// None when the manifest has no item with the idref
+    href: Option<String>,
+    media_type: Option<String>,
+    /// Non linear items, like footnotes, are reached through links and skipped when paging through the book
+    linear: bool,
+}
+
+/// A link into the book
+#[derive(Serialize, Debug)]
+pub struct NavTarget {
+    label: String,
+    /// The file inside the epub, none for links that leave the book
+    href: Option<String>,
+    /// The spot inside the file
+    fragment: Option<String>,
+    /// Where the file is in the spine
+    spine_index: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TocEntry {
+    #[serde(flatten)]
+    target: NavTarget,
+    children: Vec<TocEntry>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Landmark {
+    /// Like toc, bodymatter or cover
+    kind: String,
+    #[serde(flatten)]
+    target: NavTarget,
+}
+
+/// Where the navigation was read from
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum NavigationSource {
+    /// The epub3 navigation document
+    Nav,
+    /// The epub2 ncx, with the guide for landmarks
+    Ncx,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BookNavigation {
+    /// None when the book has neither a navigation document nor an ncx
+    source: Option<NavigationSource>,
+    toc: Vec<TocEntry>,
+    landmarks: Vec<Landmark>,
+    /// The page numbers of the printed book, empty for most books
+    page_list: Vec<NavTarget>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BookManifest {
+    /// The epub version from the package, like 2.0 or 3.0
+    version: Option<String>,
+    spine: Vec<SpineItem>,
+    manifest: Vec<ManifestItem>,
+}
+
+/// The package document with the manifest and spine pulled out
+struct Package {
+    opf_path: String,
+    element: Element,
+    manifest: Vec<ManifestItem>,
+    spine: Vec<SpineItem>,
+    /// Where each file is in the spine, by its path inside the epub
+    spine_positions: HashMap<String, usize>,
+}
+
+impl Package {
+    /// Builds a link from an href, relative hrefs are resolved against the file they were found in
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The file the href comes from
+    /// * `label` - What the link is called
+    /// * `href` - The href as written
+    ///
+    fn target(&self, base: &str, label: String, href: Option<&str>) -> NavTarget {
+        let label = label.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let Some(href) = href.filter(|href| !href.contains("://")) else {
+            return NavTarget {
+                label,
+                href: None,
+                fragment: None,
+                spine_index: None,
+            };
+        };
+
+        let (path, fragment) = match href.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment.to_string())),
+            None => (href, None),
+        };
+        // A bare fragment points into the file it was found in
+        let href = if path.is_empty() {
+            base.to_string()
+        } else {
+            resolve_zip_path(parent_dir(base), path)
+        };
+
+        NavTarget {
+            label,
+            spine_index: self.spine_positions.get(&href).copied(),
+            href: Some(href),
+            fragment,
+        }
+    }
+
+    fn item_with(&self, predicate: impl Fn(&ManifestItem) -> bool) -> Option<&ManifestItem> {
+        self.manifest.iter().find(|item| predicate(item))
+    }
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn read_package<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Package, String> {
+    let opf_path = find_opf_path(archive).map_err(|err| err.to_string())?;
+    let opf = read_zip_entry(archive, &opf_path).map_err(|err| err.to_string())?;
+    let element = Element::parse(opf.as_slice()).map_err(|err| err.to_string())?;
+    let opf_dir = parent_dir(&opf_path);
+
+    let manifest: Vec<ManifestItem> = element
+        .get_child("manifest")
+        .map(|manifest| {
+            child_elements(manifest, "item")
+                .filter_map(|item| {
+                    Some(ManifestItem {
+                        id: item.attributes.get("id")?.clone(),
+                        href: resolve_zip_path(opf_dir, item.attributes.get("href")?),
+                        media_type: item
+                            .attributes
+                            .get("media-type")
+                            .cloned()
+                            .unwrap_or_default(),
+                        properties: item
+                            .attributes
+                            .get("properties")
+                            .map(|properties| {
+                                properties.split_whitespace().map(String::from).collect()
+                            })
+                            .unwrap_or_default(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let spine: Vec<SpineItem> = element
+        .get_child("spine")
+        .map(|spine| {
+            child_elements(spine, "itemref")
+                .filter_map(|itemref| {
+                    let idref = itemref.attributes.get("idref")?;
+                    let item = manifest.iter().find(|item| &item.id == idref);
+
+                    Some(SpineItem {
+                        idref: idref.clone(),
+                        href: item.map(|item| item.href.clone()),
+                        media_type: item.map(|item| item.media_type.clone()),
+                        linear: itemref.attributes.get("linear").map(String::as_str) != Some("no"),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut spine_positions = HashMap::new();
+    for (index, item) in spine.iter().enumerate() {
+        if let Some(href) = &item.href {
+            spine_positions.entry(href.clone()).or_insert(index);
+        }
+    }
+
+    Ok(Package {
+        opf_path,
+        element,
+        manifest,
+        spine,
+        spine_positions,
+    })
+}
+
+/// The xhtml files of the spine in reading order with where they are in the spine, for going through a books text
+pub(crate) fn spine_documents<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<(usize, String)>, String> {
+    Ok(read_package(archive)?
+        .spine
+        .into_iter()
+        .enumerate()
+        .filter(|(_, item)| item.media_type.as_deref() == Some(XHTML_MEDIA_TYPE))
+        .filter_map(|(spine_index, item)| Some((spine_index, item.href?)))
+        .collect())
+}
+
+/// Reads an xhtml or xml file from the epub, html entities are swapped first since navigation documents often use them
+fn read_document<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Option<Element> {
+    let content = read_zip_entry(archive, path).ok()?;
+
+    Element::parse(replace_html_entities(&content).as_bytes()).ok()
+}
+
+/// Collects the nav elements of a navigation document, they can sit anywhere in the body
+fn find_navs<'a>(element: &'a Element, navs: &mut Vec<&'a Element>) {
+    for child in element
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+    {
+        if child.name == "nav" {
+            navs.push(child);
+        } else {
+            find_navs(child, navs);
+        }
+    }
+}
+
+fn nav_type(element: &Element) -> impl Iterator<Item = &str> {
+    // epub:type, xmltree keys attributes by their local name
+    element
+        .attributes
+        .get("type")
+        .map(String::as_str)
+        .unwrap_or_default()
+        .split_whitespace()
+}
+
+/// Reads the list items of a nav, headings without a link are kept with their children
+fn nav_entries(package: &Package, base: &str, list: &Element) -> Vec<TocEntry> {
+    child_elements(list, "li")
+        .filter_map(|item| {
+            let link = item.get_child("a").or_else(|| item.get_child("span"))?;
+            let children = item
+                .get_child("ol")
+                .map(|list| nav_entries(package, base, list))
+                .unwrap_or_default();
+
+            Some(TocEntry {
+                target: package.target(
+                    base,
+                    element_text(link),
+                    link.attributes.get("href").map(String::as_str),
+                ),
+                children,
+            })
+        })
+        .collect()
+}
+
+fn flatten(entries: Vec<TocEntry>, targets: &mut Vec<NavTarget>) {
+    for entry in entries {
+        targets.push(entry.target);
+        flatten(entry.children, targets);
+    }
+}
+
+fn read_nav_document(package: &Package, nav_path: &str, document: &Element) -> BookNavigation {
+    let mut navs = Vec::new();
+    find_navs(document, &mut navs);
+    let nav_of_type = |kind: &str| {
+        navs.iter()
+            .find(|nav| nav_type(nav).any(|nav_type| nav_type == kind))
+            .and_then(|nav| nav.get_child("ol"))
+    };
+
+    let toc = nav_of_type("toc")
+        .map(|list| nav_entries(package, nav_path, list))
+        .unwrap_or_default();
+    let landmarks = nav_of_type("landmarks")
+        .map(|list| {
+            child_elements(list, "li")
+                .filter_map(|item| item.get_child("a"))
+                .map(|link| Landmark {
+                    kind: nav_type(link).next().unwrap_or_default().to_string(),
+                    target: package.target(
+                        nav_path,
+                        element_text(link),
+                        link.attributes.get("href").map(String::as_str),
+                    ),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut page_list = Vec::new();
+    if let Some(list) = nav_of_type("page-list") {
+        flatten(nav_entries(package, nav_path, list), &mut page_list);
+    }
+
+    BookNavigation {
+        source: Some(NavigationSource::Nav),
+        toc,
+        landmarks,
+        page_list,
+    }
+}
+
+fn ncx_label(element: &Element) -> String {
+    element
+        .get_child("navLabel")
+        .and_then(|label| label.get_child("text"))
+        .map(element_text)
+        .unwrap_or_default()
+}
+
+fn ncx_target(package: &Package, ncx_path: &str, element: &Element) -> NavTarget {
+    package.target(
+        ncx_path,
+        ncx_label(element),
+        element
+            .get_child("content")
+            .and_then(|content| content.attributes.get("src"))
+            .map(String::as_str),
+    )
+}
+
+fn ncx_entries(package: &Package, ncx_path: &str, parent: &Element) -> Vec<TocEntry> {
+    child_elements(parent, "navPoint")
+        .map(|point| TocEntry {
+            target: ncx_target(package, ncx_path, point),
+            children: ncx_entries(package, ncx_path, point),
+        })
+        .collect()
+}
+
+/// Reads an epub2 ncx, landmarks come from the guide in the package document
+fn read_ncx(package: &Package, ncx_path: &str, ncx: &Element) -> BookNavigation {
+    let toc = ncx
+        .get_child("navMap")
+        .map(|map| ncx_entries(package, ncx_path, map))
+        .unwrap_or_default();
+    let page_list = ncx
+        .get_child("pageList")
+        .map(|list| {
+            child_elements(list, "pageTarget")
+                .map(|target| ncx_target(package, ncx_path, target))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    BookNavigation {
+        source: Some(NavigationSource::Ncx),
+        toc,
+        landmarks: read_guide(package),
+        page_list,
+    }
+}
+
+fn read_guide(package: &Package) -> Vec<Landmark> {
+    package
+        .element
+        .get_child("guide")
+        .map(|guide| {
+            child_elements(guide, "reference")
+                .map(|reference| Landmark {
+                    kind: reference
+                        .attributes
+                        .get("type")
+                        .cloned()
+                        .unwrap_or_default(),
+                    target: package.target(
+                        &package.opf_path,
+                        reference
+                            .attributes
+                            .get("title")
+                            .cloned()
+                            .unwrap_or_default(),
+                        reference.attributes.get("href").map(String::as_str),
+                    ),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Prefers the epub3 navigation document and falls back to the ncx, epub3 books often carry both
+fn read_navigation<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    package: &Package,
+) -> BookNavigation {
+    let nav_path = package
+        .item_with(|item| item.properties.iter().any(|property| property == "nav"))
+        .map(|item| item.href.clone());
+    if let Some(nav_path) = nav_path {
+        if let Some(document) = read_document(archive, &nav_path) {
+            let navigation = read_nav_document(package, &nav_path, &document);
+            if !navigation.toc.is_empty() {
+                return navigation;
+            }
+        }
+    }
+
+    let toc_id = package
+        .element
+        .get_child("spine")
+        .and_then(|spine| spine.attributes.get("toc"));
+    let ncx_path = package
+        .item_with(|item| Some(&item.id) == toc_id)
+        .or_else(|| package.item_with(|item| item.media_type == NCX_MEDIA_TYPE))
+        .map(|item| item.href.clone());
+    if let Some(ncx_path) = ncx_path {
+        if let Some(ncx) = read_document(archive, &ncx_path) {
+            return read_ncx(package, &ncx_path, &ncx);
+        }
+    }
+
+    BookNavigation {
+        source: None,
+        toc: Vec::new(),
+        landmarks: read_guide(package),
+        page_list: Vec::new(),
+    }
+}
+
+/// Opens an epub off the async runtime and hands its package document to `read`
+async fn with_package<T, F>(book_location: String, read: F) -> Result<T, ShelfError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut ZipArchive<BufReader<File>>, Package) -> T + Send + 'static,
+{
+    if !Path::new(&book_location).is_file() {
+        return Err(ShelfError::not_found(&book_location));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = File::open(&book_location)
+            .map_err(|err| ShelfError::io("open", &book_location, err))?;
+        let mut archive = ZipArchive::new(BufReader::new(file))
+            .map_err(|err| ShelfError::parse(&book_location, err.to_string()))?;
+        let package =
+            read_package(&mut archive).map_err(|err| ShelfError::parse(&book_location, err))?;
+
+        Ok(read(&mut archive, package))
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("book inspection", err))?
+}
+
+/// Reads the table of contents, landmarks and page list of a book, for the chapter sidebar and page numbers
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to read
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_book_navigation(book_location: String) -> Result<BookNavigation, ShelfError> {
+    with_package(book_location, |archive, package| {
+        read_navigation(archive, &package)
+    })
+    .await
+}
+
+/// Reads the reading order and every file listed in a book
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to read
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_book_manifest(book_location: String) -> Result<BookManifest, ShelfError> {
+    with_package(book_location, |_, package| BookManifest {
+        version: package.element.attributes.get("version").cloned(),
+        spine: package.spine,
+        manifest: package.manifest,
+    })
+    .await
+}
@@ -0,0 +1,457 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use tauri::State;
+use tracing::error;
+
+use crate::{
+    authors::normalize_author,
+    book::util::move_file,
+    book_item::{get_all_books, Book},
+    book_worker::BookWorker,
+    database::get_db,
+    error::ShelfError,
+    shelf::shelf_settings_values,
+};
+
+static FIELDS: [&str; 6] = [
+    "title",
+    "author",
+    "author_sort",
+    "series",
+    "series_index",
+    "ext",
+];
+static UNKNOWN_AUTHOR: &str = "Unknown";
+// Keeps paths clear of the 260 character limit older Windows tools still have
+static MAX_SEGMENT_CHARS: usize = 100;
+
+/// A piece of a path template, either text that's copied as is or a field filled in from the book
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Field(String),
+}
+
+/// A file the organizer moves, or would move on a dry run
+#[derive(Serialize, Debug)]
+pub struct PlannedMove {
+    title: String,
+    from: String,
+    to: String,
+}
+
+/// A book the organizer left where it is
+#[derive(Serialize, Debug)]
+pub struct SkippedBook {
+    book_location: String,
+    reason: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrganizeReport {
+    dry_run: bool,
+    moves: Vec<PlannedMove>,
+    skipped: Vec<SkippedBook>,
+}
+
+/// Splits a template into its folders, each made of text and fields
+/// The file name gets ".{ext}" added when the template leaves it out
+fn parse_template(template: &str) -> Result<Vec<Vec<TemplatePart>>, String> {
+    let mut segments = Vec::new();
+
+    for raw_segment in template.trim().replace('\\', "/").split('/') {
+        let mut parts = Vec::new();
+        let mut rest = raw_segment;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed {{ in the template \"{}\"", template))?;
+            let field = &rest[start + 1..start + end];
+            if !FIELDS.contains(&field) {
+                return Err(format!(
+                    "Unknown field {{{}}}, the template can use {}",
+                    field,
+                    FIELDS.map(|field| format!("{{{}}}", field)).join(", ")
+                ));
+            }
+
+            parts.push(TemplatePart::Field(field.to_string()));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+
+        if !parts.is_empty() {
+            segments.push(parts);
+        }
+    }
+
+    let file_name = segments
+        .last_mut()
+        .ok_or_else(|| "The template is empty".to_string())?;
+    if !file_name.contains(&TemplatePart::Field("ext".to_string())) {
+        file_name.push(TemplatePart::Text(".".to_string()));
+        file_name.push(TemplatePart::Field("ext".to_string()));
+    }
+
+    Ok(segments)
+}
+
+/// Series positions are padded so "02" sorts before "10", parts keep their fraction, "01.5"
+fn format_series_index(series_index: f64) -> String {
+    let index = series_index.to_string();
+    if index.split('.').next().map_or(0, str::len) < 2 {
+        format!("0{}", index)
+    } else {
+        index
+    }
+}
+
+/// The values a books fields are filled in with
+fn book_fields(book: &Book, author_sort: Option<&String>) -> HashMap<&'static str, String> {
+    let first_author = book
+        .get_authors()
+        .and_then(|authors| authors.split(" & ").next())
+        .and_then(normalize_author);
+
+    HashMap::from([
+        ("title", book.get_title().trim().to_string()),
+        (
+            "author",
+            first_author
+                .as_ref()
+                .map_or(UNKNOWN_AUTHOR.to_string(), |author| author.display.clone()),
+        ),
+        (
+            "author_sort",
+            author_sort
+                .cloned()
+                .or_else(|| first_author.map(|author| author.sort))
+                .unwrap_or(UNKNOWN_AUTHOR.to_string()),
+        ),
+        (
+            "series",
+            book.get_series().unwrap_or_default().trim().to_string(),
+        ),
+        (
+            "series_index",
+            book.get_series()
+                .and(book.get_series_index())
+                .map(format_series_index)
+                .unwrap_or_default(),
+        ),
+        (
+            "ext",
+            Path::new(book.get_book_location())
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        ),
+    ])
+}
+
+/// Fills in a folder or file name. When a field is empty the text after it goes too, so
+/// "{series_index} - {title}" becomes "Dune" rather than " - Dune" for books outside a series
+fn render_segment(parts: &[TemplatePart], fields: &HashMap<&'static str, String>) -> String {
+    let mut rendered = String::new();
+    let mut after_empty_field = false;
+
+    for part in parts {
+        match part {
+            TemplatePart::Text(text) => {
+                if !after_empty_field {
+                    rendered.push_str(text);
+                }
+            }
+            TemplatePart::Field(field) => {
+                let value = fields.get(field.as_str()).map_or("", String::as_str);
+                after_empty_field = value.is_empty();
+                rendered.push_str(value);
+            }
+        }
+    }
+
+    rendered.trim().to_string()
+}
+
+/// Shortens a name to fit the segment limit, file names keep their extension
+fn truncate_segment(segment: &str, is_file_name: bool) -> String {
+    if segment.chars().count() <= MAX_SEGMENT_CHARS {
+        return segment.to_string();
+    }
+
+    let (stem, extension) = match segment.rsplit_once('.') {
+        Some((stem, extension)) if is_file_name => (stem, format!(".{}", extension)),
+        _ => (segment, String::new()),
+    };
+    let stem_chars = MAX_SEGMENT_CHARS.saturating_sub(extension.chars().count());
+
+    format!(
+        "{}{}",
+        stem.chars().take(stem_chars).collect::<String>().trim_end(),
+        extension
+    )
+}
+
+/// Builds where a book belongs under the library folder, none when every part of the template came out empty
+fn book_target(
+    segments: &[Vec<TemplatePart>],
+    fields: &HashMap<&'static str, String>,
+    library_dir: &Path,
+) -> Option<PathBuf> {
+    let mut target = library_dir.to_path_buf();
+    let mut file_name = None;
+
+    for (position, segment) in segments.iter().enumerate() {
+        let is_file_name = position + 1 == segments.len();
+        let rendered = Book::sanitize_windows_filename(truncate_segment(
+            &render_segment(segment, fields),
+            is_file_name,
+        ));
+
+        // Folders that came out empty are left out, "." and ".." end up empty after sanitizing
+        if rendered.is_empty() || (is_file_name && rendered.starts_with('.')) {
+            continue;
+        }
+        if is_file_name {
+            file_name = Some(rendered);
+        } else {
+            target.push(rendered);
+        }
+    }
+
+    Some(target.join(file_name?))
+}
+
+/// Adds " (2)", " (3)"... to a file name until it no longer collides with anything
+pub(crate) fn deduplicate_target(
+    target: PathBuf,
+    source: &Path,
+    taken: &HashSet<String>,
+) -> PathBuf {
+    // Compared without case since Windows and macOS treat "Dune.epub" and "dune.epub" as the same file
+    let is_free = |path: &Path| {
+        let key = path.to_string_lossy().to_lowercase();
+        let is_source = key == source.to_string_lossy().to_lowercase();
+
+        !taken.contains(&key) && (is_source || !path.exists())
+    };
+
+    if is_free(&target) {
+        return target;
+    }
+
+    let stem = target
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = target
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|copy| target.with_file_name(format!("{} ({}){}", stem, copy, extension)))
+        .find(|candidate| is_free(candidate))
+        .expect("there's always a free copy number")
+}
+
+/// Works out where every book goes without touching anything
+fn plan_moves(
+    books: &[Book],
+    author_sorts: &HashMap<i64, String>,
+    segments: &[Vec<TemplatePart>],
+    library_dir: &Path,
+) -> (Vec<PlannedMove>, Vec<SkippedBook>) {
+    let mut moves = Vec::new();
+    let mut skipped = Vec::new();
+    let mut taken = HashSet::new();
+
+    for book in books {
+        let source = Path::new(book.get_book_location());
+        let skip_reason = if !source.starts_with(library_dir) {
+            Some("It's outside the library folder")
+        } else if !source.is_file() {
+            Some("The file is missing")
+        } else {
+            None
+        };
+        if let Some(reason) = skip_reason {
+            skipped.push(SkippedBook {
+                book_location: book.get_book_location().clone(),
+                reason: reason.to_string(),
+            });
+            continue;
+        }
+
+        let author_sort = book.get_id().and_then(|id| author_sorts.get(&id));
+        let Some(target) = book_target(segments, &book_fields(book, author_sort), library_dir)
+        else {
+            skipped.push(SkippedBook {
+                book_location: book.get_book_location().clone(),
+                reason: "The template gave it an empty file name".to_string(),
+            });
+            continue;
+        };
+
+        let target = deduplicate_target(target, source, &taken);
+        taken.insert(target.to_string_lossy().to_lowercase());
+
+        if target != source {
+            moves.push(PlannedMove {
+                title: book.get_title().clone(),
+                from: book.get_book_location().clone(),
+                to: target.to_string_lossy().replace('\\', "/"),
+            });
+        }
+    }
+
+    (moves, skipped)
+}
+
+/// Removes the folders a move left empty, stopping at the library folder
+fn remove_empty_parents(path: &Path, library_dir: &Path) {
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == library_dir || !dir.starts_with(library_dir) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+}
+
+/// Points the moved books at their new files, all of them or none
+async fn update_book_locations(moves: &[PlannedMove]) -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+    for planned_move in moves {
+        sqlx::query("UPDATE books SET book_location = $1 WHERE book_location = $2")
+            .bind(&planned_move.to)
+            .bind(&planned_move.from)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Renames and moves the books in the library folder to match a path template. The template is relative to the
+/// library folder and can use {title}, {author}, {author_sort}, {series}, {series_index} and {ext}.
+/// Files that would land on the same name get a number added, books outside the library folder are left alone.
+/// The database is only updated once every file has moved, if that fails the files are moved back
+///
+/// # Arguments
+///
+/// * `template` - Such as "{author_sort}/{series}/{series_index} - {title}.{ext}", defaults to the organize_template setting
+/// * `dry_run` - Only reports what would move
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn organize_library(
+    template: Option<String>,
+    dry_run: bool,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<OrganizeReport, ShelfError> {
+    let (library_dir, saved_template) = {
+        let book_worker = state.lock().unwrap();
+        let settings = book_worker.get_application_settings();
+
+        (
+            settings.get("book_location").cloned(),
+            settings.get("organize_template").cloned(),
+        )
+    };
+    let library_dir = library_dir
+        .filter(|dir| Path::new(dir).is_dir())
+        .ok_or_else(|| ShelfError::settings("library folder", "isn't set or doesn't exist"))?;
+    let library_dir = Path::new(&library_dir);
+
+    let template = template
+        .or(saved_template)
+        .or_else(|| {
+            shelf_settings_values()
+                .remove("ORGANIZE_TEMPLATE")
+                .map(|(_, default)| default)
+        })
+        .unwrap_or_default();
+    let segments = parse_template(&template).map_err(ShelfError::invalid)?;
+
+    let books = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load books", err))?;
+    let author_sorts: HashMap<i64, String> = sqlx::query_as::<_, (i64, String)>(
+        "SELECT ba.book_id, min(a.sort_name) FROM book_authors ba
+         JOIN authors a ON a.id = ba.author_id
+         WHERE ba.role = 'aut' GROUP BY ba.book_id",
+    )
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load authors", err))?
+    .into_iter()
+    .collect();
+
+    let (planned, mut skipped) = plan_moves(&books, &author_sorts, &segments, library_dir);
+    if dry_run {
+        return Ok(OrganizeReport {
+            dry_run,
+            moves: planned,
+            skipped,
+        });
+    }
+
+    let mut moves = Vec::new();
+    for planned_move in planned {
+        let target = Path::new(&planned_move.to);
+        let moved = target
+            .parent()
+            .map_or(Ok(()), create_dir_all)
+            .and_then(|_| move_file(Path::new(&planned_move.from), target));
+
+        match moved {
+            Ok(()) => moves.push(planned_move),
+            Err(err) => skipped.push(SkippedBook {
+                book_location: planned_move.from,
+                reason: format!("Failed to move it: {}", err),
+            }),
+        }
+    }
+
+    let updated = update_book_locations(&moves).await;
+
+    if let Err(err) = updated {
+        for planned_move in &moves {
+            if let Err(move_err) =
+                move_file(Path::new(&planned_move.to), Path::new(&planned_move.from))
+            {
+                error!(
+                    "Failed to move {} back to {}: {}",
+                    planned_move.to, planned_move.from, move_err
+                );
+            }
+            remove_empty_parents(Path::new(&planned_move.to), library_dir);
+        }
+
+        return Err(ShelfError::database("update the book locations", err));
+    }
+
+    let mut book_worker = state.lock().unwrap();
+    for planned_move in &moves {
+        book_worker.move_book(&planned_move.from, planned_move.to.clone());
+        remove_empty_parents(Path::new(&planned_move.from), library_dir);
+    }
+
+    Ok(OrganizeReport {
+        dry_run,
+        moves,
+        skipped,
+    })
+}
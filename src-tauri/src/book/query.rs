@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+use crate::{
+    book::{status::ReadStatus, util::escape_like},
+    book_item::Book,
+    database::get_db,
+    error::ShelfError,
+};
+
+static DEFAULT_PAGE_SIZE: i64 = 50;
+static MAX_PAGE_SIZE: i64 = 500;
+
+// Leading articles are ignored so "The Hobbit" sorts under H
+static SORT_TITLE: &str = "(CASE
+    WHEN lower(b.title) LIKE 'the %' THEN substr(b.title, 5)
+    WHEN lower(b.title) LIKE 'an %' THEN substr(b.title, 4)
+    WHEN lower(b.title) LIKE 'a %' THEN substr(b.title, 3)
+    ELSE b.title END) COLLATE NOCASE";
+
+// The first credited author by surname, books that haven't been indexed fall back to the authors field
+static SORT_AUTHOR: &str = "coalesce(
+    (SELECT min(a.sort_name) FROM book_authors ba JOIN authors a ON a.id = ba.author_id
+     WHERE ba.book_id = b.id AND ba.role = 'aut'),
+    b.authors) COLLATE NOCASE";
+
+/// What the books can be ordered by
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Title,
+    Author,
+    DateAdded,
+    LastRead,
+    Progress,
+    /// The word count
+    Length,
+}
+
+impl SortKey {
+    fn expression(&self) -> &'static str {
+        match self {
+            SortKey::Title => SORT_TITLE,
+            SortKey::Author => SORT_AUTHOR,
+            SortKey::DateAdded => "b.date_added",
+            SortKey::LastRead => "b.last_read",
+            SortKey::Progress => "b.progress",
+            SortKey::Length => "b.word_count",
+        }
+    }
+}
+
+/// Filters, sorting and the page to fetch. Every filter is optional and they are combined with AND
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct BookQuery {
+    /// File extension, "epub"
+    format: Option<String>,
+    /// Part of an authors name
+    author: Option<String>,
+    author_id: Option<i64>,
+    tag: Option<String>,
+    /// The series (epub collection) a book belongs to
+    collection: Option<String>,
+    read_status: Option<ReadStatus>,
+    /// 0 to 5, unrated books are left out
+    min_rating: Option<i64>,
+    /// Dates as YYYY-MM-DD, after is inclusive and before is exclusive
+    added_after: Option<String>,
+    added_before: Option<String>,
+    finished_after: Option<String>,
+    finished_before: Option<String>,
+    /// Word counts, both inclusive. Books that weren't counted yet are left out
+    min_words: Option<i64>,
+    max_words: Option<i64>,
+    sort: SortKey,
+    descending: bool,
+    /// Pages start at 0
+    page: i64,
+    page_size: Option<i64>,
+}
+
+/// A page of results along with the total so the frontend can size its pagination
+#[derive(Serialize, Debug)]
+pub struct BookPage {
+    books: Vec<Book>,
+    total: i64,
+    page: i64,
+    page_size: i64,
+}
+
+/// Appends the WHERE clause for the queries filters, shared by the count and the page query
+fn push_filters<'a>(builder: &mut QueryBuilder<'a, Sqlite>, query: &'a BookQuery) {
+    builder.push(" WHERE 1 = 1");
+
+    if let Some(format) = &query.format {
+        builder
+            .push(" AND lower(b.book_location) LIKE '%.' || lower(")
+            .push_bind(format.trim_start_matches('.'))
+            .push(")");
+    }
+    if let Some(author) = &query.author {
+        builder
+            .push(" AND b.authors LIKE '%' || ")
+            .push_bind(escape_like(author))
+            .push(" || '%' ESCAPE '\\'");
+    }
+    if let Some(author_id) = query.author_id {
+        builder
+            .push(" AND EXISTS (SELECT 1 FROM book_authors ba WHERE ba.book_id = b.id AND ba.author_id = ")
+            .push_bind(author_id)
+            .push(")");
+    }
+    if let Some(tag) = &query.tag {
+        builder
+            .push(" AND EXISTS (SELECT 1 FROM book_tags bt WHERE bt.book_id = b.id AND bt.tag = ")
+            .push_bind(tag)
+            .push(" COLLATE NOCASE)");
+    }
+    if let Some(collection) = &query.collection {
+        builder
+            .push(" AND b.series = ")
+            .push_bind(collection)
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(read_status) = query.read_status {
+        builder
+            .push(" AND b.read_status = ")
+            .push_bind(read_status.as_str());
+    }
+    if let Some(min_rating) = query.min_rating {
+        builder.push(" AND b.rating >= ").push_bind(min_rating);
+    }
+    if let Some(added_after) = &query.added_after {
+        builder.push(" AND b.date_added >= ").push_bind(added_after);
+    }
+    if let Some(added_before) = &query.added_before {
+        builder.push(" AND b.date_added < ").push_bind(added_before);
+    }
+    if let Some(finished_after) = &query.finished_after {
+        builder
+            .push(" AND b.date_finished >= ")
+            .push_bind(finished_after);
+    }
+    if let Some(finished_before) = &query.finished_before {
+        builder
+            .push(" AND b.date_finished < ")
+            .push_bind(finished_before);
+    }
+    if let Some(min_words) = query.min_words {
+        builder.push(" AND b.word_count >= ").push_bind(min_words);
+    }
+    if let Some(max_words) = query.max_words {
+        builder.push(" AND b.word_count <= ").push_bind(max_words);
+    }
+}
+
+/// Runs a query against the books table
+///
+/// # Arguments
+///
+/// * `query` - The filters, sort and page to fetch
+///
+pub async fn run_book_query(query: &BookQuery) -> Result<BookPage, sqlx::Error> {
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    // Past this the offset would overflow, there are no books that far in anyway
+    let page = query.page.clamp(0, i64::MAX / page_size);
+
+    let mut count_builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT COUNT(*) FROM books b");
+    push_filters(&mut count_builder, query);
+    let total: i64 = count_builder
+        .build_query_scalar()
+        .fetch_one(get_db())
+        .await?;
+
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT b.* FROM books b");
+    push_filters(&mut builder, query);
+
+    // Books missing the sort value go last either way, ties fall back to the title
+    let direction = if query.descending { "DESC" } else { "ASC" };
+    let sort = query.sort.expression();
+    builder.push(format!(
+        " ORDER BY {sort} IS NULL, {sort} {direction}, {SORT_TITLE}, b.id"
+    ));
+    builder.push(" LIMIT ").push_bind(page_size);
+    builder.push(" OFFSET ").push_bind(page * page_size);
+
+    let books = builder.build_query_as::<Book>().fetch_all(get_db()).await?;
+
+    Ok(BookPage {
+        books,
+        total,
+        page,
+        page_size,
+    })
+}
+
+/// Fetches a filtered, sorted page of the library for the dashboard
+///
+/// # Arguments
+///
+/// * `query` - The filters, sort key and page, every field is optional
+///
+#[tauri::command]
+pub async fn query_books(query: BookQuery) -> Result<BookPage, ShelfError> {
+    run_book_query(&query)
+        .await
+        .map_err(|err| ShelfError::database("query books", err))
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+use tauri::{Manager, State, Window};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    authors::index_unindexed_books,
+    book::{
+        bookio::create_book_vec,
+        import_errors::{clear_import_errors, get_ignored_locations, record_import_errors},
+        length::count_uncounted_books,
+        trash::get_removed_locations,
+        util::find_epub_paths,
+    },
+    book_item::Book,
+    book_worker::{add_new_books, BookWorker},
+};
+
+static PROGRESS_EVENT: &str = "scan-progress";
+static FINISHED_EVENT: &str = "scan-finished";
+// Books are added a batch at a time, small enough that cancelling is quick and the shelf fills in as it goes
+static BATCH_SIZE: usize = 16;
+
+/// Keeps track of the scan running in the background, there's only ever one
+#[derive(Default)]
+pub struct LibraryScanner {
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl LibraryScanner {
+    pub fn is_running(&self) -> bool {
+        self.cancel.is_some()
+    }
+}
+
+/// Marks the scan as stopped once it's dropped, a scan that panics would otherwise block every scan after it
+struct RunningScan {
+    window: Window,
+}
+
+impl Drop for RunningScan {
+    fn drop(&mut self) {
+        if let Ok(mut scanner) = self.window.state::<Mutex<LibraryScanner>>().lock() {
+            scanner.cancel = None;
+        }
+    }
+}
+
+/// Sent after every batch of files
+#[derive(Serialize, Debug, Clone)]
+pub struct ScanProgress {
+    /// Epubs found that aren't in the library yet
+    found: usize,
+    processed: usize,
+    failed: usize,
+    /// Seconds left, none until the first batch is done
+    eta_seconds: Option<u64>,
+}
+
+/// Sent once the scan stops, whether it finished or was cancelled
+#[derive(Serialize, Debug, Clone)]
+pub struct ScanFinished {
+    found: usize,
+    processed: usize,
+    added: usize,
+    failed: usize,
+    cancelled: bool,
+}
+
+/// Epubs in the library folder that aren't on the shelf yet
+/// Books removed from the library without deleting the file stay out until they're restored,
+/// files on the import error ignore list stay out until they're taken off it
+async fn find_new_books(library_dir: String, known_locations: HashSet<String>) -> Vec<String> {
+    let mut removed_locations = get_removed_locations().await.unwrap_or_default();
+    removed_locations.extend(get_ignored_locations().await.unwrap_or_default());
+
+    // Walking a big library folder takes a moment
+    tauri::async_runtime::spawn_blocking(move || {
+        find_epub_paths(Path::new(&library_dir))
+            .into_iter()
+            .filter(|path| {
+                let normalized = path.replace('\\', "/");
+                !known_locations.contains(&normalized) && !removed_locations.contains(&normalized)
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Reads new books in batches, the worker is only locked while a finished batch is added to the cache
+async fn run_scan(window: &Window, cancel: &AtomicBool) -> ScanFinished {
+    let worker = window.state::<Mutex<BookWorker>>();
+    let (library_dir, known_locations) = {
+        let book_worker = worker.lock().unwrap();
+        let cache = book_worker.get_book_cache();
+
+        (
+            book_worker
+                .get_application_settings()
+                .get("book_location")
+                .cloned(),
+            cache
+                .get_books()
+                .map(|books| {
+                    books
+                        .iter()
+                        .map(|book| book.get_book_location().clone())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        )
+    };
+
+    let new_paths = match library_dir {
+        Some(dir) => find_new_books(dir, known_locations).await,
+        None => Vec::new(),
+    };
+    let found = new_paths.len();
+    info!(found, "Scanning for new books");
+    let started = Instant::now();
+    let mut processed = 0;
+    let mut added = 0;
+    let mut failed = 0;
+    let mut cancelled = false;
+
+    for batch in new_paths.chunks(BATCH_SIZE) {
+        if cancel.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+
+        let paths = batch.to_vec();
+        let (books, failures) =
+            tauri::async_runtime::spawn_blocking(move || create_book_vec(&paths))
+                .await
+                .unwrap_or_default();
+        processed += batch.len();
+        failed += batch.len() - books.len();
+        if let Err(err) = record_import_errors(&failures).await {
+            warn!("Failed to record import errors: {}", err);
+        }
+        if !books.is_empty() {
+            // Files that failed on an earlier scan and read fine now
+            let locations: Vec<String> = books
+                .iter()
+                .map(|book| book.get_book_location().clone())
+                .collect();
+            if let Err(err) = clear_import_errors(&locations).await {
+                warn!("Failed to clear import errors: {}", err);
+            }
+
+            match add_new_books(&worker, books).await {
+                Ok(batch_added) => added += batch_added,
+                Err(err) => error!("Failed to add scanned books: {}", err),
+            }
+        }
+
+        let per_file = started.elapsed().as_secs_f64() / processed as f64;
+        let progress = ScanProgress {
+            found,
+            processed,
+            failed,
+            eta_seconds: Some((per_file * (found - processed) as f64).round() as u64),
+        };
+        if let Err(err) = window.emit(PROGRESS_EVENT, progress) {
+            warn!("Failed to send scan progress: {}", err);
+        }
+    }
+
+    // Books added before authors and word counts were kept get indexed and counted once the new ones are in
+    if !cancelled {
+        if let Err(err) = index_unindexed_books().await {
+            warn!("Failed to index authors: {}", err);
+        }
+        match count_uncounted_books().await {
+            Ok(counted) => {
+                let mut book_worker = worker.lock().unwrap();
+                for book in counted {
+                    book_worker.replace_book(book);
+                }
+            }
+            Err(err) => warn!("Failed to count words: {}", err),
+        }
+    }
+
+    ScanFinished {
+        found,
+        processed,
+        added,
+        failed,
+        cancelled,
+    }
+}
+
+/// Starts scanning the library folder in the background, nothing happens if a scan is already running
+/// Progress comes through scan-progress events and scan-finished is sent at the end
+///
+/// # Arguments
+///
+/// * `window` - The window the events are sent to
+///
+pub fn start_scan(window: Window) -> bool {
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let scanner = window.state::<Mutex<LibraryScanner>>();
+        let mut scanner = scanner.lock().unwrap();
+        if scanner.is_running() {
+            return false;
+        }
+        scanner.cancel = Some(cancel.clone());
+    }
+
+    let running = RunningScan {
+        window: window.clone(),
+    };
+    tauri::async_runtime::spawn(
+        async move {
+            let finished = run_scan(&window, &cancel).await;
+            info!(?finished, "Scan finished");
+
+            drop(running);
+            if let Err(err) = window.emit(FINISHED_EVENT, finished) {
+                warn!("Failed to send the scan result: {}", err);
+            }
+        }
+        .instrument(info_span!("library_scan")),
+    );
+
+    true
+}
+
+/// Scans the library folder for new books in the background, returns false if a scan was already running
+#[tauri::command]
+pub fn start_library_scan(window: Window) -> bool {
+    start_scan(window)
+}
+
+/// Stops the running scan after the batch it's working on, returns false if nothing was running
+#[tauri::command]
+pub fn cancel_library_scan(state: State<'_, Mutex<LibraryScanner>>) -> bool {
+    match &state.lock().unwrap().cancel {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The books currently on the shelf, used to refresh the dashboard after a scan
+#[tauri::command]
+pub fn get_library_books(state: State<'_, Mutex<BookWorker>>) -> Option<Vec<Book>> {
+    state.lock().unwrap().get_book_cache().get_books().cloned()
+}
@@ -0,0 +1,379 @@
+use serde::Serialize;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{
+    book_item::{get_all_books, Book},
+    error::ShelfError,
+};
+
+static DEFAULT_RESULT_LIMIT: usize = 50;
+
+// How much a match in each field counts towards the score
+static TITLE_WEIGHT: f64 = 1.0;
+static SERIES_WEIGHT: f64 = 0.8;
+static AUTHORS_WEIGHT: f64 = 0.7;
+
+/// A book that matched a search and how well it matched
+#[derive(Serialize, Debug)]
+pub struct SearchResult {
+    book: Book,
+    score: f64,
+}
+
+/// Letters that don't decompose into a base letter and a combining mark
+fn fold_special_letter(letter: char) -> Option<&'static str> {
+    match letter {
+        'ß' => Some("ss"),
+        'æ' => Some("ae"),
+        'œ' => Some("oe"),
+        'ø' => Some("o"),
+        'ł' => Some("l"),
+        'đ' | 'ð' => Some("d"),
+        'þ' => Some("th"),
+        'ı' => Some("i"),
+        _ => None,
+    }
+}
+
+/// Whether the accents on a letter can be dropped. Only Latin, Greek and Cyrillic, in other scripts the marks
+/// are vowels or change the letter, ガ isn't カ
+fn drops_accents(letter: char) -> bool {
+    matches!(letter,
+        '\u{0000}'..='\u{024F}' // Latin
+        | '\u{1E00}'..='\u{1EFF}' // Latin extended additional
+        | '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' // Greek
+        | '\u{0400}'..='\u{052F}' // Cyrillic
+    )
+}
+
+/// Folds text down to what searches are matched on, "Crème Brûlée!" becomes "creme brulee"
+/// Accents on Latin, Greek and Cyrillic letters and case are dropped and anything that isn't a letter or number
+/// separates words
+///
+/// # Arguments
+///
+/// * `text` - The text to fold
+///
+pub fn normalize_text(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    let mut drop_marks = false;
+
+    for letter in text.nfkd().flat_map(char::to_lowercase) {
+        if is_combining_mark(letter) {
+            if !drop_marks {
+                folded.push(letter);
+            }
+            continue;
+        }
+        drop_marks = drops_accents(letter);
+
+        if let Some(replacement) = fold_special_letter(letter) {
+            folded.push_str(replacement);
+        } else if letter.is_alphanumeric() {
+            folded.push(letter);
+        } else {
+            folded.push(' ');
+        }
+    }
+
+    // The marks that were kept go back onto their letters
+    folded
+        .nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Edit distance counting a swap of two neighbouring letters as one edit, so "tolkein" is one away from "tolkien"
+pub(crate) fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        distances[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// How many typos a word of this length is allowed, short words have to match exactly
+fn allowed_typos(word_length: usize) -> usize {
+    match word_length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Scores a single query word against the words of a field, 1 for an exact word down to 0 for no match
+fn word_score(query_word: &str, field_words: &[&str]) -> f64 {
+    let query_letters: Vec<char> = query_word.chars().collect();
+    let typos = allowed_typos(query_letters.len());
+
+    field_words
+        .iter()
+        .map(|field_word| {
+            if *field_word == query_word {
+                return 1.0;
+            }
+            if field_word.starts_with(query_word) {
+                return 0.8;
+            }
+
+            let field_letters: Vec<char> = field_word.chars().collect();
+            // A word being typed might be a typo away from the start of a longer word
+            let field_prefix = &field_letters[..field_letters.len().min(query_letters.len())];
+
+            if typos > 0 && edit_distance(&query_letters, &field_letters) <= typos {
+                0.6
+            } else if typos > 0 && edit_distance(&query_letters, field_prefix) <= typos {
+                0.5
+            } else if query_letters.len() >= 3 && field_word.contains(query_word) {
+                0.4
+            } else {
+                0.0
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Scores a normalized query against a normalized field, 0 when they don't match
+fn field_score(query: &str, field: &str) -> f64 {
+    if query.is_empty() || field.is_empty() {
+        return 0.0;
+    }
+    if field == query {
+        return 100.0;
+    }
+    if field.starts_with(query) {
+        return 90.0;
+    }
+    if field.contains(query) {
+        return 75.0;
+    }
+
+    // Every word has to match something, otherwise "lord rings" would match any book with "lord" in it
+    let field_words: Vec<&str> = field.split(' ').collect();
+    let mut total = 0.0;
+    let mut query_word_count = 0;
+    for query_word in query.split(' ') {
+        let score = word_score(query_word, &field_words);
+        if score == 0.0 {
+            return 0.0;
+        }
+        total += score;
+        query_word_count += 1;
+    }
+
+    60.0 * total / query_word_count as f64
+}
+
+/// Scores a book against a normalized query, the best matching field wins
+fn book_score(query: &str, book: &Book) -> f64 {
+    [
+        (Some(book.get_title().as_str()), TITLE_WEIGHT),
+        (book.get_series(), SERIES_WEIGHT),
+        (book.get_authors(), AUTHORS_WEIGHT),
+    ]
+    .iter()
+    .filter_map(|(field, weight)| Some(field_score(query, &normalize_text((*field)?)) * weight))
+    .fold(0.0, f64::max)
+}
+
+/// Ranks books against a search, best matches first. Books that don't match are left out
+///
+/// # Arguments
+///
+/// * `books` - The books to search through
+/// * `query` - What the user typed
+///
+pub fn rank_books(books: Vec<Book>, query: &str) -> Vec<SearchResult> {
+    let query = normalize_text(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results: Vec<SearchResult> = books
+        .into_iter()
+        .filter_map(|book| {
+            let score = book_score(&query, &book);
+            (score > 0.0).then_some(SearchResult { book, score })
+        })
+        .collect();
+
+    results.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.book.get_title().cmp(b.book.get_title()))
+    });
+
+    results
+}
+
+/// Searches titles, authors and series ignoring case and accents, with some room for typos
+///
+/// # Arguments
+///
+/// * `query` - What the user typed
+/// * `limit` - The most results to return, defaults to 50
+///
+#[tauri::command]
+pub async fn search_books(
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchResult>, ShelfError> {
+    let books = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load books", err))?;
+
+    let mut results = rank_books(books, &query);
+    results.truncate(limit.unwrap_or(DEFAULT_RESULT_LIMIT));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, authors: Option<&str>) -> Book {
+        Book::new(
+            Some("cover.jpg".to_string()),
+            format!("/books/{}.epub", title),
+            title.to_string(),
+        )
+        .with_authors(authors.map(str::to_string))
+    }
+
+    fn titles(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|result| result.book.get_title().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn normalize_text_drops_accents_case_and_punctuation() {
+        assert_eq!(normalize_text("Crème Brûlée!"), "creme brulee");
+        assert_eq!(
+            normalize_text("  Straße  der Ænigma "),
+            "strasse der aenigma"
+        );
+        assert_eq!(normalize_text("ＦＵＬＬ width"), "full width");
+        assert_eq!(normalize_text(""), "");
+    }
+
+    #[test]
+    fn normalize_text_keeps_the_marks_of_other_scripts() {
+        assert_eq!(normalize_text("Ελληνικά Война"), "ελληνικα воина");
+        assert_eq!(normalize_text("ガラス"), "ガラス");
+        assert_eq!(normalize_text("हिन्दी"), "हिन्दी");
+        assert_eq!(normalize_text("ภาษาไทย"), "ภาษาไทย");
+    }
+
+    #[test]
+    fn decomposed_and_composed_titles_match() {
+        // "é" written as e + combining acute accent
+        let books = vec![book("Les Mise\u{301}rables", Some("Victor Hugo"))];
+
+        assert_eq!(
+            titles(&rank_books(books, "misérables")),
+            ["Les Mise\u{301}rables"]
+        );
+    }
+
+    #[test]
+    fn accent_insensitive_both_ways() {
+        let books = vec![
+            book("Gödel, Escher, Bach", Some("Douglas Hofstadter")),
+            book("Cien años de soledad", Some("Gabriel García Márquez")),
+        ];
+
+        assert_eq!(
+            titles(&rank_books(books.clone(), "godel")),
+            ["Gödel, Escher, Bach"]
+        );
+        assert_eq!(
+            titles(&rank_books(books.clone(), "AÑOS")),
+            ["Cien años de soledad"]
+        );
+        assert_eq!(
+            titles(&rank_books(books, "garcia marquez")),
+            ["Cien años de soledad"]
+        );
+    }
+
+    #[test]
+    fn non_latin_titles_match() {
+        let books = vec![
+            book("ノルウェイの森", Some("村上春樹")),
+            book("Преступление и наказание", Some("Фёдор Достоевский")),
+        ];
+
+        assert_eq!(
+            titles(&rank_books(books.clone(), "ノルウェイ")),
+            ["ノルウェイの森"]
+        );
+        assert_eq!(
+            titles(&rank_books(books.clone(), "преступление")),
+            ["Преступление и наказание"]
+        );
+        // ё folds to е
+        assert_eq!(
+            titles(&rank_books(books, "федор")),
+            ["Преступление и наказание"]
+        );
+    }
+
+    #[test]
+    fn typos_are_tolerated() {
+        let books = vec![
+            book("The Hobbit", Some("J. R. R. Tolkien")),
+            book("Dune", Some("Frank Herbert")),
+        ];
+
+        assert_eq!(
+            titles(&rank_books(books.clone(), "tolkein")),
+            ["The Hobbit"]
+        );
+        assert_eq!(titles(&rank_books(books.clone(), "hobit")), ["The Hobbit"]);
+        assert_eq!(titles(&rank_books(books.clone(), "dume")), ["Dune"]);
+        // Short words have to be exact
+        assert!(rank_books(books, "dub").is_empty());
+    }
+
+    #[test]
+    fn closer_matches_rank_first() {
+        let books = vec![
+            book("Brave New World", None),
+            book("New World Order", None),
+            book("World", None),
+        ];
+
+        assert_eq!(
+            titles(&rank_books(books, "world")),
+            ["World", "Brave New World", "New World Order"]
+        );
+    }
+
+    #[test]
+    fn empty_queries_return_nothing() {
+        assert!(rank_books(vec![book("Dune", None)], " !? ").is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+use epub::doc::EpubDoc;
+use regex::Regex;
+use serde::Serialize;
+use tauri::State;
+use xmltree::Element;
+
+use crate::{
+    book_item::{get_all_books, update_book_series_batch, Book},
+    book_worker::BookWorker,
+    error::ShelfError,
+};
+
+// Checked in order, the stricter patterns go first so "Title (Series, #2)" isn't read as "Title (Series, #" book 2
+static FILENAME_SERIES_PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+
+fn filename_series_patterns() -> &'static Vec<Regex> {
+    FILENAME_SERIES_PATTERNS.get_or_init(|| {
+    [
+        // The Fellowship of the Ring (The Lord of the Rings, #1)
+        r"(?i)^.+?\s*\((?P<series>[^()]+?),?\s*(?:#|book|vol\.?|volume|part)\s*(?P<index>\d+(?:\.\d+)?)\)$",
+        // [Discworld 03] Equal Rites
+        r"(?i)^\[(?P<series>[^\]]+?)\s*(?:#|book|vol\.?|volume|part)?\s*(?P<index>\d+(?:\.\d+)?)\]\s*.+$",
+        // Discworld #3 - Equal Rites, Discworld Book 3 - Equal Rites
+        r"(?i)^(?P<series>.+?)\s*(?:#|book|vol\.?|volume|part)\s*(?P<index>\d+(?:\.\d+)?)\s+-\s+.+$",
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect()
+    })
+}
+
+/// A series and its books in reading order
+#[derive(Serialize, Debug)]
+pub struct BookSeries {
+    name: String,
+    books: Vec<Book>,
+}
+
+fn parse_index(index: Option<&String>) -> Option<f64> {
+    index.and_then(|index| index.trim().parse::<f64>().ok())
+}
+
+/// Reads the series calibre writes into the opf, <meta name="calibre:series" content="..."/>
+fn calibre_series<R: Read + Seek>(ebook: &EpubDoc<R>) -> Option<(String, Option<f64>)> {
+    let series = ebook.mdata("calibre:series")?;
+    let series_index = parse_index(
+        ebook
+            .metadata
+            .get("calibre:series_index")
+            .and_then(|index| index.first()),
+    );
+
+    Some((series, series_index))
+}
+
+fn meta_property(meta: &Element) -> Option<&str> {
+    meta.attributes.get("property").map(String::as_str)
+}
+
+/// Reads an epub3 series collection. The epub crate drops the refines attribute, so the package document is
+/// read again to tie the collection-type and group-position metas to their collection by id
+fn epub3_collection<R: Read + Seek>(ebook: &mut EpubDoc<R>) -> Option<(String, Option<f64>)> {
+    // Most books have no collection, those don't need the package document parsed again
+    ebook.metadata.get("belongs-to-collection")?;
+
+    let root_file = ebook.root_file.clone();
+    let package = Element::parse(ebook.get_resource_by_path(root_file)?.as_slice()).ok()?;
+    let metas: Vec<&Element> = package
+        .get_child("metadata")?
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .filter(|child| child.name == "meta")
+        .collect();
+    let refinement = |id: &str, refined_property: &str| {
+        let refines = format!("#{}", id);
+        metas
+            .iter()
+            .find(|meta| {
+                meta.attributes.get("refines") == Some(&refines)
+                    && meta_property(meta) == Some(refined_property)
+            })
+            .and_then(|meta| meta.get_text())
+            .map(|text| text.trim().to_string())
+    };
+
+    metas
+        .iter()
+        .filter(|meta| meta_property(meta) == Some("belongs-to-collection"))
+        .find_map(|collection| {
+            let id = collection.attributes.get("id")?;
+            if refinement(id, "collection-type")? != "series" {
+                return None;
+            }
+
+            let series = collection.get_text()?.trim().to_string();
+            Some((
+                series,
+                parse_index(refinement(id, "group-position").as_ref()),
+            ))
+        })
+}
+
+/// Falls back to common naming schemes like "Series #3 - Title" when the epub doesn't say. The number needs
+/// a marker or brackets, otherwise titles like "Fahrenheit 451 - Ray Bradbury" would be read as a series
+///
+/// # Arguments
+///
+/// * `book_location` - The path of the book, only the file name is looked at
+///
+pub fn filename_series(book_location: &str) -> Option<(String, Option<f64>)> {
+    let file_stem = Path::new(book_location).file_stem()?.to_string_lossy();
+    let file_stem = file_stem.trim();
+
+    filename_series_patterns().iter().find_map(|pattern| {
+        let captures = pattern.captures(file_stem)?;
+        let series = captures["series"]
+            .trim()
+            .trim_end_matches([',', '-', '_'])
+            .trim();
+
+        (!series.is_empty()).then(|| (series.to_string(), captures["index"].parse::<f64>().ok()))
+    })
+}
+
+/// Works out which series a book belongs to and where it falls in it
+///
+/// # Arguments
+///
+/// * `ebook` - The opened epub
+/// * `book_location` - The path of the book, used when the epub has no series metadata
+///
+pub fn detect_series<R: Read + Seek>(
+    ebook: &mut EpubDoc<R>,
+    book_location: &str,
+) -> Option<(String, Option<f64>)> {
+    calibre_series(ebook)
+        .or_else(|| epub3_collection(ebook))
+        .filter(|(series, _)| !series.trim().is_empty())
+        .or_else(|| filename_series(book_location))
+}
+
+/// Groups books by series, series are sorted by name and their books by index then title
+///
+/// # Arguments
+///
+/// * `books` - The books to group, books without a series are left out
+///
+pub fn group_by_series(books: Vec<Book>) -> Vec<BookSeries> {
+    let mut grouped: BTreeMap<String, BookSeries> = BTreeMap::new();
+
+    for book in books {
+        let Some(series) = book.get_series().map(str::to_string) else {
+            continue;
+        };
+
+        grouped
+            .entry(series.to_lowercase())
+            .or_insert_with(|| BookSeries {
+                name: series,
+                books: Vec::new(),
+            })
+            .books
+            .push(book);
+    }
+
+    grouped
+        .into_values()
+        .map(|mut series| {
+            // Books missing an index go to the end of their series
+            series.books.sort_by(|a, b| {
+                let a_index = a.get_series_index().unwrap_or(f64::MAX);
+                let b_index = b.get_series_index().unwrap_or(f64::MAX);
+                a_index
+                    .total_cmp(&b_index)
+                    .then_with(|| a.get_title().cmp(b.get_title()))
+            });
+            series
+        })
+        .collect()
+}
+
+/// Returns every series in the library with its books in reading order
+#[tauri::command]
+pub async fn get_books_by_series() -> Result<Vec<BookSeries>, ShelfError> {
+    let books = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load books", err))?;
+
+    Ok(group_by_series(books))
+}
+
+/// Looks for series information in books that don't have any yet, for books added before series were tracked
+/// Returns the number of books that were updated
+#[tauri::command]
+pub async fn detect_library_series(
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<usize, ShelfError> {
+    let books = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load books", err))?;
+
+    // Opening every epub is slow, it's kept off the async runtime
+    let updated_books: Vec<Book> = tauri::async_runtime::spawn_blocking(move || {
+        books
+            .into_iter()
+            .filter(|book| book.get_series().is_none())
+            .filter_map(|book| {
+                let (series, series_index) = match EpubDoc::new(book.get_book_location()) {
+                    Ok(mut ebook) => detect_series(&mut ebook, book.get_book_location()),
+                    Err(_) => filename_series(book.get_book_location()),
+                }?;
+
+                Some(book.with_series(Some(series), series_index))
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("series detection", err))?;
+
+    if updated_books.is_empty() {
+        return Ok(0);
+    }
+
+    update_book_series_batch(&updated_books)
+        .await
+        .map_err(|err| ShelfError::database("save series", err))?;
+
+    let mut book_worker = state.lock().unwrap();
+    for book in &updated_books {
+        book_worker.replace_book(book.clone());
+    }
+
+    Ok(updated_books.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filename_series_needs_a_marker_or_brackets() {
+        assert_eq!(
+            filename_series("/books/Discworld #3 - Equal Rites.epub"),
+            Some(("Discworld".to_string(), Some(3.0)))
+        );
+        assert_eq!(
+            filename_series("/books/[Discworld 03] Equal Rites.epub"),
+            Some(("Discworld".to_string(), Some(3.0)))
+        );
+        assert_eq!(
+            filename_series("/books/The Fellowship of the Ring (The Lord of the Rings, #1).epub"),
+            Some(("The Lord of the Rings".to_string(), Some(1.0)))
+        );
+        assert_eq!(
+            filename_series("/books/Fahrenheit 451 - Ray Bradbury.epub"),
+            None
+        );
+    }
+}
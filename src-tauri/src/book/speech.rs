@@ -0,0 +1,406 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde::Serialize;
+use xmltree::{Element, XMLNode};
+use zip::ZipArchive;
+
+use crate::{
+    book::{
+        metadata::read_zip_entry, navigation::spine_documents, validate::replace_html_entities,
+    },
+    error::ShelfError,
+    xml::{is_block_element, is_hidden_element, parse_preserving_whitespace},
+};
+
+// Words ending in a full stop that rarely end a sentence, compared without case or the stop
+static ABBREVIATIONS: [&str; 24] = [
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "vs", "etc", "e.g", "i.e", "cf", "no",
+    "vol", "ch", "fig", "pp", "gen", "lt", "capt", "col", "rev",
+];
+// Closing quotes and brackets stay with the sentence they end
+static CLOSING_PUNCTUATION: [char; 8] = ['"', '\'', '”', '’', '»', ')', ']', '」'];
+// epub:type and role values of footnotes and the markers linking to them, neither is read aloud
+static NOTE_TYPES: [&str; 10] = [
+    "noteref",
+    "footnote",
+    "footnotes",
+    "endnote",
+    "endnotes",
+    "rearnote",
+    "doc-noteref",
+    "doc-footnote",
+    "doc-endnote",
+    "doc-endnotes",
+];
+
+/// A spot in the xhtml of a chapter
+#[derive(Serialize, Debug, Clone)]
+pub struct TextPosition {
+    /// Child element indices from the body down, text nodes and comments aren't counted
+    path: Vec<usize>,
+    /// Offset into the text content of the element at the path, in UTF-16 code units like javascript strings
+    offset: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Sentence {
+    text: String,
+    /// UTF-16 offsets into the chapter text, the end is exclusive
+    start: usize,
+    end: usize,
+    /// Where the sentence starts and ends in the xhtml, enough to build a DOM range
+    from: TextPosition,
+    to: TextPosition,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChapterText {
+    spine_index: usize,
+    /// The file inside the epub
+    href: String,
+    /// What's read aloud, paragraphs and headings are separated by newlines
+    text: String,
+    sentences: Vec<Sentence>,
+}
+
+/// A word of the chapter text and where it came from
+struct Segment {
+    /// UTF-16 offset into the chapter text
+    start: usize,
+    /// The element holding the word
+    path: Vec<usize>,
+    /// UTF-16 offset into the text content of that element
+    offset: usize,
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The length of an elements text content, skipped elements still take up room in the DOM
+fn text_len(element: &Element) -> usize {
+    element
+        .children
+        .iter()
+        .map(|child| match child {
+            XMLNode::Text(content) | XMLNode::CData(content) => utf16_len(content),
+            XMLNode::Element(child) => text_len(child),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Elements whose text isn't read: hidden ones, ruby annotations and footnotes.
+/// The base text of ruby is read, the reading in rt would repeat it
+fn is_skipped(element: &Element) -> bool {
+    let attribute = |name: &str| element.attributes.get(name).map(String::as_str);
+    let style = attribute("style")
+        .unwrap_or_default()
+        .to_lowercase()
+        .replace(' ', "");
+    // epub:type, xmltree keys attributes by their local name
+    let is_note = [attribute("type"), attribute("role")]
+        .into_iter()
+        .flatten()
+        .flat_map(str::split_whitespace)
+        .any(|kind| NOTE_TYPES.contains(&kind));
+
+    is_hidden_element(element)
+        || matches!(element.name.as_str(), "rt" | "rp")
+        || attribute("hidden").is_some()
+        || attribute("aria-hidden") == Some("true")
+        || style.contains("display:none")
+        || style.contains("visibility:hidden")
+        || is_note
+}
+
+/// Walks the body of a chapter, collecting the text that's read aloud along with where each word came from
+#[derive(Default)]
+struct TextCollector {
+    text: String,
+    /// UTF-16 length of the text
+    length: usize,
+    segments: Vec<Segment>,
+    /// Whitespace came after the last word
+    pending_space: bool,
+}
+
+impl TextCollector {
+    fn push_word(&mut self, word: &str, path: &[usize], offset: usize) {
+        if self.pending_space && !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push(' ');
+            self.length += 1;
+        }
+        self.pending_space = false;
+
+        let length = utf16_len(word);
+        self.segments.push(Segment {
+            start: self.length,
+            path: path.to_vec(),
+            offset,
+        });
+        self.text.push_str(word);
+        self.length += length;
+    }
+
+    /// Whitespace is collapsed, words split across inline elements like "<i>word</i>s" stay together
+    fn push_text(&mut self, content: &str, path: &[usize], offset: usize) {
+        let mut word_start: Option<(usize, usize)> = None;
+        let mut position = 0;
+
+        for (byte, c) in content.char_indices() {
+            if c.is_whitespace() {
+                if let Some((start_byte, start)) = word_start.take() {
+                    self.push_word(&content[start_byte..byte], path, offset + start);
+                }
+                self.pending_space = true;
+            } else if word_start.is_none() {
+                word_start = Some((byte, position));
+            }
+            position += c.len_utf16();
+        }
+
+        if let Some((start_byte, start)) = word_start {
+            self.push_word(&content[start_byte..], path, offset + start);
+        }
+    }
+
+    fn end_block(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+            self.length += 1;
+        }
+        self.pending_space = false;
+    }
+
+    /// Collects the text of an element, returns the UTF-16 length of its text content
+    fn collect(&mut self, element: &Element, path: &mut Vec<usize>) -> usize {
+        let mut offset = 0;
+        let mut child_index = 0;
+
+        for child in &element.children {
+            match child {
+                XMLNode::Text(content) | XMLNode::CData(content) => {
+                    self.push_text(content, path, offset);
+                    offset += utf16_len(content);
+                }
+                XMLNode::Element(child) => {
+                    path.push(child_index);
+                    child_index += 1;
+
+                    offset += if is_skipped(child) {
+                        text_len(child)
+                    } else if is_block_element(child) {
+                        self.end_block();
+                        let length = self.collect(child, path);
+                        self.end_block();
+                        length
+                    } else {
+                        self.collect(child, path)
+                    };
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+
+        offset
+    }
+
+    /// Where a UTF-16 offset of the chapter text is in the xhtml
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - An offset inside a word
+    /// * `after` - Whether the spot right after the character at the position is wanted, for the ends of ranges
+    ///
+    fn locate(&self, position: usize, after: bool) -> TextPosition {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start <= position)
+            .saturating_sub(1);
+        let segment = &self.segments[index];
+
+        TextPosition {
+            path: segment.path.clone(),
+            offset: segment.offset + (position - segment.start) + usize::from(after),
+        }
+    }
+}
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？')
+}
+
+/// Whether the full stop ending a word is part of an abbreviation or an initial like the J. in J. R. R. Tolkien
+fn is_abbreviation(word: &str) -> bool {
+    let word = word
+        .trim_start_matches(|c: char| !c.is_alphanumeric())
+        .trim_end_matches('.')
+        .to_lowercase();
+    let mut letters = word.chars();
+    let is_initial = matches!((letters.next(), letters.next()), (Some(c), None) if c.is_alphabetic() && c != 'i');
+
+    is_initial || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Splits text into sentences, returned as UTF-16 ranges. Sentences never run past a newline,
+/// and a stop followed by a lowercase word, like "e.g. this", doesn't end one
+fn split_sentences(text: &str) -> Vec<(usize, usize)> {
+    let mut chars = Vec::new();
+    let mut position = 0;
+    for c in text.chars() {
+        chars.push((position, c));
+        position += c.len_utf16();
+    }
+    let position_of = |index: usize| chars.get(index).map_or(position, |(position, _)| *position);
+
+    let mut sentences = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut word_start = 0;
+    let mut index = 0;
+
+    while index < chars.len() {
+        let (at, c) = chars[index];
+        if c == '\n' {
+            if let Some(start) = start.take() {
+                sentences.push((start, at));
+            }
+            word_start = index + 1;
+            index += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            word_start = index + 1;
+            index += 1;
+            continue;
+        }
+        start.get_or_insert(at);
+
+        if is_terminator(c) {
+            let mut end = index + 1;
+            while end < chars.len()
+                && (is_terminator(chars[end].1) || CLOSING_PUNCTUATION.contains(&chars[end].1))
+            {
+                end += 1;
+            }
+            let word: String = chars[word_start..=index].iter().map(|(_, c)| c).collect();
+            let next_word = chars[end..]
+                .iter()
+                .map(|(_, c)| *c)
+                .find(|c| !c.is_whitespace() || *c == '\n');
+
+            let ends_sentence = match chars.get(end) {
+                None => true,
+                // Chinese and Japanese don't put spaces between sentences
+                Some(_) if matches!(c, '。' | '！' | '？') => true,
+                Some((_, next)) if next.is_whitespace() => {
+                    !(next_word.is_some_and(char::is_lowercase)
+                        || c == '.' && is_abbreviation(&word))
+                }
+                Some(_) => false,
+            };
+            if ends_sentence {
+                if let Some(start) = start.take() {
+                    sentences.push((start, position_of(end)));
+                }
+                index = end;
+                continue;
+            }
+        }
+        index += 1;
+    }
+
+    if let Some(start) = start {
+        sentences.push((start, position));
+    }
+
+    sentences
+}
+
+/// Reads the text of a chapter and splits it into sentences
+fn chapter_text(spine_index: usize, href: String, content: &[u8]) -> Result<ChapterText, String> {
+    let document = parse_preserving_whitespace(replace_html_entities(content).as_bytes())
+        .map_err(|err| format!("{} isn't valid xhtml: {}", href, err))?;
+    let body = document.get_child("body").unwrap_or(&document);
+
+    let mut collector = TextCollector::default();
+    collector.collect(body, &mut Vec::new());
+    let text = collector.text.trim_end().to_string();
+
+    let utf16: Vec<u16> = text.encode_utf16().collect();
+    let sentences = split_sentences(&text)
+        .into_iter()
+        .map(|(start, end)| Sentence {
+            text: String::from_utf16_lossy(&utf16[start..end]),
+            start,
+            end,
+            from: collector.locate(start, false),
+            to: collector.locate(end - 1, true),
+        })
+        .collect();
+
+    Ok(ChapterText {
+        spine_index,
+        href,
+        text,
+        sentences,
+    })
+}
+
+/// Reads chapters as plain text split into sentences, for text to speech. Footnotes, ruby readings and hidden
+/// elements are left out. Each sentence carries where it is in the xhtml so the reader can highlight it while it's spoken
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to read
+/// * `spine_index` - The first chapter, counted the same way as the spine of the navigation commands
+/// * `end_spine_index` - The last chapter to read, defaults to the first one
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_chapter_text(
+    book_location: String,
+    spine_index: usize,
+    end_spine_index: Option<usize>,
+) -> Result<Vec<ChapterText>, ShelfError> {
+    if !Path::new(&book_location).is_file() {
+        return Err(ShelfError::not_found(&book_location));
+    }
+    let end_spine_index = end_spine_index.unwrap_or(spine_index);
+    if end_spine_index < spine_index {
+        return Err(ShelfError::invalid(
+            "The last chapter can't come before the first",
+        ));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = File::open(&book_location)
+            .map_err(|err| ShelfError::io("open", &book_location, err))?;
+        let mut archive = ZipArchive::new(BufReader::new(file))
+            .map_err(|err| ShelfError::parse(&book_location, err.to_string()))?;
+
+        let documents: Vec<(usize, String)> = spine_documents(&mut archive)
+            .map_err(|err| ShelfError::parse(&book_location, err))?
+            .into_iter()
+            .filter(|(index, _)| (spine_index..=end_spine_index).contains(index))
+            .collect();
+        if documents.is_empty() {
+            return Err(ShelfError::not_found_in(
+                format!("Chapter {}", spine_index),
+                &book_location,
+            ));
+        }
+
+        documents
+            .into_iter()
+            .map(|(index, href)| {
+                let content = read_zip_entry(&mut archive, &href)
+                    .map_err(|err| ShelfError::epub(&book_location, err))?;
+                chapter_text(index, href, &content)
+                    .map_err(|err| ShelfError::parse(&book_location, err))
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("text extraction", err))?
+}
@@ -0,0 +1,174 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{book_item::Book, book_worker::BookWorker, database::get_db, error::ShelfError};
+
+/// Where a reader is with a book
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadStatus {
+    Unread,
+    Reading,
+    Finished,
+    Abandoned,
+}
+
+impl ReadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::Reading => "reading",
+            ReadStatus::Finished => "finished",
+            ReadStatus::Abandoned => "abandoned",
+        }
+    }
+}
+
+/// Runs an update against a single book, then swaps the updated book into the cache
+///
+/// # Arguments
+///
+/// * `book_location` - The book to update
+/// * `update` - Runs the update, it gets the id of the book
+/// * `state` - The book worker holding the cache
+///
+async fn update_book<F, Fut>(
+    book_location: &str,
+    update: F,
+    state: &State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError>
+where
+    F: FnOnce(i64) -> Fut,
+    Fut: std::future::Future<Output = Result<(), sqlx::Error>>,
+{
+    let book = async {
+        let book_id = sqlx::query_scalar::<_, i64>("SELECT id FROM books WHERE book_location = $1")
+            .bind(book_location)
+            .fetch_optional(get_db())
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        update(book_id).await?;
+
+        sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
+            .bind(book_id)
+            .fetch_one(get_db())
+            .await
+    }
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::RowNotFound => ShelfError::not_found(book_location),
+        err => ShelfError::database(format!("update {}", book_location), err),
+    })?;
+
+    state.lock().unwrap().replace_book(book.clone());
+
+    Ok(book)
+}
+
+/// Sets where a reader is with a book. Starting keeps the first start date, finishing stamps the finish date
+/// and going back to unread clears both
+///
+/// # Arguments
+///
+/// * `book_location` - The book to update
+/// * `status` - unread, reading, finished or abandoned
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_read_status(
+    book_location: String,
+    status: ReadStatus,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    update_book(
+        &book_location,
+        |book_id| async move {
+            sqlx::query(
+                "UPDATE books SET read_status = $1,
+                 date_started = CASE
+                     WHEN $1 = 'unread' THEN NULL
+                     ELSE coalesce(date_started, CURRENT_TIMESTAMP) END,
+                 date_finished = CASE
+                     WHEN $1 = 'finished' THEN coalesce(date_finished, CURRENT_TIMESTAMP)
+                     WHEN $1 = 'abandoned' THEN date_finished
+                     ELSE NULL END,
+                 progress = CASE WHEN $1 = 'finished' THEN 1.0 ELSE progress END
+                 WHERE id = $2",
+            )
+            .bind(status.as_str())
+            .bind(book_id)
+            .execute(get_db())
+            .await
+            .map(|_| ())
+        },
+        &state,
+    )
+    .await
+}
+
+/// Rates a book out of 5
+///
+/// # Arguments
+///
+/// * `book_location` - The book to rate
+/// * `rating` - 0 to 5, nothing clears the rating
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_book_rating(
+    book_location: String,
+    rating: Option<i64>,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    if rating.is_some_and(|rating| !(0..=5).contains(&rating)) {
+        return Err(ShelfError::invalid(format!(
+            "Ratings go from 0 to 5, got {}",
+            rating.unwrap()
+        )));
+    }
+
+    update_book(
+        &book_location,
+        |book_id| async move {
+            sqlx::query("UPDATE books SET rating = $1 WHERE id = $2")
+                .bind(rating)
+                .bind(book_id)
+                .execute(get_db())
+                .await
+                .map(|_| ())
+        },
+        &state,
+    )
+    .await
+}
+
+/// Saves a review of a book
+///
+/// # Arguments
+///
+/// * `book_location` - The book being reviewed
+/// * `review` - Free text, nothing or only whitespace clears the review
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_book_review(
+    book_location: String,
+    review: Option<String>,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    let review = review.filter(|review| !review.trim().is_empty());
+
+    update_book(
+        &book_location,
+        |book_id| async move {
+            sqlx::query("UPDATE books SET review = $1 WHERE id = $2")
+                .bind(review)
+                .bind(book_id)
+                .execute(get_db())
+                .await
+                .map(|_| ())
+        },
+        &state,
+    )
+    .await
+}
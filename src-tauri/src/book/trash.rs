@@ -0,0 +1,568 @@
+use std::{
+    collections::HashSet,
+    fs::{self, create_dir_all},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Sqlite, Transaction};
+use tauri::State;
+use tracing::{error, warn};
+
+use crate::{
+    authors::index_book_authors,
+    book::util::{get_cover_dir, move_file},
+    book_item::{book_insert_query, Book},
+    book_worker::{get_trash_dir, BookWorker},
+    database::get_db,
+    error::ShelfError,
+};
+
+static DEFAULT_RETENTION_DAYS: i64 = 30;
+static MILLIS_PER_DAY: u128 = 24 * 60 * 60 * 1000;
+
+/// A book in the trash as shown to the user
+#[derive(Serialize, FromRow, Debug)]
+pub struct TrashEntry {
+    id: i64,
+    book_location: String,
+    title: String,
+    /// False when only the library entry was removed and the file was left alone
+    file_deleted: bool,
+    /// UTC, same format as CURRENT_TIMESTAMP
+    removed_at: String,
+    /// When the file is deleted for good, none when there's no file in the trash
+    expires_at: Option<String>,
+}
+
+#[derive(FromRow, Debug)]
+struct TrashRow {
+    book_location: String,
+    trash_location: Option<String>,
+    cover_location: Option<String>,
+    snapshot: String,
+}
+
+#[derive(Serialize, Deserialize, FromRow, Debug)]
+struct SessionSnapshot {
+    started_at: String,
+    ended_at: String,
+    start_progress: Option<f64>,
+    end_progress: Option<f64>,
+    pages_read: Option<i64>,
+}
+
+/// Everything removed along with the books row, so restoring it loses nothing
+#[derive(Serialize, Deserialize, Debug)]
+struct BookSnapshot {
+    book: Book,
+    tags: Vec<String>,
+    identifiers: Vec<(String, String)>,
+    sessions: Vec<SessionSnapshot>,
+}
+
+fn millis_since_epoch() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis())
+        .unwrap_or_default()
+}
+
+/// Moves a file into the trash folder, the name is stamped so files with the same name don't collide
+///
+/// # Arguments
+///
+/// * `path` - The file to move
+///
+fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let destination = get_trash_dir().join(format!(
+        "{}-{}",
+        millis_since_epoch(),
+        file_name.to_string_lossy()
+    ));
+
+    move_file(path, &destination)?;
+    Ok(destination)
+}
+
+/// How many days files stay in the trash, from the trash_retention_days setting
+pub fn retention_days(state: &State<'_, Mutex<BookWorker>>) -> i64 {
+    state
+        .lock()
+        .unwrap()
+        .get_application_settings()
+        .get("trash_retention_days")
+        .and_then(|days| days.parse().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// The library entries that were removed without deleting their files, the scan leaves these out
+pub async fn get_removed_locations() -> Result<HashSet<String>, sqlx::Error> {
+    let locations = sqlx::query_scalar::<_, String>(
+        "SELECT book_location FROM trash WHERE trash_location IS NULL",
+    )
+    .fetch_all(get_db())
+    .await?;
+
+    Ok(locations.into_iter().collect())
+}
+
+/// Whether a book other than this one shows the cover, those covers have to stay where they are
+///
+/// # Arguments
+///
+/// * `cover_filename` - The cover's name in the cover cache
+/// * `book_id` - The book that's letting go of the cover
+///
+pub async fn cover_shared(cover_filename: &str, book_id: Option<i64>) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM books WHERE cover_location = $1 AND id != $2)",
+    )
+    .bind(cover_filename)
+    .bind(book_id)
+    .fetch_one(get_db())
+    .await
+}
+
+/// Deletes files that have been in the trash longer than the retention period
+/// Files left without an entry, like ones a failed removal couldn't move back, are aged by the stamp on their name
+async fn purge_expired(retention_days: i64) -> Result<(), sqlx::Error> {
+    let expired = sqlx::query_as::<_, (i64, Option<String>, Option<String>)>(
+        "SELECT id, trash_location, cover_location FROM trash
+         WHERE (trash_location IS NOT NULL OR cover_location IS NOT NULL)
+         AND removed_at < datetime('now', '-' || $1 || ' days')",
+    )
+    .bind(retention_days)
+    .fetch_all(get_db())
+    .await?;
+
+    for (id, trash_location, cover_location) in expired {
+        let file_deleted = trash_location.is_some();
+        for file in [trash_location, cover_location].into_iter().flatten() {
+            _ = fs::remove_file(file);
+        }
+
+        // Entries for books whose file was left alone stay, they keep the scan from adding the book back
+        let statement = if file_deleted {
+            "DELETE FROM trash WHERE id = $1"
+        } else {
+            "UPDATE trash SET cover_location = NULL WHERE id = $1"
+        };
+        sqlx::query(statement).bind(id).execute(get_db()).await?;
+    }
+
+    let referenced: HashSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT trash_location FROM trash WHERE trash_location IS NOT NULL
+         UNION SELECT cover_location FROM trash WHERE cover_location IS NOT NULL",
+    )
+    .fetch_all(get_db())
+    .await?
+    .into_iter()
+    .collect();
+    let oldest_kept = millis_since_epoch().saturating_sub(retention_days as u128 * MILLIS_PER_DAY);
+
+    for path in fs::read_dir(get_trash_dir())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+    {
+        let trashed_at = path
+            .file_name()
+            .and_then(|name| name.to_str()?.split_once('-'))
+            .and_then(|(stamp, _)| stamp.parse::<u128>().ok());
+
+        if trashed_at.is_some_and(|trashed_at| trashed_at < oldest_kept)
+            && !referenced.contains(path.to_string_lossy().as_ref())
+        {
+            _ = fs::remove_file(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// A book's file and cover, moved into the trash folder ahead of removing the book. If the removal doesn't
+/// go through they're moved back with `restore`
+pub struct TrashedFiles {
+    book_location: PathBuf,
+    /// None when the file was left where it is
+    trash_location: Option<PathBuf>,
+    cover_location: PathBuf,
+    trashed_cover: Option<PathBuf>,
+}
+
+impl TrashedFiles {
+    /// Nothing moved yet
+    pub fn new(book: &Book) -> TrashedFiles {
+        TrashedFiles {
+            book_location: PathBuf::from(book.get_book_location()),
+            trash_location: None,
+            cover_location: PathBuf::from(book.get_cover_location()),
+            trashed_cover: None,
+        }
+    }
+
+    /// Moves the book's file into the trash folder
+    pub fn trash_file(&mut self) -> io::Result<()> {
+        self.trash_location = Some(move_to_trash(&self.book_location)?);
+        Ok(())
+    }
+
+    /// Moves the book's cover into the trash folder, skip it when another book is showing the cover.
+    /// Only the cover cache entry goes, covers kept elsewhere aren't ours to move
+    pub fn trash_cover(&mut self) {
+        if self.cover_location.starts_with(get_cover_dir()) && self.cover_location.is_file() {
+            self.trashed_cover = move_to_trash(&self.cover_location).ok();
+        }
+    }
+
+    /// Moves the files back to where they were, for when the book couldn't be removed
+    pub fn restore(&self) {
+        for (original, trashed) in [
+            (&self.book_location, &self.trash_location),
+            (&self.cover_location, &self.trashed_cover),
+        ] {
+            if let Some(trashed) = trashed {
+                if let Err(err) = move_file(trashed, original) {
+                    error!(
+                        "Failed to move {:?} back to {:?}: {}",
+                        trashed, original, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Drops a book from the library and records it in the trash. Its tags, identifiers and reading sessions go
+/// with the books row, so they're kept in the entry's snapshot
+///
+/// # Arguments
+///
+/// * `transaction` - The caller commits it, or moves the files back when it can't
+/// * `book` - The book to remove
+/// * `files` - Its files that were moved to the trash
+/// * `retention_days` - For when the entry expires
+///
+pub async fn trash_book(
+    transaction: &mut Transaction<'_, Sqlite>,
+    book: &Book,
+    files: &TrashedFiles,
+    retention_days: i64,
+) -> Result<TrashEntry, sqlx::Error> {
+    let book_id = book.get_id().ok_or(sqlx::Error::RowNotFound)?;
+
+    let tags = sqlx::query_scalar::<_, String>("SELECT tag FROM book_tags WHERE book_id = $1")
+        .bind(book_id)
+        .fetch_all(&mut **transaction)
+        .await?;
+    let identifiers = sqlx::query_as::<_, (String, String)>(
+        "SELECT kind, value FROM book_identifiers WHERE book_id = $1",
+    )
+    .bind(book_id)
+    .fetch_all(&mut **transaction)
+    .await?;
+    let sessions = sqlx::query_as::<_, SessionSnapshot>(
+        "SELECT started_at, ended_at, start_progress, end_progress, pages_read
+         FROM reading_sessions WHERE book_id = $1 ORDER BY started_at",
+    )
+    .bind(book_id)
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    let snapshot = serde_json::to_string(&BookSnapshot {
+        book: book.clone(),
+        tags,
+        identifiers,
+        sessions,
+    })
+    .map_err(|err| sqlx::Error::Protocol(err.to_string()))?;
+
+    let deleted = sqlx::query("DELETE FROM books WHERE id = $1")
+        .bind(book_id)
+        .execute(&mut **transaction)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    sqlx::query_as::<_, TrashEntry>(
+        "INSERT INTO trash (book_location, title, trash_location, cover_location, snapshot)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, book_location, title, trash_location IS NOT NULL AS file_deleted, removed_at,
+         CASE WHEN trash_location IS NOT NULL
+             THEN datetime(removed_at, '+' || $6 || ' days') END AS expires_at",
+    )
+    .bind(book.get_book_location())
+    .bind(book.get_title())
+    .bind(
+        files
+            .trash_location
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string()),
+    )
+    .bind(
+        files
+            .trashed_cover
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string()),
+    )
+    .bind(&snapshot)
+    .bind(retention_days)
+    .fetch_one(&mut **transaction)
+    .await
+}
+
+/// Takes a book out of the library. Its progress, status, tags and reading history are kept in the trash so the
+/// removal can be undone, annotations are stored by the reader and aren't touched
+/// Removing only the library entry keeps the file out of future scans until it's restored.
+/// The files are moved before the removal is committed, if that fails they're moved back
+///
+/// # Arguments
+///
+/// * `book_location` - The book to remove
+/// * `delete_file` - Moves the file to the trash folder as well, it's deleted for good after the retention period
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn remove_book(
+    book_location: String,
+    delete_file: bool,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<TrashEntry, ShelfError> {
+    let retention_days = retention_days(&state);
+    let database_error = |err: sqlx::Error| match err {
+        sqlx::Error::RowNotFound => ShelfError::not_found(&book_location),
+        err => ShelfError::database(format!("remove {}", book_location), err),
+    };
+
+    let book = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE book_location = $1")
+        .bind(&book_location)
+        .fetch_optional(get_db())
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| ShelfError::not_found(&book_location))?;
+    let cover_shared = cover_shared(book.get_cover_filename(), book.get_id())
+        .await
+        .map_err(database_error)?;
+
+    let mut files = TrashedFiles::new(&book);
+    if delete_file {
+        files
+            .trash_file()
+            .map_err(|err| ShelfError::io("trash", &book_location, err))?;
+    }
+    if !cover_shared {
+        files.trash_cover();
+    }
+
+    let removed = async {
+        let mut transaction = get_db().begin().await?;
+        let entry = trash_book(&mut transaction, &book, &files, retention_days).await?;
+        transaction.commit().await?;
+
+        Ok(entry)
+    }
+    .await;
+
+    let entry = removed.map_err(|err| {
+        // Nothing was removed, so the files go back to where they were
+        files.restore();
+        database_error(err)
+    })?;
+
+    state.lock().unwrap().remove_book(&book_location);
+
+    Ok(entry)
+}
+
+/// Lists the trash, newest first. Files past the retention period are deleted for good first
+#[tauri::command]
+pub async fn get_trash(state: State<'_, Mutex<BookWorker>>) -> Result<Vec<TrashEntry>, ShelfError> {
+    let retention_days = retention_days(&state);
+    async {
+        purge_expired(retention_days).await?;
+
+        sqlx::query_as::<_, TrashEntry>(
+            "SELECT id, book_location, title, removed_at,
+             trash_location IS NOT NULL AS file_deleted,
+             CASE WHEN trash_location IS NOT NULL
+                 THEN datetime(removed_at, '+' || $1 || ' days') END AS expires_at
+             FROM trash ORDER BY removed_at DESC, id DESC",
+        )
+        .bind(retention_days)
+        .fetch_all(get_db())
+        .await
+    }
+    .await
+    .map_err(|err| ShelfError::database("load the trash", err))
+}
+
+/// Puts a removed book back in the library with everything it had, the file goes back to where it was
+///
+/// # Arguments
+///
+/// * `trash_id` - The trash entry to restore
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn restore_book(
+    trash_id: i64,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    let book = async {
+        let row = sqlx::query_as::<_, TrashRow>(
+            "SELECT book_location, trash_location, cover_location, snapshot
+             FROM trash WHERE id = $1",
+        )
+        .bind(trash_id)
+        .fetch_optional(get_db())
+        .await
+        .map_err(|err| ShelfError::database("load the trash", err))?
+        .ok_or_else(|| ShelfError::not_found_in("That book", "the trash"))?;
+        let snapshot: BookSnapshot = serde_json::from_str(&row.snapshot).map_err(|err| {
+            ShelfError::parse(
+                &row.book_location,
+                format!("its trash entry is damaged, {}", err),
+            )
+        })?;
+
+        let original = Path::new(&row.book_location);
+        if let Some(trash_location) = &row.trash_location {
+            if original.exists() {
+                return Err(ShelfError::invalid(format!(
+                    "Can't restore, something is already at {}",
+                    row.book_location
+                )));
+            }
+            original
+                .parent()
+                .map_or(Ok(()), create_dir_all)
+                .and_then(|_| move_file(Path::new(trash_location), original))
+                .map_err(|err| ShelfError::io("move the file back to", original, err))?;
+        }
+        if let Some(trashed_cover) = &row.cover_location {
+            let cover_location = PathBuf::from(snapshot.book.get_cover_location());
+            if !cover_location.exists() {
+                _ = move_file(Path::new(trashed_cover), &cover_location);
+            }
+        }
+
+        let restored = async {
+            let mut transaction = get_db().begin().await?;
+
+            let book_id = book_insert_query(std::slice::from_ref(&snapshot.book))
+                .build()
+                .execute(&mut *transaction)
+                .await?
+                .last_insert_rowid();
+
+            for tag in &snapshot.tags {
+                sqlx::query("INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES ($1, $2)")
+                    .bind(book_id)
+                    .bind(tag)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            for (kind, value) in &snapshot.identifiers {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO book_identifiers (book_id, kind, value)
+                     VALUES ($1, $2, $3)",
+                )
+                .bind(book_id)
+                .bind(kind)
+                .bind(value)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            for session in &snapshot.sessions {
+                sqlx::query(
+                    "INSERT INTO reading_sessions
+                     (book_id, started_at, ended_at, start_progress, end_progress, pages_read)
+                     VALUES ($1, $2, $3, $4, $5, $6)",
+                )
+                .bind(book_id)
+                .bind(&session.started_at)
+                .bind(&session.ended_at)
+                .bind(session.start_progress)
+                .bind(session.end_progress)
+                .bind(session.pages_read)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            sqlx::query("DELETE FROM trash WHERE id = $1")
+                .bind(trash_id)
+                .execute(&mut *transaction)
+                .await?;
+
+            let book = sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
+                .bind(book_id)
+                .fetch_one(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+
+            Ok::<Book, sqlx::Error>(book)
+        }
+        .await;
+
+        restored.map_err(|err| {
+            // The entry is still in the trash, so is the file
+            if let Some(trash_location) = &row.trash_location {
+                _ = move_file(original, Path::new(trash_location));
+            }
+            ShelfError::database(format!("restore {}", row.book_location), err)
+        })
+    }
+    .await?;
+
+    if let Err(err) = index_book_authors(std::slice::from_ref(&book)).await {
+        warn!("Failed to index authors: {}", err);
+    }
+    state.lock().unwrap().add_book(book.clone());
+
+    Ok(book)
+}
+
+/// Deletes files in the trash for good. Emptying the whole trash keeps the entries of books whose files were
+/// left alone, since dropping those lets the next scan add the book again
+///
+/// # Arguments
+///
+/// * `trash_id` - A single entry to delete, nothing empties the trash
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn empty_trash(trash_id: Option<i64>) -> Result<(), ShelfError> {
+    async {
+        let entries = sqlx::query_as::<_, (i64, Option<String>, Option<String>)>(
+            "SELECT id, trash_location, cover_location FROM trash
+             WHERE ($1 IS NULL AND trash_location IS NOT NULL) OR id = $1",
+        )
+        .bind(trash_id)
+        .fetch_all(get_db())
+        .await?;
+
+        for (id, trash_location, cover_location) in entries {
+            for file in [trash_location, cover_location].into_iter().flatten() {
+                _ = fs::remove_file(file);
+            }
+            sqlx::query("DELETE FROM trash WHERE id = $1")
+                .bind(id)
+                .execute(get_db())
+                .await?;
+        }
+
+        // Files without an entry
+        if trash_id.is_none() {
+            purge_expired(0).await?;
+        }
+
+        Ok(())
+    }
+    .await
+    .map_err(|err| ShelfError::database("empty the trash", err))
+}
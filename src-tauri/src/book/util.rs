@@ -3,100 +3,17 @@ use regex::Regex;
 
 use sqlx::Sqlite;
 use tauri::{api::path::app_cache_dir, generate_context, Config};
+use tracing::{debug, error, warn};
 
 use crate::book_item::Book;
 
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     fs::{self, create_dir_all, File},
-    io::BufReader,
+    io::{self, BufReader},
     path::{Path, PathBuf},
 };
 
-/// Finds a chunk in the dataset that starts with the same letter as the key, returning the found value
-/// One this chunk is found we binary search within that section, theoretically faster
-///
-/// # Arguments
-///
-/// * `dataset` - A dataset sorted in alphabetical order
-/// * `key` - The key to look for
-///
-pub fn chunk_binary_search_index(dataset: &Vec<Book>, key: &String) -> Option<usize> {
-    let title = key.to_string();
-    //handel lower case
-    let low = dataset
-        .iter()
-        .position(|b| b.get_title()[..1] == title[..1]);
-
-    if let Some(index) = low {
-        let mut high = dataset
-            .iter()
-            .rposition(|b| b.get_title()[..1] == title[..1])
-            .unwrap();
-        let mut unwrapped_low = index;
-        while unwrapped_low <= high {
-            let mid = (unwrapped_low + high) / 2;
-            match dataset[mid].get_title().cmp(&title) {
-                Ordering::Equal => {
-                    //return Some(mid);
-                    return None;
-                }
-                Ordering::Less => {
-                    unwrapped_low = mid + 1;
-                }
-                Ordering::Greater => {
-                    high = mid - 1;
-                }
-            }
-        }
-        Some(unwrapped_low)
-    } else {
-        Some(dataset.len())
-    }
-}
-
-/// Finds a chunk in the dataset that starts with the same letter as the key, returning the found index
-/// One this chunk is found we binary search within that section, theoretically faster
-///
-/// # Arguments
-///
-/// * `dataset` - A dataset sorted in alphabetical order
-/// * `key` - The key to look for
-///
-pub fn chunk_binary_search_index_load(dataset: &[Book], key: &String) -> Option<usize> {
-    let title = key.to_string();
-    //handel lower case
-    let low = dataset
-        .iter()
-        .position(|b| b.get_title()[..1] == title[..1]);
-
-    if let Some(index) = low {
-        let mut high = dataset
-            .iter()
-            .rposition(|b| b.get_title()[..1] == title[..1])
-            .unwrap();
-        let mut unwrapped_low = index;
-        while unwrapped_low <= high {
-            let mid = (unwrapped_low + high) / 2;
-            match dataset[mid].get_title().cmp(&title) {
-                Ordering::Equal => {
-                    return Some(mid);
-                }
-                Ordering::Less => {
-                    unwrapped_low = mid + 1;
-                }
-                Ordering::Greater => {
-                    high = mid - 1;
-                }
-            }
-        }
-        Some(unwrapped_low)
-    } else {
-        None
-    }
-}
-
 /// Looks in an Epubs resources for a given key with a given mime type, returning the data as a string
 ///
 /// # Arguments
@@ -115,12 +32,15 @@ pub fn check_epub_resource(
     epub_resources
         .keys()
         .find(|key| {
-            key_regex.is_match(key) && mime_regex.is_match(&doc.get_resource(key).unwrap().1)
+            key_regex.is_match(key)
+                && doc
+                    .get_resource(key)
+                    .is_some_and(|(_, mime)| mime_regex.is_match(&mime))
         })
         .map(|key| key.to_owned())
 }
 
-pub fn create_batch_query(batch_books: Vec<&Book>) -> Result<String, ()> {
+pub fn create_batch_query(batch_books: Vec<&Book>) -> String {
     let mut query_builder: sqlx::QueryBuilder<Sqlite> =
         sqlx::QueryBuilder::new("INSERT INTO books (cover_location, book_location, title) ");
 
@@ -132,8 +52,8 @@ pub fn create_batch_query(batch_books: Vec<&Book>) -> Result<String, ()> {
     });
 
     let query = query_builder.into_sql();
-    println!("{:?}", query);
-    Ok(query)
+    debug!(query, "Built the book insert");
+    query
 }
 
 pub fn get_cover_dir() -> PathBuf {
@@ -141,7 +61,7 @@ pub fn get_cover_dir() -> PathBuf {
     cache_dir.push("cache");
     cache_dir.push(env!("COVER_IMAGE_FOLDER_NAME"));
     if let Err(err) = create_dir_all(&cache_dir) {
-        eprintln!("Error creating {:?} directory: {:?}", err, cache_dir);
+        error!("Error creating {:?} directory: {:?}", cache_dir, err);
     }
 
     cache_dir
@@ -150,7 +70,7 @@ pub fn is_file_empty<P: AsRef<Path>>(file_path: P) -> bool {
     match fs::metadata(&file_path) {
         Ok(metadata) => metadata.len() == 0,
         Err(_) => {
-            println!(
+            warn!(
                 "Failed to retrieve metadata for file: {}",
                 file_path.as_ref().display()
             );
@@ -159,6 +79,58 @@ pub fn is_file_empty<P: AsRef<Path>>(file_path: P) -> bool {
     }
 }
 
+/// Finds every epub under a directory, organized libraries keep their books in sub folders
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search
+///
+pub fn find_epub_paths(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut epub_paths = Vec::new();
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            epub_paths.extend(find_epub_paths(&path));
+        } else if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == "epub")
+        {
+            if let Some(path) = path.to_str() {
+                epub_paths.push(path.to_owned());
+            }
+        }
+    }
+
+    epub_paths
+}
+
+/// Moves a file, falling back to copying it when it's going to another drive
+///
+/// # Arguments
+///
+/// * `from` - The file to move
+/// * `to` - Where it's going, the parent folder has to exist
+///
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+/// Escapes the wildcards of a LIKE pattern with a backslash, the query has to declare it as the ESCAPE character
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 /// Gets the current tauri context.
 pub fn current_context() -> Config {
     generate_context!().config().clone()
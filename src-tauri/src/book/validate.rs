@@ -0,0 +1,804 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use epub::doc::EpubDoc;
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::{info, instrument};
+use xml::{
+    reader::{EventReader, ParserConfig, XmlEvent as ReaderEvent},
+    writer::{EmitterConfig, EventWriter},
+};
+use xmltree::Element;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    book::{
+        metadata::{read_zip_entry, resolve_zip_path, CONTAINER_PATH},
+        organize::deduplicate_target,
+    },
+    book_item::{get_all_books, unique_find_cover},
+    book_worker::get_repaired_dir,
+    database::get_db,
+    error::ShelfError,
+    xml::child_elements,
+};
+
+static MIMETYPE: &str = "application/epub+zip";
+static XHTML_MEDIA_TYPE: &str = "application/xhtml+xml";
+
+// Html entities xml doesn't know about, the usual reason an otherwise fine chapter fails to parse
+static HTML_ENTITIES: [(&str, &str); 24] = [
+    ("&nbsp;", "&#160;"),
+    ("&shy;", "&#173;"),
+    ("&mdash;", "&#8212;"),
+    ("&ndash;", "&#8211;"),
+    ("&hellip;", "&#8230;"),
+    ("&lsquo;", "&#8216;"),
+    ("&rsquo;", "&#8217;"),
+    ("&ldquo;", "&#8220;"),
+    ("&rdquo;", "&#8221;"),
+    ("&laquo;", "&#171;"),
+    ("&raquo;", "&#187;"),
+    ("&bull;", "&#8226;"),
+    ("&middot;", "&#183;"),
+    ("&copy;", "&#169;"),
+    ("&reg;", "&#174;"),
+    ("&trade;", "&#8482;"),
+    ("&eacute;", "&#233;"),
+    ("&egrave;", "&#232;"),
+    ("&agrave;", "&#224;"),
+    ("&ccedil;", "&#231;"),
+    ("&auml;", "&#228;"),
+    ("&ouml;", "&#246;"),
+    ("&uuml;", "&#252;"),
+    ("&szlig;", "&#223;"),
+];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Readers, or shelf itself, may refuse the book
+    Error,
+    /// The book opens but something in it is off
+    Warning,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    NotAnArchive,
+    MimetypeMissing,
+    MimetypeNotFirst,
+    MimetypeCompressed,
+    MimetypeWrong,
+    ContainerMissing,
+    ContainerBroken,
+    PackageMissing,
+    PackageBroken,
+    ManifestMissing,
+    DuplicateId,
+    MissingResource,
+    SpineMissing,
+    SpineEmpty,
+    UnknownSpineItem,
+    BrokenXhtml,
+    /// The epub crate can't open it, so it can't be added to the shelf
+    Unreadable,
+    NoCover,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidationIssue {
+    kind: IssueKind,
+    severity: Severity,
+    message: String,
+    /// The file inside the epub the issue is about
+    entry: Option<String>,
+    /// Whether `repair_epub` fixes it
+    repairable: bool,
+}
+
+impl ValidationIssue {
+    fn error(kind: IssueKind, message: impl Into<String>) -> Self {
+        ValidationIssue {
+            kind,
+            severity: Severity::Error,
+            message: message.into(),
+            entry: None,
+            repairable: false,
+        }
+    }
+
+    fn warning(kind: IssueKind, message: impl Into<String>) -> Self {
+        ValidationIssue {
+            severity: Severity::Warning,
+            ..Self::error(kind, message)
+        }
+    }
+
+    fn in_entry(self, entry: impl Into<String>) -> Self {
+        ValidationIssue {
+            entry: Some(entry.into()),
+            ..self
+        }
+    }
+
+    fn repairable(self, repairable: bool) -> Self {
+        ValidationIssue { repairable, ..self }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValidationReport {
+    book_location: String,
+    /// False when any issue is an error
+    valid: bool,
+    issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn new(book_location: String, issues: Vec<ValidationIssue>) -> Self {
+        ValidationReport {
+            book_location,
+            valid: !issues.iter().any(|issue| issue.severity == Severity::Error),
+            issues,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RepairReport {
+    repaired_location: String,
+    fixed: Vec<ValidationIssue>,
+    /// What the repaired copy still has wrong with it
+    remaining: Vec<ValidationIssue>,
+}
+
+/// What validation found, repairing works from the same picture so it only touches what was reported
+#[derive(Default)]
+struct Inspection {
+    issues: Vec<ValidationIssue>,
+    opf_path: Option<String>,
+    container_broken: bool,
+    /// Manifest ids pointing at files that aren't in the archive
+    missing_ids: HashSet<String>,
+    /// Spine idrefs that aren't in the manifest
+    unknown_idrefs: HashSet<String>,
+    /// Chapters that parse once their html entities are swapped for numeric ones
+    entity_fixes: Vec<String>,
+}
+
+impl Inspection {
+    fn push(&mut self, issue: ValidationIssue) {
+        self.issues.push(issue);
+    }
+
+    fn has_dangling_references(&self) -> bool {
+        !self.missing_ids.is_empty() || !self.unknown_idrefs.is_empty()
+    }
+}
+
+pub(crate) fn replace_html_entities(content: &[u8]) -> String {
+    HTML_ENTITIES.iter().fold(
+        String::from_utf8_lossy(content).to_string(),
+        |content, (entity, numeric)| content.replace(entity, numeric),
+    )
+}
+
+fn attribute<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+    element.attributes.get(name).map(String::as_str)
+}
+
+fn check_mimetype<R: Read + Seek>(archive: &mut ZipArchive<R>, inspection: &mut Inspection) {
+    let first_is_mimetype = archive
+        .by_index_raw(0)
+        .is_ok_and(|entry| entry.name() == "mimetype");
+
+    let mut content = String::new();
+    let compressed = match archive.by_name("mimetype") {
+        Ok(mut entry) => {
+            _ = entry.read_to_string(&mut content);
+            entry.compression() != CompressionMethod::Stored
+        }
+        Err(_) => {
+            inspection.push(
+                ValidationIssue::error(IssueKind::MimetypeMissing, "There's no mimetype file")
+                    .repairable(true),
+            );
+            return;
+        }
+    };
+
+    if content.trim() != MIMETYPE {
+        inspection.push(
+            ValidationIssue::error(
+                IssueKind::MimetypeWrong,
+                format!(
+                    "The mimetype is {:?} instead of {}",
+                    content.trim(),
+                    MIMETYPE
+                ),
+            )
+            .in_entry("mimetype")
+            .repairable(true),
+        );
+    }
+    if !first_is_mimetype {
+        inspection.push(
+            ValidationIssue::warning(
+                IssueKind::MimetypeNotFirst,
+                "The mimetype isn't the first file in the archive",
+            )
+            .in_entry("mimetype")
+            .repairable(true),
+        );
+    }
+    if compressed {
+        inspection.push(
+            ValidationIssue::warning(IssueKind::MimetypeCompressed, "The mimetype is compressed")
+                .in_entry("mimetype")
+                .repairable(true),
+        );
+    }
+}
+
+/// Finds the opf through container.xml, a broken container is repairable when the archive holds exactly one opf
+fn check_container<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    names: &HashSet<String>,
+    inspection: &mut Inspection,
+) {
+    let candidates: Vec<&String> = names
+        .iter()
+        .filter(|name| name.to_lowercase().ends_with(".opf"))
+        .collect();
+    let fallback = match candidates.as_slice() {
+        [only] => Some(only.to_string()),
+        _ => None,
+    };
+
+    let listed = match read_zip_entry(archive, CONTAINER_PATH) {
+        Ok(container) => match Element::parse(container.as_slice()) {
+            Ok(container) => container
+                .get_child("rootfiles")
+                .and_then(|rootfiles| rootfiles.get_child("rootfile"))
+                .and_then(|rootfile| attribute(rootfile, "full-path"))
+                .map(String::from)
+                .ok_or_else(|| {
+                    ValidationIssue::error(
+                        IssueKind::ContainerBroken,
+                        "container.xml doesn't list a package document",
+                    )
+                }),
+            Err(err) => Err(ValidationIssue::error(
+                IssueKind::ContainerBroken,
+                format!("container.xml isn't valid xml: {}", err),
+            )),
+        },
+        Err(_) => Err(ValidationIssue::error(
+            IssueKind::ContainerMissing,
+            "There's no META-INF/container.xml",
+        )),
+    };
+
+    let issue = match listed {
+        Ok(opf_path) if names.contains(&opf_path) => {
+            inspection.opf_path = Some(opf_path);
+            return;
+        }
+        Ok(opf_path) => ValidationIssue::error(
+            IssueKind::PackageMissing,
+            format!("The package document {} isn't in the archive", opf_path),
+        ),
+        Err(issue) => issue,
+    };
+
+    inspection.container_broken = fallback.is_some();
+    inspection.opf_path = fallback;
+    inspection.push(
+        issue
+            .in_entry(CONTAINER_PATH)
+            .repairable(inspection.container_broken),
+    );
+}
+
+fn check_xhtml<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    zip_path: &str,
+    inspection: &mut Inspection,
+) {
+    let Ok(content) = read_zip_entry(archive, zip_path) else {
+        return;
+    };
+    let Err(err) = Element::parse(content.as_slice()) else {
+        return;
+    };
+
+    let fixable = Element::parse(replace_html_entities(&content).as_bytes()).is_ok();
+    if fixable {
+        inspection.entity_fixes.push(zip_path.to_string());
+    }
+    inspection.push(
+        ValidationIssue::error(
+            IssueKind::BrokenXhtml,
+            format!("The chapter isn't valid xhtml: {}", err),
+        )
+        .in_entry(zip_path)
+        .repairable(fixable),
+    );
+}
+
+/// Checks every manifest item exists and every spine entry points at one
+fn check_package<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    names: &HashSet<String>,
+    opf_path: &str,
+    inspection: &mut Inspection,
+) {
+    let package = match read_zip_entry(archive, opf_path)
+        .map_err(|err| err.to_string())
+        .and_then(|opf| Element::parse(opf.as_slice()).map_err(|err| err.to_string()))
+    {
+        Ok(package) => package,
+        Err(err) => {
+            inspection.push(
+                ValidationIssue::error(
+                    IssueKind::PackageBroken,
+                    format!("The package document can't be read: {}", err),
+                )
+                .in_entry(opf_path),
+            );
+            return;
+        }
+    };
+    let opf_dir = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let Some(manifest) = package.get_child("manifest") else {
+        inspection.push(
+            ValidationIssue::error(IssueKind::ManifestMissing, "The package has no manifest")
+                .in_entry(opf_path),
+        );
+        return;
+    };
+
+    let mut ids = HashSet::new();
+    let mut chapters = Vec::new();
+    for item in child_elements(manifest, "item") {
+        let (Some(id), Some(href)) = (attribute(item, "id"), attribute(item, "href")) else {
+            continue;
+        };
+        if !ids.insert(id.to_string()) {
+            inspection.push(
+                ValidationIssue::warning(
+                    IssueKind::DuplicateId,
+                    format!("More than one manifest item has the id {}", id),
+                )
+                .in_entry(opf_path),
+            );
+        }
+
+        let zip_path = resolve_zip_path(opf_dir, href);
+        if !names.contains(&zip_path) {
+            inspection.missing_ids.insert(id.to_string());
+            inspection.push(
+                ValidationIssue::error(
+                    IssueKind::MissingResource,
+                    format!("The manifest lists {} but it isn't in the archive", href),
+                )
+                .in_entry(zip_path)
+                .repairable(true),
+            );
+        } else if attribute(item, "media-type") == Some(XHTML_MEDIA_TYPE) {
+            chapters.push(zip_path);
+        }
+    }
+
+    match package.get_child("spine") {
+        Some(spine) => {
+            let mut readable = 0;
+            for itemref in child_elements(spine, "itemref") {
+                let Some(idref) = attribute(itemref, "idref") else {
+                    continue;
+                };
+                if !ids.contains(idref) {
+                    inspection.unknown_idrefs.insert(idref.to_string());
+                    inspection.push(
+                        ValidationIssue::error(
+                            IssueKind::UnknownSpineItem,
+                            format!("The spine lists {} which isn't in the manifest", idref),
+                        )
+                        .in_entry(opf_path)
+                        .repairable(true),
+                    );
+                } else if !inspection.missing_ids.contains(idref) {
+                    readable += 1;
+                }
+            }
+
+            if readable == 0 {
+                inspection.push(
+                    ValidationIssue::error(
+                        IssueKind::SpineEmpty,
+                        "The spine has no chapters that can be read",
+                    )
+                    .in_entry(opf_path),
+                );
+            }
+        }
+        None => inspection.push(
+            ValidationIssue::error(IssueKind::SpineMissing, "The package has no spine")
+                .in_entry(opf_path),
+        ),
+    }
+
+    for chapter in chapters {
+        check_xhtml(archive, &chapter, inspection);
+    }
+}
+
+/// Runs every check on an open archive
+fn inspect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Inspection {
+    let mut inspection = Inspection::default();
+    let names: HashSet<String> = archive.file_names().map(String::from).collect();
+
+    check_mimetype(archive, &mut inspection);
+    check_container(archive, &names, &mut inspection);
+    if let Some(opf_path) = inspection.opf_path.clone() {
+        check_package(archive, &names, &opf_path, &mut inspection);
+    }
+
+    inspection
+}
+
+/// Opens the book the way shelf does when adding it
+fn check_readable(book_location: &Path, inspection: &mut Inspection) {
+    match EpubDoc::new(book_location) {
+        Ok(doc) => {
+            if let Err(err) = unique_find_cover(doc) {
+                inspection.push(ValidationIssue::warning(
+                    IssueKind::NoCover,
+                    format!("No cover could be found: {}", err),
+                ));
+            }
+        }
+        Err(err) => inspection.push(ValidationIssue::error(
+            IssueKind::Unreadable,
+            format!("The epub can't be opened: {}", err),
+        )),
+    }
+}
+
+fn open_archive(book_location: &Path) -> Result<ZipArchive<BufReader<File>>, ValidationIssue> {
+    File::open(book_location)
+        .map_err(|err| err.to_string())
+        .and_then(|file| ZipArchive::new(BufReader::new(file)).map_err(|err| err.to_string()))
+        .map_err(|err| {
+            ValidationIssue::error(
+                IssueKind::NotAnArchive,
+                format!("The file isn't a zip archive: {}", err),
+            )
+        })
+}
+
+fn validate_file(book_location: &Path) -> Inspection {
+    let mut inspection = match open_archive(book_location) {
+        Ok(mut archive) => inspect(&mut archive),
+        Err(issue) => {
+            return Inspection {
+                issues: vec![issue],
+                ..Default::default()
+            }
+        }
+    };
+    check_readable(book_location, &mut inspection);
+
+    inspection
+}
+
+/// Streams the package document through, leaving out manifest items for missing files and spine entries pointing at nothing.
+/// Streaming keeps namespaces and prefixed attributes like opf:role the way they were
+fn drop_dangling_references(opf: &[u8], inspection: &Inspection) -> Result<Vec<u8>, String> {
+    let config = ParserConfig::new()
+        .ignore_comments(false)
+        .cdata_to_characters(false);
+    let reader = EventReader::new_with_config(opf, config);
+    let mut writer = EventWriter::new_with_config(Vec::new(), EmitterConfig::new());
+
+    let mut depth = 0;
+    // Depth of a dropped element, its children are dropped along with it
+    let mut skip_depth: Option<usize> = None;
+
+    for event in reader {
+        let event = event.map_err(|err| err.to_string())?;
+
+        match &event {
+            ReaderEvent::StartElement {
+                name, attributes, ..
+            } => {
+                depth += 1;
+                if skip_depth.is_some() {
+                    continue;
+                }
+
+                let value = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == key)
+                        .map(|attr| attr.value.as_str())
+                };
+                let dropped = match name.local_name.as_str() {
+                    "item" => value("id").is_some_and(|id| inspection.missing_ids.contains(id)),
+                    "itemref" => value("idref").is_some_and(|idref| {
+                        inspection.missing_ids.contains(idref)
+                            || inspection.unknown_idrefs.contains(idref)
+                    }),
+                    _ => false,
+                };
+                if dropped {
+                    skip_depth = Some(depth);
+                    continue;
+                }
+            }
+            ReaderEvent::EndElement { .. } => {
+                let current_depth = depth;
+                depth -= 1;
+
+                if let Some(skipped) = skip_depth {
+                    if skipped == current_depth {
+                        skip_depth = None;
+                    }
+                    continue;
+                }
+            }
+            _ if skip_depth.is_some() => continue,
+            _ => {}
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+fn container_xml(opf_path: &str) -> String {
+    let opf_path = opf_path
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;");
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="{}" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+        opf_path
+    )
+}
+
+fn write_entry<W: Write + Seek>(
+    writer: &mut ZipWriter<W>,
+    name: &str,
+    options: SimpleFileOptions,
+    data: &[u8],
+) -> Result<(), String> {
+    writer
+        .start_file(name, options)
+        .map_err(|err| err.to_string())
+        .and_then(|_| writer.write_all(data).map_err(|err| err.to_string()))
+}
+
+/// Copies the archive, replacing the entries the inspection found a fix for and leaving the rest untouched
+fn write_repaired_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    inspection: &Inspection,
+    destination: &Path,
+) -> Result<(), String> {
+    let file = File::create(destination).map_err(|err| err.to_string())?;
+    let mut writer = ZipWriter::new(BufWriter::new(file));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // Readers expect the mimetype first and uncompressed
+    write_entry(&mut writer, "mimetype", stored, MIMETYPE.as_bytes())?;
+    if inspection.container_broken {
+        if let Some(opf_path) = &inspection.opf_path {
+            write_entry(
+                &mut writer,
+                CONTAINER_PATH,
+                deflated,
+                container_xml(opf_path).as_bytes(),
+            )?;
+        }
+    }
+
+    let mut written = HashSet::new();
+    for index in 0..archive.len() {
+        let name = archive
+            .by_index_raw(index)
+            .map_err(|err| err.to_string())?
+            .name()
+            .to_string();
+        // Broken archives can hold the same name twice, the first one is what readers see
+        if name == "mimetype"
+            || (inspection.container_broken && name == CONTAINER_PATH)
+            || !written.insert(name.clone())
+        {
+            continue;
+        }
+
+        let is_opf = inspection.opf_path.as_deref() == Some(name.as_str());
+        if is_opf && inspection.has_dangling_references() {
+            let opf = read_zip_entry(archive, &name).map_err(|err| err.to_string())?;
+            let opf = drop_dangling_references(&opf, inspection)?;
+            write_entry(&mut writer, &name, deflated, &opf)?;
+        } else if inspection.entity_fixes.contains(&name) {
+            let content = read_zip_entry(archive, &name).map_err(|err| err.to_string())?;
+            write_entry(
+                &mut writer,
+                &name,
+                deflated,
+                replace_html_entities(&content).as_bytes(),
+            )?;
+        } else {
+            let entry = archive.by_index_raw(index).map_err(|err| err.to_string())?;
+            writer.raw_copy_file(entry).map_err(|err| err.to_string())?;
+        }
+    }
+
+    writer
+        .finish()
+        .map_err(|err| err.to_string())?
+        .flush()
+        .map_err(|err| err.to_string())
+}
+
+/// The name of the repaired copy, "<name> (repaired).epub"
+fn repaired_file_name(book_location: &Path) -> String {
+    let stem = book_location
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    format!("{} (repaired).epub", stem)
+}
+
+fn repair_file(book_location: &Path, destination: PathBuf) -> Result<RepairReport, ShelfError> {
+    let mut archive = open_archive(book_location)
+        .map_err(|issue| ShelfError::parse(book_location, issue.message))?;
+    let inspection = inspect(&mut archive);
+
+    let fixed: Vec<ValidationIssue> = inspection
+        .issues
+        .iter()
+        .filter(|issue| issue.repairable)
+        .cloned()
+        .collect();
+    if fixed.is_empty() {
+        return Err(ShelfError::invalid(format!(
+            "{} has nothing that can be repaired",
+            book_location.display()
+        )));
+    }
+
+    let destination = deduplicate_target(destination, book_location, &HashSet::new());
+    let temp_location = destination.with_extension("epub.tmp");
+    let written = write_repaired_archive(&mut archive, &inspection, &temp_location)
+        .map_err(|err| ShelfError::parse(book_location, err))
+        .and_then(|_| {
+            fs::rename(&temp_location, &destination)
+                .map_err(|err| ShelfError::io("write the repaired copy to", &destination, err))
+        });
+    if written.is_err() {
+        _ = fs::remove_file(&temp_location);
+    }
+    written?;
+
+    Ok(RepairReport {
+        repaired_location: destination.to_string_lossy().to_string(),
+        fixed,
+        remaining: validate_file(&destination).issues,
+    })
+}
+
+/// Checks an epub for the problems that stop it from opening or confuse the cover search:
+/// the mimetype, container.xml, the manifest and spine, missing files and chapters that aren't valid xhtml
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to check
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn validate_epub(book_location: String) -> Result<ValidationReport, ShelfError> {
+    if !Path::new(&book_location).is_file() {
+        return Err(ShelfError::not_found(&book_location));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let issues = validate_file(Path::new(&book_location)).issues;
+        ValidationReport::new(book_location, issues)
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("validation", err))
+}
+
+/// Checks every book on the shelf along with the files that failed to import, returns the ones with issues
+#[tauri::command]
+#[instrument]
+pub async fn validate_library() -> Result<Vec<ValidationReport>, ShelfError> {
+    let mut locations: Vec<String> = get_all_books()
+        .await
+        .map_err(|err| ShelfError::database("load the library", err))?
+        .iter()
+        .map(|book| book.get_book_location().to_string())
+        .collect();
+    locations.extend(
+        sqlx::query_scalar::<_, String>("SELECT book_location FROM import_errors")
+            .fetch_all(get_db())
+            .await
+            .map_err(|err| ShelfError::database("load the import errors", err))?,
+    );
+
+    let reports = tauri::async_runtime::spawn_blocking(move || {
+        locations
+            .into_par_iter()
+            .filter(|location| Path::new(location).is_file())
+            .map(|location| {
+                let issues = validate_file(Path::new(&location)).issues;
+                ValidationReport::new(location, issues)
+            })
+            .filter(|report| !report.issues.is_empty())
+            .collect::<Vec<ValidationReport>>()
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("validation", err))?;
+    info!(books = reports.len(), "Library validation found issues");
+
+    Ok(reports)
+}
+
+/// Writes a copy of an epub with the repairable issues fixed, the original is left as it is.
+/// Returns what was fixed and anything the copy still has wrong
+///
+/// # Arguments
+///
+/// * `book_location` - The epub to repair
+/// * `destination` - The file or folder to write the copy to, defaults to "<name> (repaired).epub" in the
+///   repaired folder of the cache. Copies next to the original would be imported by the next scan
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn repair_epub(
+    book_location: String,
+    destination: Option<String>,
+) -> Result<RepairReport, ShelfError> {
+    let source = PathBuf::from(&book_location);
+    if !source.is_file() {
+        return Err(ShelfError::not_found(&book_location));
+    }
+    let destination = match destination.map(PathBuf::from) {
+        Some(folder) if folder.is_dir() => folder.join(repaired_file_name(&source)),
+        Some(destination) => destination,
+        None => get_repaired_dir().join(repaired_file_name(&source)),
+    };
+
+    let report = tauri::async_runtime::spawn_blocking(move || repair_file(&source, destination))
+        .await
+        .map_err(|err| ShelfError::interrupted("repair", err))??;
+    info!(
+        book_location,
+        repaired_location = report.repaired_location,
+        fixed = report.fixed.len(),
+        "Repaired epub"
+    );
+
+    Ok(report)
+}
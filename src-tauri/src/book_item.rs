@@ -1,28 +1,38 @@
 use std::{
+    collections::HashSet,
     fs::{create_dir_all, File},
     io::BufReader,
     path::PathBuf,
     sync::Mutex,
 };
 
+use tracing::{error, instrument, warn};
+
 use crate::{
     book::{
         bookio::{get_book_cover_image, write_cover_image, BookError},
+        length::ChapterLength,
         util::{check_epub_resource, current_context, get_cover_dir},
     },
     book_worker::BookWorker,
     database::get_db,
+    error::ShelfError,
 };
 use epub::doc::EpubDoc;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::{sqlite::SqliteQueryResult, Sqlite};
+use sqlx::{
+    sqlite::{SqliteConnection, SqliteQueryResult},
+    QueryBuilder, Sqlite, SqlitePool,
+};
 use tauri::{api::path::app_cache_dir, State};
-use tokio::runtime::Runtime;
 use xmltree::Element;
 
 use crate::xml::extract_image_source;
 
+// 17 binds per book keeps a chunk well under sqlites bind limit
+static INSERT_CHUNK_SIZE: usize = 256;
+
 // TODO just make it empty vector instead of usig option
 /// This is used for organization
 pub struct BookCache {
@@ -32,11 +42,21 @@ pub struct BookCache {
 impl BookCache {
     /// Used to update the contents of the book_cache.json file
     pub fn new(books: Option<Vec<Book>>) -> BookCache {
-        BookCache { books }
+        let mut cache = BookCache { books };
+        cache.sort();
+        cache
+    }
+
+    /// The shelf shows the cache as is, so it's kept in title order
+    fn sort(&mut self) {
+        if let Some(books) = self.books.as_mut() {
+            books.sort_by_cached_key(|book| book.title.to_lowercase());
+        }
     }
 
     pub fn update_books(&mut self, new_books: Option<Vec<Book>>) {
         self.books = new_books;
+        self.sort();
     }
     pub fn get_book_amount(&self) -> usize {
         match &self.books {
@@ -50,15 +70,102 @@ impl BookCache {
     pub fn find_by_title(&self, title: &str) -> Option<&Book> {
         self.books.as_ref()?.iter().find(|book| book.title == title)
     }
+    pub fn find_by_location(&self, book_location: &str) -> Option<&Book> {
+        self.books
+            .as_ref()?
+            .iter()
+            .find(|book| book.book_location == book_location)
+    }
+    /// Swaps out the cached book sharing the same location
+    pub fn replace_book(&mut self, new_book: Book) {
+        if let Some(book) = self.books.as_mut().and_then(|books| {
+            books
+                .iter_mut()
+                .find(|book| book.book_location == new_book.book_location)
+        }) {
+            *book = new_book;
+        }
+        // An edit may have changed the title
+        self.sort();
+    }
+    pub fn move_book(&mut self, book_location: &str, new_location: String) {
+        if let Some(book) = self.books.as_mut().and_then(|books| {
+            books
+                .iter_mut()
+                .find(|book| book.book_location == book_location)
+        }) {
+            book.book_location = new_location;
+        }
+    }
+    pub fn add_book(&mut self, book: Book) {
+        let books = self.books.get_or_insert_with(Vec::new);
+        let title = book.title.to_lowercase();
+        let position = books.partition_point(|existing| existing.title.to_lowercase() <= title);
+        books.insert(position, book);
+    }
+    pub fn remove_book(&mut self, book_location: &str) {
+        if let Some(books) = self.books.as_mut() {
+            books.retain(|book| book.book_location != book_location);
+        }
+    }
 }
 
 /// Used for handling books on the front end#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 
 #[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
 pub struct Book {
+    #[serde(default)]
+    #[sqlx(default)]
+    id: Option<i64>,
     cover_location: Option<String>,
     book_location: String,
     title: String,
+    #[serde(default)]
+    #[sqlx(default)]
+    authors: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    series: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    series_index: Option<f64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    description: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    rating: Option<i64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    date_added: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    last_read: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    progress: Option<f64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    read_status: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    date_started: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    date_finished: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    review: Option<String>,
+    #[serde(default)]
+    #[sqlx(default)]
+    word_count: Option<i64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    character_count: Option<i64>,
+    /// Counted when the book is read from its file, stored in book_chapters rather than the books row
+    #[serde(skip)]
+    #[sqlx(skip)]
+    chapter_lengths: Vec<ChapterLength>,
 }
 
 // Authors are creative right? surely there arent two books with the same title
@@ -72,69 +179,138 @@ impl Book {
     pub fn new(cover_location: Option<String>, book_location: String, title: String) -> Book {
         // Tries to write the cover image to 'cover_cache'
         // Otherwise uses default.jpg from /public
-        let final_cover_location = match cover_location {
-            Some(cover_loc) => Some(cover_loc),
-            None => {
-                let epub_doc = EpubDoc::new(&book_location)
-                    .map_err(|err| format!("Error opening EpubDoc: {}", err))
-                    .unwrap();
-
-                let covers_directory = get_cover_dir();
-
-                // why why why, am I supposed to make another object before the ensures everything is g? (unwrap)
-                let mut cover_name = epub_doc.mdata("title").unwrap();
-                cover_name.push_str(".jpg");
-                let cover_path_san = Self::sanitize_windows_filename(cover_name.clone());
-                let cover_path =
-                    &covers_directory.join(Self::sanitize_windows_filename(cover_name));
-
-                match get_book_cover_image(epub_doc) {
-                    Ok(cover_data) => match write_cover_image(cover_data, cover_path) {
-                        // I need the path as a string plz
-                        Ok(_) => Some(cover_path_san.clone()),
-                        Err(_) => {
-                            println!(
-                                "Wrote the file successfully but it was written into the abyss, perhaps a folder is missing from the 'cover_path'"
-                            );
-
-                            None
-                        }
-                    },
-                    Err(err) => {
-                        println!("{}", err);
-                        None
-                    }
-                }
-            }
-        };
+        let final_cover_location = cover_location.or_else(|| {
+            Self::extract_cover(&book_location, &title)
+                .map_err(|err| warn!("{}", err))
+                .ok()
+        });
 
         Book {
+            id: None,
             cover_location: final_cover_location,
             book_location,
             title,
+            authors: None,
+            series: None,
+            series_index: None,
+            description: None,
+            rating: None,
+            date_added: None,
+            last_read: None,
+            progress: None,
+            read_status: None,
+            date_started: None,
+            date_finished: None,
+            review: None,
+            word_count: None,
+            character_count: None,
+            chapter_lengths: Vec::new(),
         }
     }
 
+    pub fn with_title(mut self, title: String) -> Book {
+        self.title = title;
+        self
+    }
+
+    pub fn with_cover_location(mut self, cover_location: Option<String>) -> Book {
+        self.cover_location = cover_location;
+        self
+    }
+
+    pub fn with_authors(mut self, authors: Option<String>) -> Book {
+        self.authors = authors;
+        self
+    }
+
+    pub fn with_series(mut self, series: Option<String>, series_index: Option<f64>) -> Book {
+        self.series = series;
+        self.series_index = series_index;
+        self
+    }
+
+    pub fn with_description(mut self, description: Option<String>) -> Book {
+        self.description = description;
+        self
+    }
+
+    /// Sets the chapter lengths along with the books totals, none leaves the book uncounted
+    pub fn with_chapter_lengths(mut self, chapter_lengths: Option<Vec<ChapterLength>>) -> Book {
+        self.word_count = chapter_lengths
+            .as_ref()
+            .map(|chapters| chapters.iter().map(ChapterLength::get_word_count).sum());
+        self.character_count = chapter_lengths.as_ref().map(|chapters| {
+            chapters
+                .iter()
+                .map(ChapterLength::get_character_count)
+                .sum()
+        });
+        self.chapter_lengths = chapter_lengths.unwrap_or_default();
+        self
+    }
+
+    /// Ratings are stored out of 5, anything outside that is clamped
+    pub fn with_rating(mut self, rating: Option<i64>) -> Book {
+        self.rating = rating.map(|rating| rating.clamp(0, 5));
+        self
+    }
+
+    /// Writes the epubs cover into the cover cache, returning the file name it was saved under
+    ///
+    /// # Arguments
+    ///
+    /// * `book_location` - The epub to take the cover from
+    /// * `title` - The books title, the cover is named after it
+    ///
+    fn extract_cover(book_location: &str, title: &str) -> Result<String, ShelfError> {
+        let epub_doc = EpubDoc::new(book_location)
+            .map_err(|err| ShelfError::parse(book_location, err.to_string()))?;
+
+        let cover_name = Self::sanitize_windows_filename(format!("{}.jpg", title));
+        let cover_path = get_cover_dir().join(&cover_name);
+
+        let cover_data =
+            get_book_cover_image(epub_doc).map_err(|err| ShelfError::epub(book_location, err))?;
+        write_cover_image(cover_data, &cover_path)
+            .map_err(|err| ShelfError::epub(&cover_path, err))?;
+
+        Ok(cover_name)
+    }
+
     /// Removes special characters from a given string and returns it
-    /// Some book titles contain characters that aren't compatible when used as filenames
+    /// Some book titles contain characters that aren't compatible when used as filenames, Windows also refuses
+    /// names ending in a dot or space and device names like CON or LPT1
     ///
     /// # Arguments
     ///
     /// * `filename` - The filename to sanitize
     ///
-    fn sanitize_windows_filename(filename: String) -> String {
-        let disallowed_chars = vec!['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+    pub fn sanitize_windows_filename(filename: String) -> String {
+        let disallowed_chars = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+        let reserved_names = [
+            "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+            "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+        ];
 
         let sanitized: String = filename
             .chars()
             .map(|c| {
-                if disallowed_chars.contains(&c) {
+                if disallowed_chars.contains(&c) || c.is_control() {
                     '_'
                 } else {
                     c
                 }
             })
             .collect();
+        let mut sanitized = sanitized
+            .trim_end_matches(|c| c == '.' || c == ' ')
+            .to_string();
+
+        // The extension doesn't matter, "con.txt" is as reserved as "con"
+        let stem = sanitized.split('.').next().unwrap_or_default();
+        if reserved_names.contains(&stem.trim_end().to_uppercase().as_str()) {
+            sanitized.insert(stem.len(), '_');
+        }
 
         sanitized
     }
@@ -145,12 +321,17 @@ impl Book {
         cache_dir.push("cache");
         cache_dir.push(env!("COVER_IMAGE_FOLDER_NAME"));
         if let Err(err) = create_dir_all(&cache_dir) {
-            eprintln!("Error creating {:?} directory: {:?}", err, cache_dir);
+            error!("Error creating {:?} directory: {:?}", cache_dir, err);
         }
 
         cache_dir
     }
 
+    /// Only books loaded from the database have an id
+    pub fn get_id(&self) -> Option<i64> {
+        self.id
+    }
+
     pub fn get_title(&self) -> &String {
         &self.title
     }
@@ -167,7 +348,6 @@ impl Book {
     }
 
     pub fn get_cover_filename(&self) -> &str {
-        println!("get {:?}", self.cover_location);
         match &self.cover_location {
             Some(cover) => cover,
             None => env!("DEFAULT_COVER_NAME"),
@@ -177,6 +357,66 @@ impl Book {
     pub fn get_book_location(&self) -> &String {
         &self.book_location
     }
+
+    pub fn get_authors(&self) -> Option<&str> {
+        self.authors.as_deref()
+    }
+
+    pub fn get_series(&self) -> Option<&str> {
+        self.series.as_deref()
+    }
+
+    pub fn get_series_index(&self) -> Option<f64> {
+        self.series_index
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_date_added(&self) -> Option<&str> {
+        self.date_added.as_deref()
+    }
+
+    pub fn get_last_read(&self) -> Option<&str> {
+        self.last_read.as_deref()
+    }
+
+    pub fn get_progress(&self) -> Option<f64> {
+        self.progress
+    }
+
+    pub fn get_read_status(&self) -> Option<&str> {
+        self.read_status.as_deref()
+    }
+
+    pub fn get_date_started(&self) -> Option<&str> {
+        self.date_started.as_deref()
+    }
+
+    pub fn get_date_finished(&self) -> Option<&str> {
+        self.date_finished.as_deref()
+    }
+
+    pub fn get_review(&self) -> Option<&str> {
+        self.review.as_deref()
+    }
+
+    pub fn get_rating(&self) -> Option<i64> {
+        self.rating
+    }
+
+    pub fn get_word_count(&self) -> Option<i64> {
+        self.word_count
+    }
+
+    pub fn get_character_count(&self) -> Option<i64> {
+        self.character_count
+    }
+
+    pub fn get_chapter_lengths(&self) -> &[ChapterLength] {
+        &self.chapter_lengths
+    }
 }
 
 #[tauri::command]
@@ -191,84 +431,185 @@ pub fn get_cover_location_command(book: Book) -> String {
 /// * `title` - The title of the book to load
 ///
 #[tauri::command]
-pub fn load_book(title: String, state: State<'_, Mutex<BookWorker>>) -> Option<Book> {
+pub fn load_book(title: String, state: State<'_, Mutex<BookWorker>>) -> Result<Book, ShelfError> {
     let book_worker = state.lock().unwrap();
     let book_cache = book_worker.get_book_cache();
-    match book_cache.find_by_title(&title) {
-        Some(book) => Some(book.clone()),
-        None => None,
-    }
+    book_cache
+        .find_by_title(&title)
+        .cloned()
+        .ok_or_else(|| ShelfError::not_found(title))
 }
 
-pub fn get_all_books() -> Result<Vec<Book>, sqlx::Error> {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-    runtime.block_on(async {
-        let books = sqlx::query_as::<_, Book>("SELECT * FROM books")
-            .fetch_all(get_db())
-            .await?;
-        Ok(books)
-    })
+pub async fn get_all_books() -> Result<Vec<Book>, sqlx::Error> {
+    sqlx::query_as::<_, Book>("SELECT * FROM books ORDER BY title COLLATE NOCASE")
+        .fetch_all(get_db())
+        .await
 }
 
-pub fn drop_books_from_table() -> Result<SqliteQueryResult, sqlx::Error> {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-    runtime.block_on(async {
-        // dont format this line
-        Ok(sqlx::query("DELETE FROM books").execute(get_db()).await?)
-    })
-}
+/// Empties the library. The trash and import errors point at its books, so they're cleared with it
+pub async fn drop_books_from_table() -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    for statement in [
+        "DELETE FROM books",
+        "DELETE FROM trash",
+        "DELETE FROM import_errors",
+    ] {
+        sqlx::query(statement).execute(&mut *transaction).await?;
+    }
 
-pub fn create_books_table() -> Result<SqliteQueryResult, sqlx::Error> {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-    runtime.block_on(async {
-        // dont format this line
-        Ok(sqlx::query("CREATE TABLE IF NOT EXISTS books (id INTEGER PRIMARY KEY AUTOINCREMENT, cover_location TEXT NOT NULL, book_location TEXT NOT NULL, title TEXT NOT NULL);").execute(get_db()).await?)    })
+    transaction.commit().await
 }
 
 // TODO should add a checksum to the db along with the books
 // I imagine indexing the checksum would be faster in comparison to ILIKE
-pub fn get_book_on_name(name: String) -> Result<Option<Book>, sqlx::Error> {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-    runtime.block_on(async {
-        match sqlx::query_as("SELECT * FROM books WHERE title ILIKE $1")
-            .bind(&name)
-            .fetch_optional(get_db())
-            .await
-        {
-            Ok(book) => Ok(book),
-            Err(e) => Err(e),
-        }
-    })
+pub async fn get_book_on_name(name: &str) -> Result<Option<Book>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM books WHERE title = $1 COLLATE NOCASE")
+        .bind(name)
+        .fetch_optional(get_db())
+        .await
 }
 
 pub async fn insert_book_db(new_book: Book) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO books (cover_location, book_location, title) VALUES ($1, $2, $3)")
+    sqlx::query("INSERT INTO books (cover_location, book_location, title, authors, series, series_index, description, rating) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
         .bind(new_book.get_cover_filename())
         .bind(new_book.get_book_location())
         .bind(new_book.get_title())
+        .bind(new_book.get_authors())
+        .bind(new_book.get_series())
+        .bind(new_book.get_series_index())
+        .bind(new_book.get_description())
+        .bind(new_book.get_rating())
         .execute(get_db())
         .await?;
     Ok(())
 }
 
-pub fn insert_book_db_batch(new_book_batch: &Vec<Book>) -> Result<(), sqlx::Error> {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-    runtime.block_on(async {
-        let mut query_builder: sqlx::QueryBuilder<Sqlite> =
-            sqlx::QueryBuilder::new("INSERT INTO books (cover_location, book_location, title) ");
-
-        //TODO Might hit bind limits if users 'accumulates' books
-        query_builder.push_values(new_book_batch.iter(), |mut b, book| {
-            b.push_bind(book.get_cover_filename())
-                .push_bind(book.get_book_location())
-                .push_bind(book.get_title());
-        });
+/// Writes a books metadata back to its row, matched on the books location
+pub async fn update_book_db(book: &Book) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query("UPDATE books SET cover_location = $1, title = $2, authors = $3, series = $4, series_index = $5, description = $6, rating = $7 WHERE book_location = $8")
+        .bind(book.get_cover_filename())
+        .bind(book.get_title())
+        .bind(book.get_authors())
+        .bind(book.get_series())
+        .bind(book.get_series_index())
+        .bind(book.get_description())
+        .bind(book.get_rating())
+        .bind(book.get_book_location())
+        .execute(get_db())
+        .await
+}
 
-        let query = query_builder.build();
-        query.execute(get_db()).await?;
+/// Saves the detected series of several books in one transaction
+pub async fn update_book_series_batch(books: &[Book]) -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
 
-        Ok(())
-    })
+    for book in books {
+        sqlx::query("UPDATE books SET series = $1, series_index = $2 WHERE book_location = $3")
+            .bind(book.get_series())
+            .bind(book.get_series_index())
+            .bind(book.get_book_location())
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    transaction.commit().await
+}
+
+/// Builds an insert for a batch of books carrying everything a backup holds, reading state included
+///
+/// # Arguments
+///
+/// * `books` - The books to insert, keep batches small enough to stay under the bind limit
+///
+pub fn book_insert_query(books: &[Book]) -> QueryBuilder<'_, Sqlite> {
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "INSERT INTO books (cover_location, book_location, title, authors, series, series_index, description, rating,
+         date_added, last_read, progress, read_status, date_started, date_finished, review, word_count, character_count) ",
+    );
+
+    query_builder.push_values(books.iter(), |mut b, book| {
+        b.push_bind(book.get_cover_filename())
+            .push_bind(book.get_book_location())
+            .push_bind(book.get_title())
+            .push_bind(book.get_authors())
+            .push_bind(book.get_series())
+            .push_bind(book.get_series_index())
+            .push_bind(book.get_description())
+            .push_bind(book.get_rating())
+            .push_bind(book.get_date_added())
+            .push_bind(book.get_last_read())
+            .push_bind(book.get_progress())
+            .push_bind(book.get_read_status().unwrap_or("unread"))
+            .push_bind(book.get_date_started())
+            .push_bind(book.get_date_finished())
+            .push_bind(book.get_review())
+            .push_bind(book.get_word_count())
+            .push_bind(book.get_character_count());
+    });
+
+    query_builder
+}
+
+/// Inserts books in statements small enough to stay under sqlites bind limit
+async fn insert_book_rows(
+    connection: &mut SqliteConnection,
+    books: &[Book],
+) -> Result<(), sqlx::Error> {
+    for chunk in books.chunks(INSERT_CHUNK_SIZE) {
+        book_insert_query(chunk)
+            .build()
+            .execute(&mut *connection)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Inserts every book in one transaction, either all of them are added or none are
+///
+/// # Arguments
+///
+/// * `pool` - The pool to insert into, the database isn't global yet while it's being recovered
+/// * `new_book_batch` - The books to insert
+///
+#[instrument(skip_all, fields(count = new_book_batch.len()))]
+pub async fn insert_book_db_batch(
+    pool: &SqlitePool,
+    new_book_batch: &[Book],
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    insert_book_rows(&mut transaction, new_book_batch).await?;
+    transaction.commit().await
+}
+
+/// Inserts the books whose title isn't in the library yet, returning the ones that were added.
+/// Checking and inserting happen in one transaction so two scans can't add the same book
+///
+/// # Arguments
+///
+/// * `new_books` - Books read from the library folder, duplicates among them are dropped too
+///
+#[instrument(skip_all, fields(count = new_books.len()))]
+pub async fn insert_new_books(new_books: Vec<Book>) -> Result<Vec<Book>, sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    let mut titles: HashSet<String> = sqlx::query_scalar("SELECT title FROM books")
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .collect();
+    let unique_new_books: Vec<Book> = new_books
+        .into_iter()
+        .filter(|book| titles.insert(book.get_title().clone()))
+        .collect();
+
+    if !unique_new_books.is_empty() {
+        insert_book_rows(&mut transaction, &unique_new_books).await?;
+    }
+    transaction.commit().await?;
+
+    Ok(unique_new_books)
 }
 
 /// The current crate used for handling Epubs has some issues with finding covers for uniquely structured books
@@ -301,14 +642,16 @@ pub fn unique_find_cover(
             &mut doc,
         ) {
             Some(unique_cover_id) => {
-                let resource: Option<(Vec<u8>, String)> = doc.get_resource(&unique_cover_id);
-
-                let file_content = &resource.unwrap().0;
-                let buffer_str = String::from_utf8_lossy(file_content);
-                let root = Element::parse(buffer_str.as_bytes()).expect("Failed to parse XML");
+                let (file_content, _) = doc
+                    .get_resource(&unique_cover_id)
+                    .ok_or(BookError::ResourceNotFound)?;
+                let buffer_str = String::from_utf8_lossy(&file_content);
+                let root =
+                    Element::parse(buffer_str.as_bytes()).map_err(|_| BookError::XmlParseError)?;
                 match extract_image_source(&root) {
+                    // The source comes from the file, so it's matched literally
                     Some(image_element_src) => match check_epub_resource(
-                        Regex::new(&image_element_src).unwrap(),
+                        Regex::new(&regex::escape(&image_element_src)).unwrap(),
                         Regex::new(r"image/jpeg").unwrap(),
                         &epub_resources,
                         &mut doc,
@@ -1,6 +1,6 @@
 use std::{
     collections::HashMap,
-    fs::{self, create_dir_all, remove_dir_all, remove_file, File, OpenOptions},
+    fs::{create_dir_all, remove_dir_all, remove_file, File, OpenOptions},
     io::{BufReader, Error, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     sync::Mutex,
@@ -10,14 +10,14 @@ use tauri::{
     api::path::{app_cache_dir, app_config_dir},
     State,
 };
+use tracing::{error, warn};
 
 use crate::{
-    book::{bookio::create_book_vec, util::current_context},
-    book_item::{
-        create_books_table, drop_books_from_table, get_all_books, insert_book_db_batch, Book,
-        BookCache,
-    },
+    authors::index_book_authors,
+    book::{length::store_chapter_lengths, util::current_context},
+    book_item::{get_all_books, insert_new_books, Book, BookCache},
     database::{append_date_to_filename, check_db_health, import_book_json},
+    error::ShelfError,
     shelf::shelf_settings_values,
 };
 
@@ -46,118 +46,45 @@ impl BookWorker {
         &self.current_book_cache
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self) -> Result<(), ShelfError> {
         _ = remove_dir_all(get_cache_dir());
 
         //Delete settings file
         //If its an error thats okay because we remake the settings file anyway
         _ = remove_file(get_settings_path());
-        _ = drop_books_from_table();
 
         self.update_book_cache(None);
-        self.restore_default_settings();
-        _ = create_books_table();
+        self.restore_default_settings()
     }
 
     pub fn import_application_settings(&mut self, new_book_cache: HashMap<String, String>) {
         self.application_user_settings = new_book_cache
     }
 
-    pub fn backup_current_books(&mut self, write_dir: Option<String>) {
-        let json_dump_path = match write_dir {
-            Some(path) => {
-                let mut export_file_name = PathBuf::from(path);
-                export_file_name = export_file_name.join("export.json");
-
-                // is returned a string here
-                // export_file_name =
-
-                match export_file_name.to_str() {
-                    Some(valid_str) => Some(PathBuf::from(append_date_to_filename(valid_str))),
-                    None => None,
-                }
-            }
-            None => get_dump_json_path(),
-        };
-        match &self.get_book_cache().get_books() {
-            Some(all_books) => match json_dump_path {
-                Some(path) => {
-                    let file = File::create(path)
-                        .expect("JSON backup path should be defined, and a valid json file");
-
-                    serde_json::to_writer(file, &all_books)
-                        .expect("failed to write to backup json file!");
-                }
-                None => println!("Failed to make json dump file"),
-            },
-            None => match get_all_books() {
-                Ok(db_books) => match json_dump_path {
-                    Some(path) => {
-                        let file = File::create(path)
-                            .expect("JSON backup path should be defined, and a valid json file");
-
-                        serde_json::to_writer(file, &db_books)
-                            .expect("failed to write to backup json file!");
-                    }
-                    None => println!("Failed to make json dump file"),
-                },
-                Err(_) => println!("Failed to create backup, no books in memory or the database"),
-            },
-        }
-    }
-
-    // check if db file is missing
-    // run backup current books if it is
-    // run import method
-    pub fn repair_db(&mut self) {
-        if !check_db_health() {
-            self.backup_current_books(None);
-
-            _ = import_book_json(None);
-        }
-    }
-
     // TODO support multiple book location
     pub fn get_application_settings(&self) -> &HashMap<String, String> {
         &self.application_user_settings
     }
 
-    // Updates the book objects items
-    fn update_books(&mut self, new_books: Vec<Book>) {
-        let current_books = get_all_books()
-            .ok()
-            .or_else(|| self.get_book_cache().get_books().cloned());
-
-        let unique_new_books: Vec<_> = new_books
-            .into_iter()
-            .filter(|book| {
-                current_books
-                    .as_ref()
-                    .map_or(true, |books| !books.contains(book))
-            })
-            .collect();
-
-        if unique_new_books.is_empty() {
-            println!("No new unique books found or current_books was None");
-            println!("epub length different but no new books");
-            return;
-        }
+    // Swaps a single edited book in the cache
+    pub fn replace_book(&mut self, book: Book) {
+        self.current_book_cache.replace_book(book)
+    }
 
-        let mut all_books = self
-            .get_book_cache()
-            .get_books()
-            .cloned()
-            .unwrap_or_default();
-        all_books.extend(unique_new_books.clone());
+    // Points a cached book at the new location of its file
+    pub fn move_book(&mut self, book_location: &str, new_location: String) {
+        self.current_book_cache
+            .move_book(book_location, new_location)
+    }
 
-        // Update book contents in memory
-        self.update_book_cache(Some(all_books));
+    // Adds a book that came back into the library to the cache
+    pub fn add_book(&mut self, book: Book) {
+        self.current_book_cache.add_book(book)
+    }
 
-        // try to update local storage book contents
-        if insert_book_db_batch(&unique_new_books).is_err() {
-            println!("Failed to update books, dumping to backup file");
-            self.repair_db();
-        }
+    // Drops a book that left the library from the cache
+    pub fn remove_book(&mut self, book_location: &str) {
+        self.current_book_cache.remove_book(book_location)
     }
 
     // concat method
@@ -165,98 +92,185 @@ impl BookWorker {
         self.current_book_cache.update_books(new_books)
     }
 
-    pub fn restore_default_settings(&mut self) {
+    pub fn restore_default_settings(&mut self) -> Result<(), ShelfError> {
         let default_settings = shelf_settings_values();
 
         for (_setting_name, (lowercase_name, default_value)) in default_settings.iter() {
-            self.update_application_setting(lowercase_name.to_string(), default_value.to_string());
+            self.update_application_setting(lowercase_name.to_string(), default_value.to_string())?;
         }
+
+        Ok(())
     }
 
-    pub fn update_application_setting(&mut self, option_name: String, value: String) {
+    /// Changes a setting and saves it to the settings file
+    ///
+    /// # Arguments
+    ///
+    /// * `option_name` - The setting to change
+    /// * `value` - The new value
+    ///
+    pub fn update_application_setting(
+        &mut self,
+        option_name: String,
+        value: String,
+    ) -> Result<(), ShelfError> {
         self.application_user_settings
             .insert(option_name.clone(), value.clone());
 
         let settings_path = get_settings_path();
-        let mut file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(settings_path)
-            .unwrap();
-
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-
-        if let Some(index) = contents.find(&format!("{}=", option_name)) {
-            let start = index + option_name.len() + 1;
-
-            if let Some(end) = contents[start..].find('\n') {
-                // Option found with a newline character after
-                let mut new_contents = contents.clone();
-                let new_value = value;
-                new_contents.replace_range(start..start + end, &new_value);
-
-                // Overwrite the file with the updated contents
-                file.seek(SeekFrom::Start(0)).unwrap();
-                file.set_len(0).unwrap();
-                file.write_all(new_contents.as_bytes()).unwrap();
-            } else {
-                // Option found without a newline character after
-                let new_value = format!("{}\n", value);
-                contents.push_str(&new_value);
-
-                // Append the new line to the end of the file
-                file.seek(SeekFrom::End(0)).unwrap();
-                file.write_all(new_value.as_bytes()).unwrap();
-            }
+        write_setting(&settings_path, &option_name, value).map_err(|err| {
+            ShelfError::io(
+                format!("save the {} setting to", option_name),
+                &settings_path,
+                err,
+            )
+        })
+    }
+}
+
+fn write_setting(settings_path: &Path, option_name: &str, value: String) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(settings_path)?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    if let Some(index) = contents.find(&format!("{}=", option_name)) {
+        let start = index + option_name.len() + 1;
+
+        if let Some(end) = contents[start..].find('\n') {
+            // Option found with a newline character after
+            let mut new_contents = contents.clone();
+            let new_value = value;
+            new_contents.replace_range(start..start + end, &new_value);
+
+            // Overwrite the file with the updated contents
+            file.seek(SeekFrom::Start(0))?;
+            file.set_len(0)?;
+            file.write_all(new_contents.as_bytes())?;
         } else {
-            // Option not found, so add it with a newline character after
-            let new_line = format!("{}={}\n", option_name, value);
-            contents.push_str(&new_line);
+            // Option found without a newline character after
+            let new_value = format!("{}\n", value);
+            contents.push_str(&new_value);
 
             // Append the new line to the end of the file
-            file.seek(SeekFrom::End(0)).unwrap();
-            file.write_all(new_line.as_bytes()).unwrap();
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(new_value.as_bytes())?;
         }
+    } else {
+        // Option not found, so add it with a newline character after
+        let new_line = format!("{}={}\n", option_name, value);
+        contents.push_str(&new_line);
+
+        // Append the new line to the end of the file
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(new_line.as_bytes())?;
     }
 
-    pub fn initialize_books(&mut self) -> Option<Vec<Book>> {
-        let dir = self.get_application_settings().get("book_location")?;
+    Ok(())
+}
+
+// Functions that are related but need to be accessed elsewhere
 
-        if !Path::new(&dir).exists() {
-            return None;
+/// Writes the books to a json backup, the database comes first since only it holds read status, ratings and reviews
+///
+/// # Arguments
+///
+/// * `state` - The worker, its cache is used when the database can't be read
+/// * `write_dir` - The folder to export to, the default backup file is used when missing
+///
+pub async fn backup_current_books(
+    state: &Mutex<BookWorker>,
+    write_dir: Option<String>,
+) -> Result<(), ShelfError> {
+    let json_dump_path = match write_dir {
+        Some(path) => {
+            let export_file_name = PathBuf::from(path).join("export.json");
+
+            export_file_name
+                .to_str()
+                .map(|valid_str| PathBuf::from(append_date_to_filename(valid_str)))
         }
+        None => get_dump_json_path(),
+    };
+
+    let all_books = match get_all_books().await {
+        Ok(books) => Some(books),
+        Err(_) => state.lock().unwrap().get_book_cache().get_books().cloned(),
+    };
+
+    let all_books = all_books.ok_or_else(|| {
+        ShelfError::invalid("There's nothing to back up, no books in memory or the database")
+    })?;
+    let path = json_dump_path
+        .ok_or_else(|| ShelfError::invalid("The backup folder isn't a valid path"))?;
+
+    let file =
+        File::create(&path).map_err(|err| ShelfError::io("create the backup", &path, err))?;
+    serde_json::to_writer(file, &all_books)
+        .map_err(|err| ShelfError::io("write the backup", &path, err.into()))
+}
 
-        //yes you could break this, but im not being paid
-        // bug: swap out a already processed book with a new one
-        let epub_paths: Vec<String> = fs::read_dir(dir)
-            .ok()?
-            .filter_map(|entry| {
-                let path = entry.ok()?.path();
-                if path.is_file() && path.extension()? == "epub" {
-                    path.to_str().map(|s| s.to_owned())
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        if self.get_book_cache().get_book_amount() != epub_paths.len() {
-            self.update_books(create_book_vec(&epub_paths));
+// check if db file is missing
+// run backup current books if it is
+// run import method
+pub async fn repair_db(state: &Mutex<BookWorker>) {
+    if !check_db_health().await {
+        if let Err(err) = backup_current_books(state, None).await {
+            error!("{}", err);
         }
 
-        self.get_book_cache().get_books().cloned()
+        _ = import_book_json(None).await;
     }
 }
 
-// Functions that are related but need to be accessed elsewhere
+/// Saves the books that aren't in the library yet and adds them to the cache, returning how many were added.
+/// The worker is only locked for the cache update so other commands keep working while the books are written
+///
+/// # Arguments
+///
+/// * `state` - The worker holding the cache
+/// * `new_books` - Books read from the library folder
+///
+pub async fn add_new_books(
+    state: &Mutex<BookWorker>,
+    new_books: Vec<Book>,
+) -> Result<usize, sqlx::Error> {
+    let added_books = match insert_new_books(new_books).await {
+        Ok(added_books) => added_books,
+        Err(err) => {
+            error!("Failed to update books, dumping to backup file: {}", err);
+            repair_db(state).await;
+            return Err(err);
+        }
+    };
 
-#[tauri::command]
-pub fn backup_books_to_json(path: String, state: State<'_, Mutex<BookWorker>>) {
+    if let Err(err) = index_book_authors(&added_books).await {
+        warn!("Failed to index authors: {}", err);
+    }
+    if let Err(err) = store_chapter_lengths(&added_books).await {
+        warn!("Failed to store chapter lengths: {}", err);
+    }
+
+    let added = added_books.len();
     let mut book_worker = state.lock().unwrap();
+    for book in added_books {
+        book_worker.add_book(book);
+    }
 
-    book_worker.backup_current_books(Some(path));
+    Ok(added)
+}
+
+#[tauri::command]
+pub async fn backup_books_to_json(
+    path: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<(), ShelfError> {
+    backup_current_books(&state, Some(path)).await
 }
 
 pub fn get_settings_path() -> PathBuf {
@@ -267,7 +281,7 @@ pub fn get_cache_dir() -> PathBuf {
     let mut cache_dir = app_cache_dir(&current_context()).expect("Failed to get cache directory");
     cache_dir.push("cache");
     if let Err(err) = create_dir_all(&cache_dir) {
-        eprintln!("Error creating cache directory: {:?}", err);
+        error!("Error creating cache directory: {:?}", err);
     }
 
     cache_dir
@@ -280,6 +294,26 @@ pub fn get_dump_json_path() -> Option<PathBuf> {
     Some(path.join(env!("BACKUP_FILENAME")))
 }
 
+/// Where removed book files are kept so they can be brought back
+pub fn get_trash_dir() -> PathBuf {
+    let trash_dir = get_cache_dir().join(env!("TRASH_FOLDER_NAME"));
+    if let Err(err) = create_dir_all(&trash_dir) {
+        error!("Error creating trash directory: {:?}", err);
+    }
+
+    trash_dir
+}
+
+/// Where repaired copies of epubs go by default, outside the library so the scan doesn't pick them up
+pub fn get_repaired_dir() -> PathBuf {
+    let repaired_dir = get_cache_dir().join(env!("REPAIRED_FOLDER_NAME"));
+    if let Err(err) = create_dir_all(&repaired_dir) {
+        error!("Error creating repaired directory: {:?}", err);
+    }
+
+    repaired_dir
+}
+
 pub fn load_settings() -> HashMap<String, String> {
     let settings_path = get_settings_path();
 
@@ -314,6 +348,7 @@ pub fn create_default_settings() -> Result<File, Error> {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(true)
         .open(get_settings_path())
         .expect("Failed to open or create settings file");
 
@@ -339,7 +374,7 @@ pub fn get_config_dir() -> PathBuf {
     full_config_path.push(env!("CONFIG_FLDR_NAME"));
 
     if let Err(err) = create_dir_all(&full_config_path) {
-        eprintln!("Error creating config directory: {:?}", err);
+        error!("Error creating config directory: {:?}", err);
     }
 
     full_config_path
@@ -0,0 +1,291 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use epub::doc::EpubDoc;
+use rayon::{
+    iter::Either,
+    prelude::{IntoParallelIterator, ParallelIterator},
+};
+use serde::Serialize;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    FromRow, Sqlite, Transaction,
+};
+use tauri::State;
+use tracing::warn;
+
+use crate::{
+    authors::index_unindexed_books,
+    book::util::get_cover_dir,
+    book_item::{get_all_books, Book},
+    book_worker::BookWorker,
+    database::get_db,
+    error::ShelfError,
+};
+
+// Calibre keeps one row per book, everything else hangs off link tables
+static CALIBRE_BOOKS_QUERY: &str = "SELECT b.id, b.title, b.path, b.has_cover, b.series_index,
+    (SELECT group_concat(a.name, ' & ') FROM books_authors_link l JOIN authors a ON a.id = l.author WHERE l.book = b.id) AS authors,
+    (SELECT s.name FROM books_series_link l JOIN series s ON s.id = l.series WHERE l.book = b.id) AS series,
+    (SELECT r.rating FROM books_ratings_link l JOIN ratings r ON r.id = l.rating WHERE l.book = b.id) AS rating,
+    (SELECT c.text FROM comments c WHERE c.book = b.id) AS description,
+    (SELECT d.name FROM data d WHERE d.book = b.id AND upper(d.format) = 'EPUB') AS epub_name
+    FROM books b ORDER BY b.sort";
+
+/// A book as calibre sees it, only the columns we map into shelf
+#[derive(FromRow, Debug)]
+struct CalibreBook {
+    id: i64,
+    title: String,
+    path: String,
+    has_cover: bool,
+    series_index: f64,
+    authors: Option<String>,
+    series: Option<String>,
+    rating: Option<i64>,
+    description: Option<String>,
+    epub_name: Option<String>,
+}
+
+/// Summary of an import, handed back to the frontend
+#[derive(Serialize, Debug, Default)]
+pub struct CalibreImportReport {
+    imported: usize,
+    already_in_library: Vec<String>,
+    without_epub: Vec<String>,
+    unreadable: Vec<String>,
+}
+
+/// Copies calibres cover.jpg into our cover cache, returning the cached file name
+///
+/// # Arguments
+///
+/// * `book_dir` - The calibre folder holding the book and its cover
+/// * `title` - The books title, used to name the cached cover
+///
+fn copy_calibre_cover(book_dir: &Path, title: &str) -> Option<String> {
+    let cover_name = Book::sanitize_windows_filename(format!("{}.jpg", title));
+
+    fs::copy(
+        book_dir.join("cover.jpg"),
+        get_cover_dir().join(&cover_name),
+    )
+    .ok()
+    .map(|_| cover_name)
+}
+
+async fn insert_calibre_book(
+    transaction: &mut Transaction<'_, Sqlite>,
+    book: &Book,
+    tags: &[String],
+    identifiers: &[(String, String)],
+) -> Result<(), sqlx::Error> {
+    let book_id = sqlx::query("INSERT INTO books (cover_location, book_location, title, authors, series, series_index, description, rating) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+        .bind(book.get_cover_filename())
+        .bind(book.get_book_location())
+        .bind(book.get_title())
+        .bind(book.get_authors())
+        .bind(book.get_series())
+        .bind(book.get_series_index())
+        .bind(book.get_description())
+        .bind(book.get_rating())
+        .execute(&mut **transaction)
+        .await?
+        .last_insert_rowid();
+
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES ($1, $2)")
+            .bind(book_id)
+            .bind(tag)
+            .execute(&mut **transaction)
+            .await?;
+    }
+
+    for (kind, value) in identifiers {
+        sqlx::query(
+            "INSERT OR REPLACE INTO book_identifiers (book_id, kind, value) VALUES ($1, $2, $3)",
+        )
+        .bind(book_id)
+        .bind(kind)
+        .bind(value)
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Reads a calibre library and adds its epubs to the books table, the files themselves are left where they are
+///
+/// # Arguments
+///
+/// * `library_dir` - The calibre library folder, the one containing metadata.db
+///
+async fn import_calibre_db(library_dir: &Path) -> Result<CalibreImportReport, sqlx::Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(library_dir.join("metadata.db"))
+        .read_only(true);
+    let calibre_db = SqlitePool::connect_with(options).await?;
+
+    let calibre_books = sqlx::query_as::<_, CalibreBook>(CALIBRE_BOOKS_QUERY)
+        .fetch_all(&calibre_db)
+        .await?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for (book_id, tag) in sqlx::query_as::<_, (i64, String)>(
+        "SELECT l.book, t.name FROM books_tags_link l JOIN tags t ON t.id = l.tag",
+    )
+    .fetch_all(&calibre_db)
+    .await?
+    {
+        tags.entry(book_id).or_default().push(tag);
+    }
+
+    let mut identifiers: HashMap<i64, Vec<(String, String)>> = HashMap::new();
+    for (book_id, kind, value) in
+        sqlx::query_as::<_, (i64, String, String)>("SELECT book, type, val FROM identifiers")
+            .fetch_all(&calibre_db)
+            .await?
+    {
+        identifiers.entry(book_id).or_default().push((kind, value));
+    }
+
+    calibre_db.close().await;
+
+    let existing_books = sqlx::query_as::<_, Book>("SELECT * FROM books")
+        .fetch_all(get_db())
+        .await?;
+    let mut known_locations: HashSet<String> = existing_books
+        .iter()
+        .map(|book| book.get_book_location().clone())
+        .collect();
+    let mut known_titles: HashSet<String> = existing_books
+        .iter()
+        .map(|book| book.get_title().clone())
+        .collect();
+
+    let mut report = CalibreImportReport::default();
+    let mut candidates = Vec::new();
+
+    for calibre_book in calibre_books {
+        let Some(epub_name) = &calibre_book.epub_name else {
+            report.without_epub.push(calibre_book.title);
+            continue;
+        };
+
+        let book_dir = library_dir.join(&calibre_book.path);
+        let book_location = book_dir
+            .join(format!("{}.epub", epub_name))
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if known_locations.contains(&book_location) || known_titles.contains(&calibre_book.title) {
+            report.already_in_library.push(calibre_book.title);
+            continue;
+        }
+
+        known_locations.insert(book_location.clone());
+        known_titles.insert(calibre_book.title.clone());
+        candidates.push((calibre_book, book_dir, book_location));
+    }
+
+    // Reading the epubs and copying covers is blocking, it's done before the transaction is opened
+    let (books, unreadable): (Vec<(i64, Book)>, Vec<String>) =
+        tauri::async_runtime::spawn_blocking(move || {
+            candidates
+                .into_par_iter()
+                .partition_map(|(calibre_book, book_dir, book_location)| {
+                    if !Path::new(&book_location).exists() {
+                        return Either::Right(book_location);
+                    }
+
+                    let cover_location = if calibre_book.has_cover {
+                        copy_calibre_cover(&book_dir, &calibre_book.title)
+                    } else {
+                        None
+                    };
+                    // Without a cover from calibre Book::new digs one out of the epub, which needs to open
+                    if cover_location.is_none() && EpubDoc::new(&book_location).is_err() {
+                        return Either::Right(book_location);
+                    }
+
+                    let book = Book::new(cover_location, book_location, calibre_book.title)
+                        .with_authors(calibre_book.authors)
+                        // Calibre gives every book an index, even the ones without a series
+                        .with_series(
+                            calibre_book.series.clone(),
+                            calibre_book
+                                .series
+                                .as_ref()
+                                .map(|_| calibre_book.series_index),
+                        )
+                        .with_description(calibre_book.description)
+                        // Calibre rates out of 10 so it can show half stars
+                        .with_rating(calibre_book.rating.map(|rating| rating / 2));
+
+                    Either::Left((calibre_book.id, book))
+                })
+        })
+        .await
+        .map_err(|err| sqlx::Error::Protocol(err.to_string()))?;
+    report.unreadable = unreadable;
+
+    let mut transaction = get_db().begin().await?;
+
+    for (calibre_id, book) in &books {
+        insert_calibre_book(
+            &mut transaction,
+            book,
+            tags.get(calibre_id).map_or(&[], |tags| tags),
+            identifiers
+                .get(calibre_id)
+                .map_or(&[], |identifiers| identifiers),
+        )
+        .await?;
+
+        report.imported += 1;
+    }
+
+    transaction.commit().await?;
+
+    Ok(report)
+}
+
+/// Imports the books from a calibre library without copying them
+///
+/// # Arguments
+///
+/// * `library_path` - The calibre library folder, the one containing metadata.db
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn import_calibre_library(
+    library_path: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<CalibreImportReport, ShelfError> {
+    let library_dir = PathBuf::from(library_path);
+
+    if !library_dir.join("metadata.db").exists() {
+        return Err(ShelfError::parse(
+            &library_dir,
+            "it's not a calibre library, metadata.db is missing",
+        ));
+    }
+
+    let report = import_calibre_db(&library_dir)
+        .await
+        .map_err(|err| ShelfError::database("import the calibre library", err))?;
+
+    if let Err(err) = index_unindexed_books().await {
+        warn!("Failed to index authors: {}", err);
+    }
+
+    // The dashboard reads from the cache, so it needs the new books too
+    let books = get_all_books().await.ok();
+    state.lock().unwrap().update_book_cache(books);
+
+    Ok(report)
+}
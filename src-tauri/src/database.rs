@@ -1,56 +1,276 @@
 use std::{
     fs::{self, File},
+    future::Future,
     io::BufReader,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-use sqlx::sqlite::{
-    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqliteQueryResult, SqliteSynchronous,
-};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqliteSynchronous};
 
 use time::{format_description::parse, OffsetDateTime};
-use tokio::sync::OnceCell;
+use tokio::{runtime::Handle, sync::OnceCell, task};
+use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
     book::util::is_file_empty,
-    book_item::{create_books_table, insert_book_db_batch, Book},
+    book_item::{insert_book_db_batch, Book},
     book_worker::{get_cache_dir, get_dump_json_path},
+    error::ShelfError,
 };
 
 static DB: OnceCell<SqlitePool> = OnceCell::const_new();
+static RECOVERY_REPORT: OnceCell<DbRecoveryReport> = OnceCell::const_new();
+// A snapshot younger than this is kept, VACUUM INTO copies the whole database
+static SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
 
-async fn create_pool() -> SqlitePool {
-    let database_name = env!("DATABASE_FILENAME");
-    let db_location = get_cache_dir().join(database_name);
+/// What the startup integrity check found, and what was done about it
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DbRecoveryReport {
+    corruption_detected: bool,
+    integrity_errors: Vec<String>,
+    quarantined_database: Option<String>,
+    restored_from: Option<String>,
+    books_recovered: usize,
+    rescan_required: bool,
+}
 
+fn get_db_path() -> PathBuf {
+    get_cache_dir().join(env!("DATABASE_FILENAME"))
+}
+
+fn get_db_snapshot_path() -> PathBuf {
+    get_cache_dir().join(env!("DATABASE_SNAPSHOT_NAME"))
+}
+
+async fn open_pool(db_location: &Path) -> Result<SqlitePool, sqlx::Error> {
     let options = SqliteConnectOptions::new()
-        .filename(&db_location) // Use the correct database file path
+        .filename(db_location)
         .create_if_missing(true)
-        .synchronous(SqliteSynchronous::Off)
-        .journal_mode(SqliteJournalMode::Memory);
+        .synchronous(SqliteSynchronous::Normal)
+        .journal_mode(SqliteJournalMode::Wal);
+
+    SqlitePool::connect_with(options).await
+}
+
+async fn create_pool(db_location: &Path) -> Result<SqlitePool, sqlx::Error> {
+    let pool = open_pool(db_location).await?;
+
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Whether opening the database failed because the file is damaged or isn't a database at all,
+/// rather than something like a lock or missing permissions
+fn is_corruption_error(err: &sqlx::Error) -> bool {
+    // SQLITE_CORRUPT and SQLITE_NOTADB, extended codes keep the primary code in the low byte
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 11 | 26))
+}
+
+/// Runs quick_check on a read only connection before the database is opened for writing. Sqlite checkpoints
+/// and deletes the WAL files when a writable connection closes, even on a file it couldn't read, so checking
+/// read only keeps them for the quarantined copy. Failures other than a damaged file are returned as errors
+///
+/// # Arguments
+///
+/// * `db_location` - The database to check
+///
+async fn check_integrity(db_location: &Path) -> Result<Vec<String>, sqlx::Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(db_location)
+        .read_only(true);
+
+    let checked = match SqlitePool::connect_with(options).await {
+        Ok(pool) => {
+            let checked = sqlx::query_scalar::<_, String>("PRAGMA quick_check")
+                .fetch_all(&pool)
+                .await;
+            pool.close().await;
+            checked.map(|rows| rows.into_iter().filter(|row| row != "ok").collect())
+        }
+        Err(err) => Err(err),
+    };
+
+    match checked {
+        Err(err) if is_corruption_error(&err) => Ok(vec![err.to_string()]),
+        checked => checked,
+    }
+}
+
+/// Runs sqlites quick_check, returning every problem it reported. An empty vector means the database is fine
+///
+/// # Arguments
+///
+/// * `pool` - The pool to check
+///
+async fn run_integrity_check(pool: &SqlitePool) -> Vec<String> {
+    match sqlx::query_scalar::<_, String>("PRAGMA quick_check")
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows.into_iter().filter(|row| row != "ok").collect(),
+        // Files that arent a database at all fail here instead of returning rows
+        Err(err) => vec![err.to_string()],
+    }
+}
+
+/// Copies the current (healthy) database next to itself so we have something to fall back on.
+/// Skipped while the last snapshot is younger than SNAPSHOT_MAX_AGE
+async fn snapshot_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let snapshot_path = get_db_snapshot_path();
+    let snapshot_age = fs::metadata(&snapshot_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    if snapshot_age.is_some_and(|age| age < SNAPSHOT_MAX_AGE) {
+        debug!("Keeping the database snapshot from {:?} ago", snapshot_age);
+        return Ok(());
+    }
+
+    let temp_path = snapshot_path.with_extension("tmp");
+
+    // VACUUM INTO refuses to overwrite existing files
+    _ = fs::remove_file(&temp_path);
+    sqlx::query("VACUUM INTO $1")
+        .bind(temp_path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+    fs::rename(&temp_path, &snapshot_path)?;
+
+    Ok(())
+}
+
+/// Moves the database, along with its WAL files, out of the way so a new one can be created.
+/// The WAL files keep their suffix so sqlite still reads the pages that weren't checkpointed when the copy is opened
+fn quarantine_db(db_location: &Path) -> Option<String> {
+    let quarantine_path =
+        append_date_to_filename(&db_location.with_extension("corrupt.db").to_string_lossy());
+    let side_files =
+        |path: &str| ["-wal", "-shm"].map(|suffix| PathBuf::from(format!("{}{}", path, suffix)));
+
+    match fs::rename(db_location, &quarantine_path) {
+        Ok(()) => {
+            let moved = side_files(&quarantine_path);
+            for (side_file, destination) in
+                side_files(&db_location.to_string_lossy()).iter().zip(moved)
+            {
+                if side_file.exists() {
+                    if let Err(err) = fs::rename(side_file, &destination) {
+                        error!("Failed to move {:?} aside {:?}", side_file, err);
+                        _ = fs::remove_file(side_file);
+                    }
+                }
+            }
+            Some(quarantine_path)
+        }
+        Err(err) => {
+            error!("Failed to move the corrupt database aside {:?}", err);
+            _ = fs::remove_file(db_location);
+            for side_file in side_files(&db_location.to_string_lossy()) {
+                _ = fs::remove_file(side_file);
+            }
+            None
+        }
+    }
+}
 
-    let pool = SqlitePool::connect_with(options).await.expect(&format!(
-        "could not connect to database_url: {:?}",
-        db_location
-    ));
+/// Looks for the newest json backup, preferring the unspent one
+fn find_latest_json_backup() -> Option<PathBuf> {
+    let backup_path = get_dump_json_path()?;
+    if backup_path.exists() {
+        return Some(backup_path);
+    }
 
-    // sqlx::migrate!(".\\migrations")
-    //     .run(&pool)
-    //     .await
-    //     .expect("migrations failed");
-    _ = create_books_table_init();
-    pool
+    // Spent backups are renamed to backup_YYYYMMDD.json, so the name sorts by date
+    let stem = backup_path.file_stem()?.to_string_lossy().to_string();
+    fs::read_dir(backup_path.parent()?)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let file_name = path.file_name()?.to_string_lossy().to_string();
+            (file_name.starts_with(&format!("{}_", stem)) && file_name.ends_with(".json"))
+                .then_some(path)
+        })
+        .max()
 }
 
-async fn create_books_table_init() -> Result<SqliteQueryResult, sqlx::Error> {
-    // dont format this line
-    Ok(sqlx::query("CREATE TABLE IF NOT EXISTS books (id INTEGER PRIMARY KEY AUTOINCREMENT, cover_location TEXT NOT NULL, book_location TEXT NOT NULL, title TEXT NOT NULL);").execute(get_db()).await?)
+async fn restore_books(pool: &SqlitePool, books: &[Book]) -> Result<usize, sqlx::Error> {
+    insert_book_db_batch(pool, books).await?;
+
+    Ok(books.len())
+}
+
+/// Rebuilds a corrupt database, first from the last good snapshot and otherwise from the newest json backup.
+/// If neither exists an empty database is created and the next scan will fill it back up
+async fn recover_db(
+    db_location: &Path,
+    report: &mut DbRecoveryReport,
+) -> Result<SqlitePool, sqlx::Error> {
+    report.quarantined_database = quarantine_db(db_location);
+
+    let snapshot_path = get_db_snapshot_path();
+    if snapshot_path.exists() && fs::copy(&snapshot_path, db_location).is_ok() {
+        if let Ok(pool) = create_pool(db_location).await {
+            if run_integrity_check(&pool).await.is_empty() {
+                report.restored_from = Some(snapshot_path.to_string_lossy().to_string());
+                report.books_recovered = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM books")
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap_or(0) as usize;
+
+                return Ok(pool);
+            }
+            pool.close().await;
+        }
+        warn!("The database snapshot is also damaged, ignoring it");
+        _ = fs::remove_file(db_location);
+    }
+
+    let pool = create_pool(db_location).await?;
+
+    let backup_books = find_latest_json_backup().and_then(|backup_path| {
+        let file = File::open(&backup_path).ok()?;
+        let books: Vec<Book> = serde_json::from_reader(BufReader::new(file)).ok()?;
+        Some((backup_path, books))
+    });
+
+    match backup_books {
+        Some((backup_path, books)) => match restore_books(&pool, &books).await {
+            Ok(recovered) => {
+                report.restored_from = Some(backup_path.to_string_lossy().to_string());
+                report.books_recovered = recovered;
+
+                // Spend the backup so the startup import doesnt add the same books again
+                if Some(&backup_path) == get_dump_json_path().as_ref() {
+                    let spent_file_name = append_date_to_filename(&backup_path.to_string_lossy());
+                    _ = fs::rename(&backup_path, spent_file_name);
+                }
+            }
+            Err(err) => {
+                error!("Failed to restore the json backup {:?}", err);
+                report.rescan_required = true;
+            }
+        },
+        None => report.rescan_required = true,
+    }
+
+    Ok(pool)
 }
-pub fn check_db_health() -> bool {
-    let binding = get_cache_dir().join(env!("DATABASE_FILENAME"));
-    let db_path = Path::new(&binding);
 
-    db_path.exists() && !is_file_empty(db_path)
+/// Checks the database file exists, has content and passes sqlites quick_check
+pub async fn check_db_health() -> bool {
+    let db_path = get_db_path();
+
+    if !db_path.exists() || is_file_empty(&db_path) {
+        return false;
+    }
+
+    run_integrity_check(get_db()).await.is_empty()
 }
 
 // Path includes the file name
@@ -70,13 +290,13 @@ pub fn append_date_to_filename(file_path: &str) -> String {
 }
 
 #[tauri::command]
-pub fn import_book_json_comm(backup_path: String) {
-    _ = import_book_json(Some(PathBuf::from(backup_path)));
+pub async fn import_book_json_comm(backup_path: String) -> Result<(), ShelfError> {
+    import_book_json(Some(PathBuf::from(&backup_path)))
+        .await
+        .map_err(|err| ShelfError::io("restore the backup", backup_path, err))
 }
-pub fn import_book_json(backup_path: Option<PathBuf>) -> Result<(), std::io::Error> {
-    // Since the db file doesn't exist, we need to remake the table. sqlx will handle recreating the file.
-    _ = create_books_table();
-
+#[instrument]
+pub async fn import_book_json(backup_path: Option<PathBuf>) -> Result<(), std::io::Error> {
     let backup_path = backup_path.or_else(get_dump_json_path);
 
     if let Some(backup_path) = backup_path {
@@ -85,36 +305,83 @@ pub fn import_book_json(backup_path: Option<PathBuf>) -> Result<(), std::io::Err
             let old_books: Vec<Book> =
                 serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|_| Vec::new());
 
-            match insert_book_db_batch(&old_books) {
+            match insert_book_db_batch(get_db(), &old_books).await {
                 Ok(()) => {
-                    println!("Restored backup containing {:?} books!", &old_books.len());
+                    info!("Restored backup containing {:?} books!", &old_books.len());
 
                     let spent_file_name = append_date_to_filename(backup_path.to_str().unwrap());
 
                     fs::rename(&backup_path, spent_file_name)?;
                 }
                 Err(e) => {
-                    println!(
+                    error!(
                         "Hurray, something went wrong while restoring the backup {:?}",
                         e
                     );
                 }
             };
         } else {
-            println!("Backup path does not exist");
+            debug!("Backup path does not exist");
         }
     } else {
-        println!("No valid backup path provided or found");
+        debug!("No valid backup path provided or found");
     }
 
     Ok(())
 }
 
-pub async fn init_db() {
-    //TODO: Handle result haha
+/// Opens the database, rebuilding it when the integrity check finds it damaged.
+/// Other failures, like a migration that doesn't apply or a locked file, are returned with the file left alone
+#[instrument]
+pub async fn init_db() -> Result<(), sqlx::Error> {
+    let db_location = get_db_path();
+    let mut report = DbRecoveryReport::default();
+
+    // A missing file is created by open_pool, there's nothing to check yet
+    let integrity_errors = if db_location.exists() {
+        check_integrity(&db_location).await?
+    } else {
+        Vec::new()
+    };
+
+    let pool = if integrity_errors.is_empty() {
+        let pool = open_pool(&db_location).await?;
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        if let Err(err) = snapshot_db(&pool).await {
+            warn!("Failed to snapshot the database {:?}", err);
+        }
+        pool
+    } else {
+        report.integrity_errors = integrity_errors;
+        report.corruption_detected = true;
+        recover_db(&db_location, &mut report).await?
+    };
+
+    if report.corruption_detected {
+        warn!("Recovered from a corrupt database {:?}", report);
+    }
 
-    DB.set(create_pool().await)
+    DB.set(pool)
         .expect("Fail to init DB, is the server running in the same node?");
+    _ = RECOVERY_REPORT.set(report);
+
+    Ok(())
+}
+
+/// Returns what the startup database check found, letting the frontend tell the user what was recovered
+#[tauri::command]
+pub fn get_db_recovery_report() -> Option<DbRecoveryReport> {
+    RECOVERY_REPORT.get().cloned()
+}
+
+/// Runs async database work from synchronous code, like the rayon workers or the opds server thread.
+/// Everything shares tauris runtime instead of starting one per call, inside an async task the worker
+/// thread is handed over while it blocks
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match Handle::try_current() {
+        Ok(handle) => task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tauri::async_runtime::block_on(future),
+    }
 }
 
 pub fn get_db<'a>() -> &'a SqlitePool {
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::{database::get_db, error::ShelfError};
+
+/// One look up, with the book and sentence it was read in
+#[derive(Serialize, FromRow, Debug)]
+pub struct Lookup {
+    id: i64,
+    /// As it was selected, like "running"
+    word: String,
+    /// The entry it was found under, like "run"
+    headword: String,
+    /// None when it wasn't looked up from a book or the book was removed since
+    book_location: Option<String>,
+    title: Option<String>,
+    context: Option<String>,
+    /// UTC
+    looked_up_at: String,
+}
+
+/// A word that was looked up, for reviewing vocabulary
+#[derive(Serialize, FromRow, Debug)]
+pub struct VocabularyWord {
+    headword: String,
+    /// How many times it was looked up
+    lookups: i64,
+    /// UTC
+    first_looked_up: String,
+    last_looked_up: String,
+}
+
+/// Records a word that was found in the dictionaries
+///
+/// # Arguments
+///
+/// * `word` - The word as it was selected
+/// * `headword` - The entry it was found under
+/// * `book_location` - The book it was read in, books that aren't in the library are left out
+/// * `context` - The sentence it was read in
+///
+pub async fn record_lookup(
+    word: &str,
+    headword: &str,
+    book_location: Option<&str>,
+    context: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO dictionary_lookups (word, headword, book_id, context)
+         VALUES ($1, $2, (SELECT id FROM books WHERE book_location = $3), $4)",
+    )
+    .bind(word)
+    .bind(headword)
+    .bind(book_location)
+    .bind(context.map(str::trim).filter(|context| !context.is_empty()))
+    .execute(get_db())
+    .await
+    .map(|_| ())
+}
+
+/// Lists the look ups, most recent first
+///
+/// # Arguments
+///
+/// * `headword` - Only the look ups of this entry, to see the sentences a word was met in
+/// * `limit` - How many to return, defaults to 100
+///
+#[tauri::command]
+pub async fn get_lookup_history(
+    headword: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<Lookup>, ShelfError> {
+    sqlx::query_as::<_, Lookup>(
+        "SELECT l.id, l.word, l.headword, b.book_location, b.title, l.context, l.looked_up_at
+         FROM dictionary_lookups l LEFT JOIN books b ON b.id = l.book_id
+         WHERE $1 IS NULL OR l.headword = $1
+         ORDER BY l.looked_up_at DESC, l.id DESC
+         LIMIT $2",
+    )
+    .bind(headword)
+    .bind(limit.unwrap_or(100).max(1))
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load the lookup history", err))
+}
+
+/// The words that were looked up with how often, the most recently looked up first
+#[tauri::command]
+pub async fn get_vocabulary() -> Result<Vec<VocabularyWord>, ShelfError> {
+    sqlx::query_as::<_, VocabularyWord>(
+        "SELECT headword, COUNT(*) AS lookups,
+             MIN(looked_up_at) AS first_looked_up, MAX(looked_up_at) AS last_looked_up
+         FROM dictionary_lookups
+         GROUP BY headword
+         ORDER BY last_looked_up DESC",
+    )
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load the vocabulary", err))
+}
+
+/// Drops a word from the vocabulary along with every time it was looked up, for words that were learnt
+/// Returns how many look ups were removed
+///
+/// # Arguments
+///
+/// * `headword` - The word to drop
+///
+#[tauri::command]
+pub async fn forget_vocabulary_word(headword: String) -> Result<u64, ShelfError> {
+    sqlx::query("DELETE FROM dictionary_lookups WHERE headword = $1")
+        .bind(headword)
+        .execute(get_db())
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|err| ShelfError::database("forget the word", err))
+}
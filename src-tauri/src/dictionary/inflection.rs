@@ -0,0 +1,132 @@
+// English words whose base form can't be found by taking a suffix off
+static IRREGULAR_FORMS: [(&str, &str); 48] = [
+    ("am", "be"),
+    ("are", "be"),
+    ("is", "be"),
+    ("was", "be"),
+    ("were", "be"),
+    ("been", "be"),
+    ("has", "have"),
+    ("had", "have"),
+    ("did", "do"),
+    ("done", "do"),
+    ("went", "go"),
+    ("gone", "go"),
+    ("said", "say"),
+    ("made", "make"),
+    ("took", "take"),
+    ("taken", "take"),
+    ("came", "come"),
+    ("saw", "see"),
+    ("seen", "see"),
+    ("knew", "know"),
+    ("known", "know"),
+    ("thought", "think"),
+    ("brought", "bring"),
+    ("bought", "buy"),
+    ("caught", "catch"),
+    ("taught", "teach"),
+    ("found", "find"),
+    ("gave", "give"),
+    ("given", "give"),
+    ("told", "tell"),
+    ("felt", "feel"),
+    ("left", "leave"),
+    ("kept", "keep"),
+    ("began", "begin"),
+    ("begun", "begin"),
+    ("wrote", "write"),
+    ("written", "write"),
+    ("better", "good"),
+    ("best", "good"),
+    ("worse", "bad"),
+    ("worst", "bad"),
+    ("children", "child"),
+    ("men", "man"),
+    ("women", "woman"),
+    ("feet", "foot"),
+    ("teeth", "tooth"),
+    ("mice", "mouse"),
+    ("people", "person"),
+];
+
+// Suffixes of English inflections and what replaces them, the first that matches is tried first
+static SUFFIX_RULES: [(&str, &str); 27] = [
+    ("'s", ""),
+    ("s'", "s"),
+    ("ies", "y"),
+    ("ves", "f"),
+    ("ves", "fe"),
+    ("sses", "ss"),
+    ("xes", "x"),
+    ("zes", "z"),
+    ("ches", "ch"),
+    ("shes", "sh"),
+    ("oes", "o"),
+    ("s", ""),
+    ("ied", "y"),
+    ("ed", ""),
+    ("ed", "e"),
+    ("ying", "ie"),
+    ("ing", ""),
+    ("ing", "e"),
+    ("ier", "y"),
+    ("iest", "y"),
+    ("er", ""),
+    ("er", "e"),
+    ("est", ""),
+    ("est", "e"),
+    ("ily", "y"),
+    ("ly", ""),
+    ("ly", "le"),
+];
+
+// Stems this short are more likely another word than the base form
+static MIN_STEM_LENGTH: usize = 2;
+
+/// Undoes a doubled final consonant, like "stopp" from "stopped" to "stop"
+fn undouble(stem: &str) -> Option<&str> {
+    let mut letters = stem.chars().rev();
+    let (last, before) = (letters.next()?, letters.next()?);
+
+    (last == before && !matches!(last, 'a' | 'e' | 'i' | 'o' | 'u' | 'l' | 's' | 'z'))
+        .then(|| &stem[..stem.len() - last.len_utf8()])
+}
+
+/// The forms to look a word up under, the word itself first and then the base forms it could be
+/// an inflection of, like "run" for "running". The rules are for English, other languages only get
+/// the word itself. Forms that don't exist are expected, the dictionaries decide which one is real
+///
+/// # Arguments
+///
+/// * `word` - The word, folded with `fold_word`
+///
+pub fn candidate_forms(word: &str) -> Vec<String> {
+    let mut forms = vec![word.to_string()];
+    let mut push = |form: &str| {
+        if form.chars().count() >= MIN_STEM_LENGTH && !forms.iter().any(|known| known == form) {
+            forms.push(form.to_string());
+        }
+    };
+
+    if let Some((_, base)) = IRREGULAR_FORMS.iter().find(|(form, _)| *form == word) {
+        push(base);
+    }
+    for (suffix, replacement) in SUFFIX_RULES {
+        // "ss" is the end of words like "glass" rather than a plural
+        let Some(stem) = word
+            .strip_suffix(suffix)
+            .filter(|_| !(suffix == "s" && word.ends_with("ss")))
+        else {
+            continue;
+        };
+        push(&format!("{}{}", stem, replacement));
+        if replacement.is_empty() && matches!(suffix, "ed" | "ing" | "er" | "est") {
+            if let Some(stem) = undouble(stem) {
+                push(stem);
+            }
+        }
+    }
+
+    forms
+}
@@ -0,0 +1,226 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use tauri::State;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    book_worker::BookWorker,
+    dictionary::{
+        history::record_lookup,
+        inflection::candidate_forms,
+        stardict::{find_ifo_paths, fold_word, Definition, DictionaryInfo, StarDict},
+    },
+    error::ShelfError,
+};
+
+/// The dictionaries found in the dictionary_location folder, loaded on the first look up
+/// and again whenever the setting changes
+#[derive(Default)]
+pub struct Dictionaries {
+    /// The folder they were loaded from
+    location: Option<String>,
+    loaded: Arc<Vec<StarDict>>,
+}
+
+/// The definitions one dictionary has for a word
+#[derive(Serialize, Debug)]
+pub struct DictionaryEntry {
+    dictionary: String,
+    headword: String,
+    definitions: Vec<Definition>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LookupResult {
+    /// The word as it was looked up, without the punctuation around it
+    word: String,
+    /// The form that was found, differs from word when only its base form was, like "run" for "running"
+    matched: Option<String>,
+    entries: Vec<DictionaryEntry>,
+}
+
+/// Loads every dictionary under a folder, dictionaries that can't be read are skipped
+///
+/// # Arguments
+///
+/// * `folder` - The dictionary_location setting
+///
+#[instrument]
+fn load_dictionaries(folder: &str) -> Result<Vec<StarDict>, ShelfError> {
+    if !Path::new(folder).is_dir() {
+        return Err(ShelfError::settings(
+            "dictionary location",
+            "isn't set or doesn't exist",
+        ));
+    }
+
+    let mut dictionaries: Vec<StarDict> = find_ifo_paths(Path::new(folder))
+        .into_par_iter()
+        .filter_map(|ifo_path| match StarDict::open(&ifo_path) {
+            Ok(dictionary) => Some(dictionary),
+            Err(err) => {
+                warn!("Skipped the dictionary {}: {}", ifo_path.display(), err);
+                None
+            }
+        })
+        .collect();
+    dictionaries.sort_by(|a, b| a.get_info().get_name().cmp(b.get_info().get_name()));
+    info!(count = dictionaries.len(), "Loaded dictionaries");
+
+    Ok(dictionaries)
+}
+
+/// The loaded dictionaries, loading them when the folder changed since the last time
+///
+/// # Arguments
+///
+/// * `worker` - For the dictionary_location setting
+/// * `dictionaries` - The loaded dictionaries
+/// * `reload` - Loads them again even when the folder didn't change, for dictionaries added to it
+///
+async fn loaded_dictionaries(
+    worker: &State<'_, Mutex<BookWorker>>,
+    dictionaries: &State<'_, Mutex<Dictionaries>>,
+    reload: bool,
+) -> Result<Arc<Vec<StarDict>>, ShelfError> {
+    let location = worker
+        .lock()
+        .unwrap()
+        .get_application_settings()
+        .get("dictionary_location")
+        .cloned()
+        .unwrap_or_default();
+    {
+        let dictionaries = dictionaries.lock().unwrap();
+        if !reload && dictionaries.location.as_ref() == Some(&location) {
+            return Ok(dictionaries.loaded.clone());
+        }
+    }
+
+    let folder = location.clone();
+    let loaded = tauri::async_runtime::spawn_blocking(move || load_dictionaries(&folder))
+        .await
+        .map_err(|err| ShelfError::interrupted("dictionary loading", err))??;
+    let loaded = Arc::new(loaded);
+
+    let mut dictionaries = dictionaries.lock().unwrap();
+    dictionaries.location = Some(location);
+    dictionaries.loaded = loaded.clone();
+    Ok(loaded)
+}
+
+/// Takes the quotes and punctuation a selection picks up off a word
+fn clean_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .replace('’', "'")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Looks a word up in every dictionary, trying its base forms when the word itself isn't in any
+fn find_entries(dictionaries: &[StarDict], word: &str) -> (Option<String>, Vec<DictionaryEntry>) {
+    for form in candidate_forms(&fold_word(word)) {
+        let entries: Vec<DictionaryEntry> = dictionaries
+            .iter()
+            .filter(|dictionary| dictionary.contains(&form))
+            .flat_map(|dictionary| {
+                let name = dictionary.get_info().get_name();
+                let entries = dictionary.lookup(&form).unwrap_or_else(|err| {
+                    warn!("Failed to read {} from {}: {}", form, name, err);
+                    Vec::new()
+                });
+                entries.into_iter().map(|entry| DictionaryEntry {
+                    dictionary: name.to_string(),
+                    headword: entry.headword,
+                    definitions: entry.definitions,
+                })
+            })
+            .collect();
+
+        if !entries.is_empty() {
+            return (Some(form), entries);
+        }
+    }
+
+    (None, Vec::new())
+}
+
+/// The dictionaries in the dictionary_location folder
+#[tauri::command]
+pub async fn get_dictionaries(
+    worker: State<'_, Mutex<BookWorker>>,
+    dictionaries: State<'_, Mutex<Dictionaries>>,
+) -> Result<Vec<DictionaryInfo>, ShelfError> {
+    let loaded = loaded_dictionaries(&worker, &dictionaries, false).await?;
+    Ok(loaded
+        .iter()
+        .map(|dictionary| dictionary.get_info().clone())
+        .collect())
+}
+
+/// Loads the dictionaries again, for dictionaries added to or removed from the folder
+#[tauri::command]
+pub async fn reload_dictionaries(
+    worker: State<'_, Mutex<BookWorker>>,
+    dictionaries: State<'_, Mutex<Dictionaries>>,
+) -> Result<Vec<DictionaryInfo>, ShelfError> {
+    let loaded = loaded_dictionaries(&worker, &dictionaries, true).await?;
+    Ok(loaded
+        .iter()
+        .map(|dictionary| dictionary.get_info().clone())
+        .collect())
+}
+
+/// Looks a word up in every dictionary. A word that isn't in any of them is looked up under the
+/// forms it could be an inflection of, like "run" for "running". Words that are found go into
+/// the lookup history
+///
+/// # Arguments
+///
+/// * `word` - The selected word
+/// * `book_location` - The book it was selected in
+/// * `context` - The sentence it was selected in, kept with the history
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn lookup_word(
+    word: String,
+    book_location: Option<String>,
+    context: Option<String>,
+    worker: State<'_, Mutex<BookWorker>>,
+    dictionaries: State<'_, Mutex<Dictionaries>>,
+) -> Result<LookupResult, ShelfError> {
+    let word = clean_word(&word);
+    if word.is_empty() {
+        return Err(ShelfError::invalid("There's no word to look up"));
+    }
+
+    let loaded = loaded_dictionaries(&worker, &dictionaries, false).await?;
+    let lookup = word.clone();
+    let (matched, entries) =
+        tauri::async_runtime::spawn_blocking(move || find_entries(&loaded, &lookup))
+            .await
+            .map_err(|err| ShelfError::interrupted("dictionary lookup", err))?;
+
+    if let Some(entry) = entries.first() {
+        record_lookup(
+            &word,
+            &entry.headword,
+            book_location.as_deref(),
+            context.as_deref(),
+        )
+        .await
+        .map_err(|err| ShelfError::database("record the lookup", err))?;
+    }
+
+    Ok(LookupResult {
+        word,
+        matched,
+        entries,
+    })
+}
@@ -0,0 +1,4 @@
+pub mod history;
+pub mod inflection;
+pub mod lookup;
+pub mod stardict;
@@ -0,0 +1,548 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, Decompress, FlushDecompress};
+use serde::Serialize;
+
+static IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+// gzip header flags, dictzip files are gzip files with a chunk table in the extra field
+static GZIP_EXTRA: u8 = 0x04;
+static GZIP_NAME: u8 = 0x08;
+static GZIP_COMMENT: u8 = 0x10;
+static GZIP_HEADER_CRC: u8 = 0x02;
+
+/// The .ifo description of a dictionary
+#[derive(Serialize, Debug, Clone)]
+pub struct DictionaryInfo {
+    name: String,
+    /// Headwords, synonyms aren't counted
+    word_count: usize,
+    author: Option<String>,
+    description: Option<String>,
+    /// The .ifo file
+    location: String,
+}
+
+impl DictionaryInfo {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// How the text of a definition is marked up, from the StarDict type letters
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionKind {
+    Text,
+    /// Pronunciation, like an IPA transcription
+    Phonetic,
+    Html,
+    /// Pango markup, an HTML like subset used by GTK
+    Pango,
+    Xdxf,
+    MediaWiki,
+    /// A list of files the definition refers to
+    Resources,
+}
+
+/// One field of a dictionary entry
+#[derive(Serialize, Debug, Clone)]
+pub struct Definition {
+    kind: DefinitionKind,
+    content: String,
+}
+
+/// A headword with its definitions
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub headword: String,
+    pub definitions: Vec<Definition>,
+}
+
+struct IndexEntry {
+    headword: String,
+    offset: u64,
+    size: u32,
+}
+
+/// Where the definitions are read from
+enum DictData {
+    Plain(PathBuf),
+    /// Compressed in chunks that can be inflated on their own, so a lookup only inflates what it needs
+    DictZip {
+        path: PathBuf,
+        chunk_length: u64,
+        /// Where each chunk starts in the file and how long it is compressed
+        chunks: Vec<(u64, usize)>,
+    },
+    /// A plain gzip file, inflated when the dictionary is loaded
+    Memory(Vec<u8>),
+}
+
+/// A StarDict dictionary, the word list is kept in memory and definitions are read on lookup
+pub struct StarDict {
+    info: DictionaryInfo,
+    data: DictData,
+    /// The field types shared by every entry, entries carry their own types without it
+    same_type_sequence: Option<String>,
+    entries: Vec<IndexEntry>,
+    /// Headwords and synonyms folded with `fold_word`, pointing into entries
+    index: HashMap<String, Vec<usize>>,
+}
+
+/// The form words are indexed and looked up in
+///
+/// # Arguments
+///
+/// * `word` - The word to fold
+///
+pub fn fold_word(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+/// Finds every .ifo file under a directory, dictionaries are often kept in a folder each
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search
+///
+pub fn find_ifo_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut ifo_paths = Vec::new();
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            ifo_paths.extend(find_ifo_paths(&path));
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("ifo"))
+        {
+            ifo_paths.push(path);
+        }
+    }
+
+    ifo_paths
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+/// Splits a NUL terminated word off the front of an index
+fn take_word(bytes: &[u8]) -> Option<(String, &[u8])> {
+    let end = bytes.iter().position(|byte| *byte == 0)?;
+    Some((
+        String::from_utf8_lossy(&bytes[..end]).into_owned(),
+        &bytes[end + 1..],
+    ))
+}
+
+/// The file next to the .ifo with another extension, names often have dots of their own like en-1.0.ifo
+fn sibling(stem: &Path, extension: &str) -> PathBuf {
+    let mut path = stem.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn read_ifo(path: &Path) -> Result<HashMap<String, String>, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut lines = content.trim_start_matches('\u{feff}').lines();
+    if lines.next().map(str::trim) != Some(IFO_MAGIC) {
+        return Err("isn't a StarDict .ifo file".to_string());
+    }
+
+    Ok(lines
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect())
+}
+
+/// Reads a file that may have been gzipped, trying the plain name first
+fn read_maybe_gzipped(stem: &Path, extension: &str) -> Option<Result<Vec<u8>, String>> {
+    let plain = sibling(stem, extension);
+    if plain.is_file() {
+        return Some(fs::read(plain).map_err(|err| err.to_string()));
+    }
+
+    let gzipped = sibling(stem, &format!("{}.gz", extension));
+    let file = File::open(gzipped).ok()?;
+    let mut bytes = Vec::new();
+    Some(
+        GzDecoder::new(BufReader::new(file))
+            .read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .map_err(|err| err.to_string()),
+    )
+}
+
+/// The .idx file, a NUL terminated word followed by the offset and size of its definition
+fn parse_idx(bytes: &[u8], offset_bits: u32) -> Result<Vec<IndexEntry>, String> {
+    let offset_size = if offset_bits == 64 { 8 } else { 4 };
+    let mut entries = Vec::new();
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let (headword, after) = take_word(rest).ok_or("the .idx file is cut short")?;
+        let numbers = after
+            .get(..offset_size + 4)
+            .ok_or("the .idx file is cut short")?;
+        let offset = match offset_size {
+            8 => u64::from_be_bytes(numbers[..8].try_into().unwrap()),
+            _ => read_u32(numbers).unwrap() as u64,
+        };
+        let size = read_u32(&numbers[offset_size..]).unwrap();
+
+        entries.push(IndexEntry {
+            headword,
+            offset,
+            size,
+        });
+        rest = &after[offset_size + 4..];
+    }
+
+    Ok(entries)
+}
+
+/// The .syn file, a NUL terminated word followed by the position of the entry it stands for
+fn parse_syn(bytes: &[u8]) -> Vec<(String, usize)> {
+    let mut synonyms = Vec::new();
+    let mut rest = bytes;
+
+    while let Some((word, after)) = take_word(rest) {
+        let Some(entry) = read_u32(after) else {
+            break;
+        };
+        synonyms.push((word, entry as usize));
+        rest = &after[4..];
+    }
+
+    synonyms
+}
+
+/// Reads the chunk table of a dictzip file, None when it's a plain gzip file
+fn open_dictzip(path: &Path) -> Result<Option<DictData>, String> {
+    let mut reader = BufReader::new(File::open(path).map_err(|err| err.to_string())?);
+    let mut header = [0; 10];
+    reader
+        .read_exact(&mut header)
+        .map_err(|err| err.to_string())?;
+    if header[..3] != [0x1f, 0x8b, 8] {
+        return Err("isn't gzip compressed".to_string());
+    }
+    let flags = header[3];
+    if flags & GZIP_EXTRA == 0 {
+        return Ok(None);
+    }
+
+    let mut length = [0; 2];
+    reader
+        .read_exact(&mut length)
+        .map_err(|err| err.to_string())?;
+    let mut extra = vec![0; u16::from_le_bytes(length) as usize];
+    reader
+        .read_exact(&mut extra)
+        .map_err(|err| err.to_string())?;
+    for flag in [GZIP_NAME, GZIP_COMMENT] {
+        if flags & flag != 0 {
+            reader
+                .read_until(0, &mut Vec::new())
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    if flags & GZIP_HEADER_CRC != 0 {
+        reader
+            .read_exact(&mut [0; 2])
+            .map_err(|err| err.to_string())?;
+    }
+    let mut position = reader.stream_position().map_err(|err| err.to_string())?;
+
+    // Extra subfields are two id bytes and a little endian length, dictzip's is "RA"
+    let mut rest = extra.as_slice();
+    while rest.len() >= 4 {
+        let length = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let Some(field) = rest.get(4..4 + length) else {
+            break;
+        };
+        if rest[..2] == *b"RA" && field.len() >= 6 {
+            let values: Vec<u16> = field
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            let (chunk_length, chunk_count) = (values[1] as u64, values[2] as usize);
+            // Offsets are divided by the chunk length when reading
+            if chunk_length == 0 {
+                return Err("the chunk table is broken".to_string());
+            }
+            let sizes = values
+                .get(3..3 + chunk_count)
+                .ok_or("the chunk table is cut short")?;
+
+            let chunks = sizes
+                .iter()
+                .map(|size| {
+                    let chunk = (position, *size as usize);
+                    position += *size as u64;
+                    chunk
+                })
+                .collect();
+            return Ok(Some(DictData::DictZip {
+                path: path.to_path_buf(),
+                chunk_length,
+                chunks,
+            }));
+        }
+        rest = &rest[4 + length..];
+    }
+
+    Ok(None)
+}
+
+fn open_data(stem: &Path) -> Result<DictData, String> {
+    let plain = sibling(stem, "dict");
+    if plain.is_file() {
+        return Ok(DictData::Plain(plain));
+    }
+
+    let compressed = sibling(stem, "dict.dz");
+    if !compressed.is_file() {
+        return Err("has no .dict or .dict.dz file".to_string());
+    }
+    match open_dictzip(&compressed)? {
+        Some(data) => Ok(data),
+        None => {
+            let file = File::open(&compressed).map_err(|err| err.to_string())?;
+            let mut bytes = Vec::new();
+            GzDecoder::new(BufReader::new(file))
+                .read_to_end(&mut bytes)
+                .map_err(|err| err.to_string())?;
+            Ok(DictData::Memory(bytes))
+        }
+    }
+}
+
+fn definition_kind(type_id: u8) -> Option<DefinitionKind> {
+    match type_id {
+        b'm' | b'l' => Some(DefinitionKind::Text),
+        b't' | b'y' => Some(DefinitionKind::Phonetic),
+        b'h' => Some(DefinitionKind::Html),
+        b'g' => Some(DefinitionKind::Pango),
+        b'x' => Some(DefinitionKind::Xdxf),
+        b'w' => Some(DefinitionKind::MediaWiki),
+        b'r' => Some(DefinitionKind::Resources),
+        _ => None,
+    }
+}
+
+/// Splits the data of an entry into its fields. Lowercase types are text ended by a NUL,
+/// uppercase ones are binary like sounds and pictures and start with their size, those are skipped.
+/// With a sametypesequence the types aren't stored and the last field runs to the end of the data
+fn parse_definitions(data: &[u8], same_type_sequence: Option<&str>) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    let mut types = same_type_sequence.map(str::bytes);
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let type_id = match &mut types {
+            Some(types) => match types.next() {
+                Some(type_id) => type_id,
+                None => break,
+            },
+            None => {
+                let type_id = rest[0];
+                rest = &rest[1..];
+                type_id
+            }
+        };
+        let is_last = types.as_ref().is_some_and(|types| types.len() == 0);
+
+        let content = if is_last {
+            std::mem::take(&mut rest)
+        } else if type_id.is_ascii_uppercase() {
+            let Some(size) = read_u32(rest) else {
+                break;
+            };
+            let Some(content) = rest.get(4..4 + size as usize) else {
+                break;
+            };
+            rest = &rest[4 + size as usize..];
+            content
+        } else {
+            let end = rest
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(rest.len());
+            let content = &rest[..end];
+            rest = rest.get(end + 1..).unwrap_or_default();
+            content
+        };
+
+        if let Some(kind) = definition_kind(type_id).filter(|_| type_id.is_ascii_lowercase()) {
+            definitions.push(Definition {
+                kind,
+                content: String::from_utf8_lossy(content).trim().to_string(),
+            });
+        }
+    }
+
+    definitions
+}
+
+impl StarDict {
+    /// Loads a dictionary from its .ifo file, the .idx, .dict and optional .syn files sit next to it
+    ///
+    /// # Arguments
+    ///
+    /// * `ifo_path` - The .ifo file
+    ///
+    pub fn open(ifo_path: &Path) -> Result<StarDict, String> {
+        let ifo = read_ifo(ifo_path)?;
+        let stem = ifo_path.with_extension("");
+        let offset_bits = ifo
+            .get("idxoffsetbits")
+            .and_then(|bits| bits.parse().ok())
+            .unwrap_or(32);
+
+        let idx = read_maybe_gzipped(&stem, "idx").ok_or("has no .idx file")??;
+        let entries = parse_idx(&idx, offset_bits)?;
+        let data = open_data(&stem)?;
+
+        let mut index: HashMap<String, Vec<usize>> = HashMap::with_capacity(entries.len());
+        let synonyms = match read_maybe_gzipped(&stem, "syn") {
+            Some(syn) => parse_syn(&syn?),
+            None => Vec::new(),
+        };
+        let words = entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.headword.as_str(), position))
+            .chain(
+                synonyms
+                    .iter()
+                    .filter(|(_, position)| *position < entries.len())
+                    .map(|(word, position)| (word.as_str(), *position)),
+            );
+        for (word, position) in words {
+            let positions = index.entry(fold_word(word)).or_default();
+            if !positions.contains(&position) {
+                positions.push(position);
+            }
+        }
+
+        let name = ifo.get("bookname").cloned().unwrap_or_else(|| {
+            stem.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        Ok(StarDict {
+            info: DictionaryInfo {
+                name,
+                word_count: entries.len(),
+                author: ifo.get("author").cloned(),
+                description: ifo.get("description").cloned(),
+                location: ifo_path.to_string_lossy().into_owned(),
+            },
+            data,
+            same_type_sequence: ifo.get("sametypesequence").cloned(),
+            entries,
+            index,
+        })
+    }
+
+    pub fn get_info(&self) -> &DictionaryInfo {
+        &self.info
+    }
+
+    /// Whether a word folded with `fold_word` is in the dictionary
+    pub fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(word)
+    }
+
+    /// Reads the entries of a word, matching any case
+    ///
+    /// # Arguments
+    ///
+    /// * `word` - The word, folded with `fold_word`
+    ///
+    pub fn lookup(&self, word: &str) -> Result<Vec<Entry>, String> {
+        let Some(positions) = self.index.get(word) else {
+            return Ok(Vec::new());
+        };
+
+        positions
+            .iter()
+            .map(|position| {
+                let entry = &self.entries[*position];
+                let data = self.read(entry.offset, entry.size as usize)?;
+                Ok(Entry {
+                    headword: entry.headword.clone(),
+                    definitions: parse_definitions(&data, self.same_type_sequence.as_deref()),
+                })
+            })
+            .collect()
+    }
+
+    fn read(&self, offset: u64, size: usize) -> Result<Vec<u8>, String> {
+        match &self.data {
+            DictData::Plain(path) => {
+                let mut file = File::open(path).map_err(|err| err.to_string())?;
+                file.seek(SeekFrom::Start(offset))
+                    .map_err(|err| err.to_string())?;
+                let mut data = vec![0; size];
+                file.read_exact(&mut data).map_err(|err| err.to_string())?;
+                Ok(data)
+            }
+            DictData::Memory(bytes) => bytes
+                .get(offset as usize..offset as usize + size)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| "a definition lies past the end of the .dict file".to_string()),
+            DictData::DictZip {
+                path,
+                chunk_length,
+                chunks,
+            } => {
+                if size == 0 {
+                    return Ok(Vec::new());
+                }
+                let first = (offset / chunk_length) as usize;
+                let last = ((offset + size as u64 - 1) / chunk_length) as usize;
+                let chunks = chunks
+                    .get(first..=last)
+                    .ok_or("a definition lies past the end of the .dict.dz file")?;
+
+                let mut file = File::open(path).map_err(|err| err.to_string())?;
+                let mut inflated = Vec::with_capacity(chunks.len() * *chunk_length as usize);
+                for (start, compressed_size) in chunks {
+                    let mut compressed = vec![0; *compressed_size];
+                    file.seek(SeekFrom::Start(*start))
+                        .map_err(|err| err.to_string())?;
+                    file.read_exact(&mut compressed)
+                        .map_err(|err| err.to_string())?;
+
+                    // Chunks end on a full flush, so each is a raw deflate stream of its own
+                    let mut chunk = Vec::with_capacity(*chunk_length as usize);
+                    Decompress::new(false)
+                        .decompress_vec(&compressed, &mut chunk, FlushDecompress::Sync)
+                        .map_err(|err| err.to_string())?;
+                    inflated.extend(chunk);
+                }
+
+                let start = (offset - first as u64 * chunk_length) as usize;
+                inflated
+                    .get(start..start + size)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| {
+                        "a definition lies past the end of the .dict.dz file".to_string()
+                    })
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::{fmt, io, path::Path};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::book::bookio::BookError;
+
+/// Everything a command can fail with. The frontend gets it as {kind, message, operation, path},
+/// message is ready to show and the rest lets the UI decide what to offer, like picking another file
+#[derive(Debug)]
+pub enum ShelfError {
+    /// Reading, writing or moving a file
+    Io {
+        operation: String,
+        path: Option<String>,
+        source: io::Error,
+    },
+    Database {
+        operation: String,
+        source: sqlx::Error,
+    },
+    /// A file that isn't what it claims to be, like a broken epub or backup
+    Parse {
+        path: String,
+        message: String,
+    },
+    Cover {
+        path: String,
+        source: BookError,
+    },
+    Settings {
+        setting: String,
+        message: String,
+    },
+    Network {
+        url: String,
+        message: String,
+    },
+    /// The book, author or trash entry the command was given isn't there
+    NotFound {
+        what: String,
+        place: String,
+    },
+    /// The request doesn't make sense, like a rating of 7
+    InvalidInput {
+        message: String,
+    },
+    /// Work handed to another thread panicked or was dropped
+    Interrupted {
+        operation: String,
+        message: String,
+    },
+}
+
+impl ShelfError {
+    /// # Arguments
+    ///
+    /// * `operation` - What was being done, reads as "Failed to {operation}"
+    /// * `path` - The file it was done to
+    /// * `source` - The error itself
+    ///
+    pub fn io(operation: impl Into<String>, path: impl AsRef<Path>, source: io::Error) -> Self {
+        ShelfError::Io {
+            operation: operation.into(),
+            path: Some(path.as_ref().to_string_lossy().to_string()),
+            source,
+        }
+    }
+
+    /// # Arguments
+    ///
+    /// * `operation` - What was being done, reads as "Failed to {operation}"
+    /// * `source` - The error sqlx returned
+    ///
+    pub fn database(operation: impl Into<String>, source: sqlx::Error) -> Self {
+        ShelfError::Database {
+            operation: operation.into(),
+            source,
+        }
+    }
+
+    pub fn parse(path: impl AsRef<Path>, message: impl Into<String>) -> Self {
+        ShelfError::Parse {
+            path: path.as_ref().to_string_lossy().to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn settings(setting: impl Into<String>, message: impl Into<String>) -> Self {
+        ShelfError::Settings {
+            setting: setting.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn network(url: impl Into<String>, message: impl Into<String>) -> Self {
+        ShelfError::Network {
+            url: url.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(what: impl Into<String>) -> Self {
+        Self::not_found_in(what, "the library")
+    }
+
+    /// # Arguments
+    ///
+    /// * `what` - The thing that's missing
+    /// * `place` - Where it was looked for, like "the trash"
+    ///
+    pub fn not_found_in(what: impl Into<String>, place: impl Into<String>) -> Self {
+        ShelfError::NotFound {
+            what: what.into(),
+            place: place.into(),
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        ShelfError::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    /// # Arguments
+    ///
+    /// * `operation` - What stopped, reads as "The {operation} stopped unexpectedly"
+    /// * `error` - Why the thread running it went away
+    ///
+    pub fn interrupted(operation: impl Into<String>, error: impl fmt::Display) -> Self {
+        ShelfError::Interrupted {
+            operation: operation.into(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Sorts the errors from reading an epub, missing covers are cover errors and broken archives are parse errors
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The epub being read
+    /// * `source` - What went wrong with it
+    ///
+    pub fn epub(path: impl AsRef<Path>, source: BookError) -> Self {
+        let path = path.as_ref();
+
+        match source {
+            BookError::NoUniqueCover | BookError::BadCoverData => ShelfError::Cover {
+                path: path.to_string_lossy().to_string(),
+                source,
+            },
+            BookError::IOError => ShelfError::io(
+                "read",
+                path,
+                io::Error::new(io::ErrorKind::Other, source.to_string()),
+            ),
+            BookError::ResourceNotFound | BookError::XmlParseError => {
+                ShelfError::parse(path, source.to_string())
+            }
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ShelfError::Io { .. } => "io",
+            ShelfError::Database { .. } => "database",
+            ShelfError::Parse { .. } => "parse",
+            ShelfError::Cover { .. } => "cover",
+            ShelfError::Settings { .. } => "settings",
+            ShelfError::Network { .. } => "network",
+            ShelfError::NotFound { .. } => "not_found",
+            ShelfError::InvalidInput { .. } => "invalid_input",
+            ShelfError::Interrupted { .. } => "interrupted",
+        }
+    }
+
+    pub fn operation(&self) -> Option<&str> {
+        match self {
+            ShelfError::Io { operation, .. }
+            | ShelfError::Database { operation, .. }
+            | ShelfError::Interrupted { operation, .. } => Some(operation),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            ShelfError::Io { path, .. } => path.as_deref(),
+            ShelfError::Parse { path, .. } | ShelfError::Cover { path, .. } => Some(path),
+            ShelfError::Network { url, .. } => Some(url),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ShelfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShelfError::Io {
+                operation,
+                path: Some(path),
+                source,
+            } => write!(f, "Failed to {} {}: {}", operation, path, source),
+            ShelfError::Io {
+                operation, source, ..
+            } => write!(f, "Failed to {}: {}", operation, source),
+            ShelfError::Database { operation, source } => {
+                write!(f, "Failed to {}: {}", operation, source)
+            }
+            ShelfError::Parse { path, message } => {
+                write!(f, "{} couldn't be read: {}", path, message)
+            }
+            ShelfError::Cover { path, source } => {
+                write!(f, "The cover of {} couldn't be read: {}", path, source)
+            }
+            ShelfError::Settings { setting, message } => {
+                write!(f, "The {} setting {}", setting, message)
+            }
+            ShelfError::Network { url, message } => {
+                write!(f, "Failed to reach {}: {}", url, message)
+            }
+            ShelfError::NotFound { what, place } => write!(f, "{} is not in {}", what, place),
+            ShelfError::InvalidInput { message } => write!(f, "{}", message),
+            ShelfError::Interrupted { operation, message } => {
+                write!(f, "The {} stopped unexpectedly: {}", operation, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShelfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShelfError::Io { source, .. } => Some(source),
+            ShelfError::Database { source, .. } => Some(source),
+            ShelfError::Cover { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for ShelfError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("ShelfError", 4)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("operation", &self.operation())?;
+        error.serialize_field("path", &self.path())?;
+        error.end()
+    }
+}
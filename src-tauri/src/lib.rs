@@ -1,6 +1,13 @@
+pub mod authors;
 pub mod book;
 pub mod book_item;
 pub mod book_worker;
+pub mod calibre;
 pub mod database;
+pub mod dictionary;
+pub mod error;
+pub mod logging;
+pub mod opds;
 pub mod shelf;
+pub mod stats;
 pub mod xml;
@@ -0,0 +1,153 @@
+use std::{
+    cmp::min,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use tracing::level_filters::LevelFilter;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    filter::Targets, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, Registry,
+};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{book_worker::get_cache_dir, database::append_date_to_filename, error::ShelfError};
+
+static LOG_FILE_PREFIX: &str = "shelf";
+static LOG_FILE_SUFFIX: &str = "log";
+// A new file is started every day, a week of them is plenty for a bug report
+static MAX_LOG_FILES: usize = 7;
+static DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::INFO;
+
+// Swaps the filter when the log_level setting changes, so it applies without a restart
+static FILTER_HANDLE: OnceLock<reload::Handle<Targets, Registry>> = OnceLock::new();
+
+pub fn get_log_dir() -> PathBuf {
+    get_cache_dir().join(env!("LOG_FOLDER_NAME"))
+}
+
+fn parse_level(level: &str) -> Option<LevelFilter> {
+    level.trim().parse().ok()
+}
+
+/// Shelf logs at the chosen level, dependencies only once something goes wrong since sqlx alone logs every query
+fn build_filter(level: LevelFilter) -> Targets {
+    Targets::new()
+        .with_default(min(level, LevelFilter::WARN))
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+}
+
+/// Sends logs to a daily file in the cache folder, and to the console in debug builds.
+/// Release builds on windows have no console, so the files are the only place logs end up
+///
+/// # Arguments
+///
+/// * `level` - The log_level setting, info when it's missing or not a level
+///
+pub fn init_logging(level: Option<&String>) {
+    let level = level
+        .and_then(|level| parse_level(level))
+        .unwrap_or(DEFAULT_LOG_LEVEL);
+    let (filter, handle) = reload::Layer::new(build_filter(level));
+
+    let file_layer = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(get_log_dir())
+        .map_err(|err| eprintln!("Failed to open the log file: {}", err))
+        .ok()
+        .map(|appender| fmt::layer().with_ansi(false).with_writer(appender));
+    let console_layer = cfg!(debug_assertions).then(fmt::layer);
+
+    if let Err(err) = tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+    {
+        eprintln!("Failed to start logging: {}", err);
+        return;
+    }
+    _ = FILTER_HANDLE.set(handle);
+
+    // Panics would otherwise only reach the console
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("{}", info);
+        default_hook(info);
+    }));
+}
+
+/// Changes how much is logged from now on
+///
+/// # Arguments
+///
+/// * `level` - One of off, error, warn, info, debug or trace
+///
+pub fn set_log_level(level: &str) -> Result<(), ShelfError> {
+    let level = parse_level(level).ok_or_else(|| {
+        ShelfError::settings(
+            "log level",
+            format!(
+                "has to be off, error, warn, info, debug or trace, got {}",
+                level
+            ),
+        )
+    })?;
+
+    if let Some(handle) = FILTER_HANDLE.get() {
+        handle
+            .reload(build_filter(level))
+            .map_err(|err| ShelfError::settings("log level", err.to_string()))?;
+    }
+    tracing::info!(%level, "Log level changed");
+
+    Ok(())
+}
+
+fn write_log_archive(archive_path: &Path) -> Result<(), io::Error> {
+    let mut archive = ZipWriter::new(File::create(archive_path)?);
+
+    for entry in fs::read_dir(get_log_dir())? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !path.is_file() || !file_name.starts_with(LOG_FILE_PREFIX) {
+            continue;
+        }
+
+        archive.start_file(file_name, SimpleFileOptions::default())?;
+        io::copy(&mut File::open(&path)?, &mut archive)?;
+    }
+
+    archive.finish()?;
+
+    Ok(())
+}
+
+/// Zips the log files into a folder so they can be attached to a bug report, returns where the archive was written
+///
+/// # Arguments
+///
+/// * `path` - The folder to export to
+///
+#[tauri::command]
+pub async fn export_logs(path: String) -> Result<String, ShelfError> {
+    let archive_path = PathBuf::from(append_date_to_filename(
+        &PathBuf::from(path).join("shelf-logs.zip").to_string_lossy(),
+    ));
+
+    let written = archive_path.clone();
+    tauri::async_runtime::spawn_blocking(move || write_log_archive(&written))
+        .await
+        .map_err(|err| ShelfError::interrupted("log export", err))?
+        .map_err(|err| ShelfError::io("export the logs to", &archive_path, err))?;
+    tracing::info!(archive = %archive_path.display(), "Exported logs");
+
+    Ok(archive_path.to_string_lossy().to_string())
+}
@@ -6,10 +6,46 @@ use std::sync::Mutex;
 
 use app::*;
 
+use app::authors::{
+    add_author_alias, get_author_aliases, get_authors, get_books_by_author, merge_authors,
+    remove_author_alias, rename_author,
+};
 use app::book::bookio::initialize_books;
-use app::database::import_book_json_comm;
+use app::book::duplicates::{find_duplicate_books, merge_duplicate_books};
+use app::book::import::import_files;
+use app::book::import_errors::{get_import_errors, ignore_import_error, retry_import};
+use app::book::length::get_reading_time;
+use app::book::metadata::edit_book_metadata;
+use app::book::navigation::{get_book_manifest, get_book_navigation};
+use app::book::organize::organize_library;
+use app::book::query::query_books;
+use app::book::scan::{cancel_library_scan, get_library_books, start_library_scan, LibraryScanner};
+use app::book::search::search_books;
+use app::book::series::{detect_library_series, get_books_by_series};
+use app::book::speech::get_chapter_text;
+use app::book::status::{set_book_rating, set_book_review, set_read_status};
+use app::book::trash::{empty_trash, get_trash, remove_book, restore_book};
+use app::book::validate::{repair_epub, validate_epub, validate_library};
+use app::calibre::import_calibre_library;
+use app::database::{get_db_recovery_report, import_book_json_comm};
+use app::dictionary::{
+    history::{forget_vocabulary_word, get_lookup_history, get_vocabulary},
+    lookup::{get_dictionaries, lookup_word, reload_dictionaries, Dictionaries},
+};
+use app::logging::export_logs;
+use app::stats::{
+    get_books_finished_per_month, get_estimated_time_left, get_reading_speed,
+    get_reading_time_per_day, record_reading_session,
+};
 use app::{
     book_item::{get_cover_location_command, load_book},
+    opds::{
+        client::{browse_opds_catalog, download_opds_book, search_opds_catalog},
+        server::{
+            get_opds_lan_access, get_opds_port, get_opds_server_port, start_opds_server,
+            stop_opds_server, OpdsServer,
+        },
+    },
     shelf::{
         change_configuration_option, get_configuration_option, reset_configuration,
         shelf_settings_values,
@@ -18,32 +54,47 @@ use app::{
 use book_item::{get_all_books, BookCache};
 use book_worker::{backup_books_to_json, load_settings, BookWorker};
 use database::import_book_json;
-use tokio::runtime::Runtime;
 
 fn main() {
-    let runtime = Runtime::new().expect("Failed to create Tokio runtime");
+    let settings = load_settings();
+    logging::init_logging(settings.get("log_level"));
 
-    // Block on the async function `init_db`
-    runtime.block_on(async {
-        database::init_db().await;
-    });
-
-    // Now we can import a backup file if it exists
+    // The database shares tauris runtime with the async commands
+    let current_books = tauri::async_runtime::block_on(async {
+        if let Err(err) = database::init_db().await {
+            tracing::error!("Failed to open the database {}", err);
+            panic!("could not open the database: {}", err);
+        }
 
-    _ = import_book_json(None);
+        // Now we can import a backup file if it exists
+        _ = import_book_json(None).await;
 
-    let current_books = get_all_books().ok();
-
-    let mut worker = BookWorker::new(load_settings(), BookCache::new(current_books));
+        get_all_books().await.ok()
+    });
 
-    let book_cache = BookCache::new(worker.initialize_books());
+    // New books in the library folder are picked up by the background scan the dashboard starts
+    let worker = BookWorker::new(settings, BookCache::new(current_books));
 
-    worker.set_book_cache(book_cache);
+    let mut opds_server = OpdsServer::default();
+    if worker
+        .get_application_settings()
+        .get("opds_enabled")
+        .is_some_and(|enabled| enabled == "true")
+    {
+        let settings = worker.get_application_settings();
+        if let Err(err) = opds_server.start(get_opds_port(settings), get_opds_lan_access(settings))
+        {
+            tracing::error!("{}", err);
+        }
+    }
 
     let worker_mutex = Mutex::new(worker);
 
     tauri::Builder::default()
         .manage(worker_mutex)
+        .manage(Mutex::new(opds_server))
+        .manage(Mutex::new(LibraryScanner::default()))
+        .manage(Mutex::new(Dictionaries::default()))
         .invoke_handler(tauri::generate_handler![
             initialize_books,
             load_book,
@@ -53,7 +104,63 @@ fn main() {
             import_book_json_comm,
             reset_configuration,
             backup_books_to_json,
-            get_cover_location_command
+            get_cover_location_command,
+            get_db_recovery_report,
+            import_calibre_library,
+            start_opds_server,
+            stop_opds_server,
+            get_opds_server_port,
+            browse_opds_catalog,
+            search_opds_catalog,
+            download_opds_book,
+            edit_book_metadata,
+            get_books_by_series,
+            detect_library_series,
+            get_authors,
+            get_books_by_author,
+            merge_authors,
+            add_author_alias,
+            remove_author_alias,
+            get_author_aliases,
+            rename_author,
+            query_books,
+            search_books,
+            record_reading_session,
+            get_reading_time_per_day,
+            get_books_finished_per_month,
+            get_reading_speed,
+            get_estimated_time_left,
+            set_read_status,
+            set_book_rating,
+            set_book_review,
+            find_duplicate_books,
+            merge_duplicate_books,
+            organize_library,
+            remove_book,
+            get_trash,
+            restore_book,
+            empty_trash,
+            import_files,
+            start_library_scan,
+            cancel_library_scan,
+            get_library_books,
+            get_import_errors,
+            retry_import,
+            ignore_import_error,
+            export_logs,
+            validate_epub,
+            validate_library,
+            repair_epub,
+            get_book_navigation,
+            get_book_manifest,
+            get_reading_time,
+            get_chapter_text,
+            get_dictionaries,
+            reload_dictionaries,
+            lookup_word,
+            get_lookup_history,
+            get_vocabulary,
+            forget_vocabulary_word
         ])
         .run(tauri::generate_context!())
         .expect("shelf seems to have fallen over");
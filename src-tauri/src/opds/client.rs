@@ -0,0 +1,344 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Serialize;
+use tauri::State;
+use url::Url;
+use xmltree::Element;
+
+use crate::{
+    book::bookio::read_book,
+    book_item::Book,
+    book_worker::{add_new_books, BookWorker},
+    error::ShelfError,
+};
+
+use super::{ACQUISITION_REL, EPUB_TYPE, IMAGE_REL, OPENSEARCH_TYPE, THUMBNAIL_REL};
+
+/// A link to a file the catalog offers for an entry
+#[derive(Serialize, Debug, Clone)]
+pub struct AcquisitionLink {
+    href: String,
+    media_type: Option<String>,
+}
+
+/// An entry is either a book (it has acquisition links) or leads to another feed
+#[derive(Serialize, Debug, Clone)]
+pub struct CatalogEntry {
+    id: Option<String>,
+    title: String,
+    authors: Vec<String>,
+    summary: Option<String>,
+    cover_url: Option<String>,
+    thumbnail_url: Option<String>,
+    navigation_url: Option<String>,
+    acquisitions: Vec<AcquisitionLink>,
+}
+
+/// A single page of a remote catalog, urls are already made absolute
+#[derive(Serialize, Debug, Clone)]
+pub struct CatalogFeed {
+    url: String,
+    title: String,
+    entries: Vec<CatalogEntry>,
+    next_url: Option<String>,
+    previous_url: Option<String>,
+    start_url: Option<String>,
+    search_url: Option<String>,
+}
+
+fn child_text(element: &Element, name: &str) -> Option<String> {
+    element
+        .get_child(name)
+        .and_then(|child| child.get_text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn child_elements<'a>(element: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    element
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .filter(move |child| child.name == name)
+}
+
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    base.join(href).ok().map(|url| url.to_string())
+}
+
+/// Finds the first link with the given rel, optionally requiring a media type containing `media_type`
+fn find_link(element: &Element, base: &Url, rel: &str, media_type: Option<&str>) -> Option<String> {
+    child_elements(element, "link")
+        .find(|link| {
+            link.attributes.get("rel").map(String::as_str) == Some(rel)
+                && media_type.map_or(true, |wanted| {
+                    link.attributes
+                        .get("type")
+                        .is_some_and(|link_type| link_type.contains(wanted))
+                })
+        })
+        .and_then(|link| resolve(base, link.attributes.get("href")?))
+}
+
+fn parse_entry(entry: &Element, base: &Url) -> CatalogEntry {
+    let mut acquisitions = Vec::new();
+    let mut navigation_url = None;
+
+    for link in child_elements(entry, "link") {
+        let Some(href) = link
+            .attributes
+            .get("href")
+            .and_then(|href| resolve(base, href))
+        else {
+            continue;
+        };
+        let rel = link.attributes.get("rel").map(String::as_str).unwrap_or("");
+        let media_type = link.attributes.get("type").cloned();
+
+        // Covers both plain acquisition and the open-access/borrow/buy variants
+        if rel.starts_with(ACQUISITION_REL) {
+            acquisitions.push(AcquisitionLink { href, media_type });
+        } else if navigation_url.is_none()
+            && media_type
+                .as_deref()
+                .is_some_and(|link_type| link_type.contains("profile=opds-catalog"))
+        {
+            navigation_url = Some(href);
+        }
+    }
+
+    CatalogEntry {
+        id: child_text(entry, "id"),
+        title: child_text(entry, "title").unwrap_or_default(),
+        authors: child_elements(entry, "author")
+            .filter_map(|author| child_text(author, "name"))
+            .collect(),
+        summary: child_text(entry, "summary").or_else(|| child_text(entry, "content")),
+        cover_url: find_link(entry, base, IMAGE_REL, None),
+        thumbnail_url: find_link(entry, base, THUMBNAIL_REL, None),
+        navigation_url,
+        acquisitions,
+    }
+}
+
+/// Turns an atom document into a feed
+///
+/// # Arguments
+///
+/// * `base` - The url the feed was fetched from, relative links are resolved against it
+/// * `root` - The feed element
+///
+pub fn parse_feed(base: &Url, root: &Element) -> CatalogFeed {
+    CatalogFeed {
+        url: base.to_string(),
+        title: child_text(root, "title").unwrap_or_default(),
+        entries: child_elements(root, "entry")
+            .map(|entry| parse_entry(entry, base))
+            .collect(),
+        next_url: find_link(root, base, "next", None),
+        previous_url: find_link(root, base, "previous", None)
+            .or_else(|| find_link(root, base, "prev", None)),
+        start_url: find_link(root, base, "start", None),
+        search_url: find_link(root, base, "search", Some("atom"))
+            .or_else(|| find_link(root, base, "search", Some(OPENSEARCH_TYPE))),
+    }
+}
+
+fn fetch_xml(url: &Url) -> Result<Element, ShelfError> {
+    let response = ureq::get(url.as_str())
+        .call()
+        .map_err(|err| ShelfError::network(url.as_str(), err.to_string()))?;
+
+    Element::parse(response.into_reader())
+        .map_err(|err| ShelfError::parse(url.as_str(), format!("not a valid catalog, {}", err)))
+}
+
+/// Fetches and parses a catalog feed
+///
+/// # Arguments
+///
+/// * `url` - The feed to fetch, any page of a paginated feed works
+///
+pub fn fetch_feed(url: &str) -> Result<CatalogFeed, ShelfError> {
+    let url = Url::parse(url)
+        .map_err(|err| ShelfError::invalid(format!("Invalid catalog url {}: {}", url, err)))?;
+
+    Ok(parse_feed(&url, &fetch_xml(&url)?))
+}
+
+/// Works out the url for a search, the search link either is the template or points at an OpenSearch description holding it
+///
+/// # Arguments
+///
+/// * `search_url` - The search link taken from a feed
+/// * `terms` - What to search for
+///
+pub fn build_search_url(search_url: &str, terms: &str) -> Result<String, ShelfError> {
+    // Resolving the link escapes the braces when the template is part of the path
+    let search_url = search_url.replace("%7B", "{").replace("%7D", "}");
+
+    let template = if search_url.contains("{searchTerms}") {
+        search_url
+    } else {
+        let description_url = Url::parse(&search_url).map_err(|err| {
+            ShelfError::invalid(format!("Invalid search url {}: {}", search_url, err))
+        })?;
+        let description = fetch_xml(&description_url)?;
+
+        // Prefer a template that returns atom, any will do otherwise
+        let templates: Vec<&Element> = child_elements(&description, "Url").collect();
+        let template = templates
+            .iter()
+            .find(|url| {
+                url.attributes
+                    .get("type")
+                    .is_some_and(|url_type| url_type.contains("atom"))
+            })
+            .or(templates.first())
+            .and_then(|url| url.attributes.get("template"))
+            .ok_or_else(|| ShelfError::parse(&search_url, "there's no search template"))?;
+
+        resolve(&description_url, template)
+            .map(|template| template.replace("%7B", "{").replace("%7D", "}"))
+            .unwrap_or(template.clone())
+    };
+
+    // Unfilled optional parameters look like {startPage?}, they have to be dropped
+    let mut url = template.replace(
+        "{searchTerms}",
+        &utf8_percent_encode(terms, NON_ALPHANUMERIC).to_string(),
+    );
+    while let (Some(start), Some(end)) = (url.find('{'), url.find('}')) {
+        if end < start {
+            break;
+        }
+        url.replace_range(start..=end, "");
+    }
+
+    Ok(url)
+}
+
+/// Picks a file name that doesn't clobber anything already in the library
+fn unique_book_path(library_dir: &Path, title: &str) -> PathBuf {
+    let file_stem = Book::sanitize_windows_filename(title.trim().to_string());
+    let mut book_path = library_dir.join(format!("{}.epub", file_stem));
+    let mut copy = 1;
+
+    while book_path.exists() {
+        copy += 1;
+        book_path = library_dir.join(format!("{} ({}).epub", file_stem, copy));
+    }
+
+    book_path
+}
+
+/// Downloads an epub into the library folder. The file is written next to its final name and renamed once complete
+///
+/// # Arguments
+///
+/// * `url` - The acquisition link
+/// * `title` - The title of the entry, used to name the file
+/// * `library_dir` - Where books are stored
+///
+pub fn download_book(url: &str, title: &str, library_dir: &Path) -> Result<PathBuf, ShelfError> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|err| ShelfError::network(url, err.to_string()))?;
+
+    let is_epub = response.content_type() == EPUB_TYPE
+        || Url::parse(response.get_url())
+            .is_ok_and(|final_url| final_url.path().to_lowercase().ends_with(".epub"));
+    if !is_epub {
+        return Err(ShelfError::invalid(format!(
+            "{} is not an epub ({}), only epubs are supported",
+            url,
+            response.content_type()
+        )));
+    }
+
+    let book_path = unique_book_path(library_dir, title);
+    let partial_path = book_path.with_extension("epub.part");
+
+    let written = File::create(&partial_path)
+        .and_then(|mut file| io::copy(&mut response.into_reader(), &mut file));
+    if let Err(err) = written.and_then(|_| fs::rename(&partial_path, &book_path)) {
+        _ = fs::remove_file(&partial_path);
+        return Err(ShelfError::io("save", &book_path, err));
+    }
+
+    Ok(book_path)
+}
+
+/// Fetches a page of a remote catalog
+///
+/// # Arguments
+///
+/// * `url` - The feed url, use the next/previous urls of a feed to page through it
+///
+#[tauri::command]
+pub fn browse_opds_catalog(url: String) -> Result<CatalogFeed, ShelfError> {
+    fetch_feed(&url)
+}
+
+/// Searches a remote catalog
+///
+/// # Arguments
+///
+/// * `search_url` - The search_url of a previously fetched feed
+/// * `query` - What to search for
+///
+#[tauri::command(rename_all = "snake_case")]
+pub fn search_opds_catalog(search_url: String, query: String) -> Result<CatalogFeed, ShelfError> {
+    fetch_feed(&build_search_url(&search_url, &query)?)
+}
+
+/// Downloads a book from a catalog into the library and adds it to the shelf
+///
+/// # Arguments
+///
+/// * `url` - The acquisition link of the entry
+/// * `title` - The entries title
+///
+#[tauri::command]
+pub async fn download_opds_book(
+    url: String,
+    title: String,
+    state: State<'_, Mutex<BookWorker>>,
+) -> Result<Book, ShelfError> {
+    let library_dir = state
+        .lock()
+        .unwrap()
+        .get_application_settings()
+        .get("book_location")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .ok_or_else(|| {
+            ShelfError::settings("book location", "has to be set before downloading books")
+        })?;
+
+    // The lock isn't held while downloading, big files would freeze the app
+    let book = tauri::async_runtime::spawn_blocking(move || {
+        let book_path = download_book(&url, &title, &library_dir)?;
+
+        read_book(&book_path.to_string_lossy()).map_err(|err| {
+            _ = fs::remove_file(&book_path);
+            err
+        })
+    })
+    .await
+    .map_err(|err| ShelfError::interrupted("download", err))??;
+
+    add_new_books(&state, vec![book.clone()])
+        .await
+        .map_err(|err| {
+            ShelfError::database(format!("add {} to the library", book.get_title()), err)
+        })?;
+
+    Ok(book)
+}
@@ -0,0 +1,18 @@
+pub mod client;
+pub mod server;
+
+pub static ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+pub static OPDS_NAMESPACE: &str = "http://opds-spec.org/2010/catalog";
+pub static DUBLIN_CORE_NAMESPACE: &str = "http://purl.org/dc/terms/";
+pub static OPENSEARCH_NAMESPACE: &str = "http://a9.com/-/spec/opensearch/1.1/";
+
+pub static NAVIGATION_FEED_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+pub static ACQUISITION_FEED_TYPE: &str =
+    "application/atom+xml;profile=opds-catalog;kind=acquisition";
+pub static OPDS_JSON_TYPE: &str = "application/opds+json";
+pub static OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+pub static EPUB_TYPE: &str = "application/epub+zip";
+
+pub static ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+pub static IMAGE_REL: &str = "http://opds-spec.org/image";
+pub static THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
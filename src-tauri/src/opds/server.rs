@@ -0,0 +1,945 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+use sqlx::{QueryBuilder, Sqlite};
+use tauri::State;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tiny_http::{Header, Request, Response, Server};
+use tracing::{error, warn};
+use url::Url;
+use xmltree::{Element, EmitterConfig, Namespace, XMLNode};
+
+use crate::{
+    book::util::escape_like,
+    book_item::Book,
+    book_worker::BookWorker,
+    database::{block_on, get_db},
+    error::ShelfError,
+};
+
+use super::{
+    ACQUISITION_FEED_TYPE, ACQUISITION_REL, ATOM_NAMESPACE, DUBLIN_CORE_NAMESPACE, EPUB_TYPE,
+    IMAGE_REL, NAVIGATION_FEED_TYPE, OPDS_JSON_TYPE, OPDS_NAMESPACE, OPENSEARCH_NAMESPACE,
+    OPENSEARCH_TYPE, THUMBNAIL_REL,
+};
+
+const PAGE_SIZE: i64 = 50;
+const DEFAULT_PORT: u16 = 8080;
+// Requests are answered by this many threads, the rest wait in the server's queue
+const WORKER_COUNT: usize = 4;
+
+/// OPDS 1.2 is atom, OPDS 2.0 is json. Both are served from the same routes, v2 lives under /opds/v2
+#[derive(Clone, Copy, PartialEq)]
+enum FeedFormat {
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    fn prefix(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "/opds",
+            FeedFormat::Json => "/opds/v2",
+        }
+    }
+}
+
+enum BookFilter {
+    Recent,
+    All,
+    Author(String),
+    Series(String),
+    Tag(String),
+    Search(String),
+}
+
+enum FeedEntry {
+    Navigation {
+        title: String,
+        href: String,
+        count: Option<i64>,
+    },
+    Publication(Box<Book>),
+}
+
+struct Feed {
+    id: String,
+    title: String,
+    // Relative to the format prefix, without the page parameter
+    href: String,
+    navigation: bool,
+    entries: Vec<FeedEntry>,
+    page: i64,
+    has_next: bool,
+}
+
+enum Reply {
+    Body(&'static str, Vec<u8>),
+    File(&'static str, File, Option<String>),
+    NotFound,
+    Failed(String),
+}
+
+/// Keeps hold of the catalog server so it can be stopped again
+#[derive(Default)]
+pub struct OpdsServer {
+    server: Option<Arc<Server>>,
+    workers: Vec<JoinHandle<()>>,
+    port: Option<u16>,
+}
+
+impl OpdsServer {
+    /// Starts serving the catalog. There's no authentication, so it only listens on this machine unless
+    /// LAN access was turned on for e-readers on the same network
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port to listen on
+    /// * `lan_access` - Listens on every interface instead of only localhost
+    ///
+    pub fn start(&mut self, port: u16, lan_access: bool) -> Result<(), ShelfError> {
+        self.stop();
+
+        let host = if lan_access { "0.0.0.0" } else { "127.0.0.1" };
+        let server = Arc::new(Server::http((host, port)).map_err(|err| ShelfError::Io {
+            operation: format!("start the opds server on port {}", port),
+            path: None,
+            source: io::Error::new(io::ErrorKind::Other, err),
+        })?);
+
+        self.workers = (0..WORKER_COUNT)
+            .map(|_| {
+                let server = Arc::clone(&server);
+                // Each ends once stop() unblocks it
+                thread::spawn(move || {
+                    for request in server.incoming_requests() {
+                        handle_request(request);
+                    }
+                })
+            })
+            .collect();
+        self.server = Some(server);
+        self.port = Some(port);
+
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            // One unblock per worker, each only wakes a single thread
+            for _ in &self.workers {
+                server.unblock();
+            }
+        }
+        for worker in self.workers.drain(..) {
+            _ = worker.join();
+        }
+        self.port = None;
+    }
+
+    pub fn get_port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+pub fn get_opds_port(settings: &HashMap<String, String>) -> u16 {
+    settings
+        .get("opds_port")
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+/// Whether the catalog is served to the whole network, it's off unless opds_lan_access is turned on
+pub fn get_opds_lan_access(settings: &HashMap<String, String>) -> bool {
+    settings
+        .get("opds_lan_access")
+        .is_some_and(|lan_access| lan_access == "true")
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string()
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+fn handle_request(request: Request) {
+    let reply = match Url::parse("http://localhost").and_then(|base| base.join(request.url())) {
+        Ok(url) => {
+            let segments: Vec<String> = url
+                .path_segments()
+                .map(|segments| {
+                    segments
+                        .filter(|segment| !segment.is_empty())
+                        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+            block_on(route(&segments, &query))
+        }
+        Err(_) => Reply::NotFound,
+    };
+
+    let content_type = |value: &str| Header::from_bytes(&b"Content-Type"[..], value).unwrap();
+    let result = match reply {
+        Reply::Body(media_type, body) => {
+            request.respond(Response::from_data(body).with_header(content_type(media_type)))
+        }
+        Reply::File(media_type, file, file_name) => {
+            let mut response = Response::from_file(file).with_header(content_type(media_type));
+            if let Some(file_name) = file_name {
+                let disposition = format!("attachment; filename=\"{}\"", file_name);
+                if let Ok(header) = Header::from_bytes(&b"Content-Disposition"[..], disposition) {
+                    response = response.with_header(header);
+                }
+            }
+            request.respond(response)
+        }
+        Reply::NotFound => {
+            request.respond(Response::from_string("Not found").with_status_code(404))
+        }
+        Reply::Failed(err) => {
+            error!("opds request failed {:?}", err);
+            request.respond(Response::from_string(err).with_status_code(500))
+        }
+    };
+
+    if let Err(err) = result {
+        warn!("Failed to answer opds request {:?}", err);
+    }
+}
+
+async fn route(segments: &[String], query: &HashMap<String, String>) -> Reply {
+    let segments: Vec<&str> = segments.iter().map(|segment| segment.as_str()).collect();
+    let (format, rest) = match segments.as_slice() {
+        ["opds", "v2", rest @ ..] => (FeedFormat::Json, rest),
+        ["opds", rest @ ..] => (FeedFormat::Atom, rest),
+        _ => return Reply::NotFound,
+    };
+    let page = query
+        .get("page")
+        .and_then(|page| page.parse::<i64>().ok())
+        .unwrap_or(0)
+        // Keeps the offset from overflowing
+        .clamp(0, i64::MAX / PAGE_SIZE - 1);
+
+    let feed = match rest {
+        [] => Ok(root_feed()),
+        ["recent"] => book_feed("recent", "Recently added", BookFilter::Recent, page).await,
+        ["all"] => book_feed("all", "All books", BookFilter::All, page).await,
+        ["authors"] => group_feed("authors", "Authors").await,
+        ["authors", author] => {
+            let href = format!("authors/{}", encode(author));
+            book_feed(&href, author, BookFilter::Author(author.to_string()), page).await
+        }
+        ["series"] => group_feed("series", "Series").await,
+        ["series", series] => {
+            let href = format!("series/{}", encode(series));
+            book_feed(&href, series, BookFilter::Series(series.to_string()), page).await
+        }
+        ["tags"] => group_feed("tags", "Tags").await,
+        ["tags", tag] => {
+            let href = format!("tags/{}", encode(tag));
+            book_feed(&href, tag, BookFilter::Tag(tag.to_string()), page).await
+        }
+        ["search"] => {
+            let terms = query.get("q").or(query.get("query")).cloned();
+            let terms = terms.unwrap_or_default();
+            let href = format!("search?q={}", encode(&terms));
+            book_feed(&href, &terms, BookFilter::Search(terms.clone()), page).await
+        }
+        ["opensearch.xml"] if format == FeedFormat::Atom => {
+            return Reply::Body(OPENSEARCH_TYPE, render_opensearch());
+        }
+        ["books", id, resource] => return book_resource(id, resource).await,
+        _ => return Reply::NotFound,
+    };
+
+    match feed {
+        Ok(feed) => match format {
+            FeedFormat::Atom => Reply::Body(
+                if feed.navigation {
+                    NAVIGATION_FEED_TYPE
+                } else {
+                    ACQUISITION_FEED_TYPE
+                },
+                render_atom(&feed),
+            ),
+            FeedFormat::Json => Reply::Body(OPDS_JSON_TYPE, render_json(&feed)),
+        },
+        Err(err) => Reply::Failed(err.to_string()),
+    }
+}
+
+fn root_feed() -> Feed {
+    let navigation = |title: &str, href: &str| FeedEntry::Navigation {
+        title: title.to_string(),
+        href: href.to_string(),
+        count: None,
+    };
+
+    Feed {
+        id: "urn:shelf:root".to_string(),
+        title: "Shelf".to_string(),
+        href: String::new(),
+        navigation: true,
+        entries: vec![
+            navigation("Recently added", "recent"),
+            navigation("Authors", "authors"),
+            navigation("Series", "series"),
+            navigation("Tags", "tags"),
+            navigation("All books", "all"),
+        ],
+        page: 0,
+        has_next: false,
+    }
+}
+
+async fn book_feed(
+    href: &str,
+    title: &str,
+    filter: BookFilter,
+    page: i64,
+) -> Result<Feed, sqlx::Error> {
+    let mut books = query_books(&filter, page).await?;
+    let has_next = books.len() as i64 > PAGE_SIZE;
+    books.truncate(PAGE_SIZE as usize);
+
+    Ok(Feed {
+        id: format!("urn:shelf:{}", href),
+        title: title.to_string(),
+        href: href.to_string(),
+        navigation: false,
+        entries: books
+            .into_iter()
+            .map(|book| FeedEntry::Publication(Box::new(book)))
+            .collect(),
+        page,
+        has_next,
+    })
+}
+
+/// Every author with how many books they're on, co-authored books count for each of their authors
+async fn author_groups() -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let authors =
+        sqlx::query_scalar::<_, String>("SELECT authors FROM books WHERE authors IS NOT NULL")
+            .fetch_all(get_db())
+            .await?;
+
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    for author in authors.iter().flat_map(|authors| authors.split(" & ")) {
+        let author = author.trim();
+        if !author.is_empty() {
+            *counts.entry(author).or_default() += 1;
+        }
+    }
+
+    let mut groups: Vec<(String, i64)> = counts
+        .into_iter()
+        .map(|(author, count)| (author.to_string(), count))
+        .collect();
+    groups.sort_by_cached_key(|(author, _)| author.to_lowercase());
+    Ok(groups)
+}
+
+/// Lists every distinct value of a column with how many books have it, tags come from book_tags
+async fn group_feed(column: &'static str, title: &str) -> Result<Feed, sqlx::Error> {
+    let groups = match column {
+        "authors" => author_groups().await?,
+        "tags" => {
+            sqlx::query_as::<_, (String, i64)>(
+                "SELECT tag, COUNT(*) FROM book_tags GROUP BY tag ORDER BY tag COLLATE NOCASE",
+            )
+            .fetch_all(get_db())
+            .await?
+        }
+        _ => {
+            sqlx::query_as::<_, (String, i64)>(&format!(
+                "SELECT {column}, COUNT(*) FROM books WHERE {column} IS NOT NULL
+                 GROUP BY {column} ORDER BY {column} COLLATE NOCASE"
+            ))
+            .fetch_all(get_db())
+            .await?
+        }
+    };
+
+    Ok(Feed {
+        id: format!("urn:shelf:{}", column),
+        title: title.to_string(),
+        href: column.to_string(),
+        navigation: true,
+        entries: groups
+            .into_iter()
+            .map(|(name, count)| FeedEntry::Navigation {
+                href: format!("{}/{}", column, encode(&name)),
+                title: name,
+                count: Some(count),
+            })
+            .collect(),
+        page: 0,
+        has_next: false,
+    })
+}
+
+/// Fetches a page of books, plus one extra so we know whether there is a next page
+async fn query_books(filter: &BookFilter, page: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT * FROM books");
+
+    match filter {
+        BookFilter::Recent => {
+            query.push(" ORDER BY id DESC");
+        }
+        BookFilter::All => {
+            query.push(" ORDER BY title COLLATE NOCASE");
+        }
+        BookFilter::Author(author) => {
+            // Co-authored books list their authors joined by " & "
+            query
+                .push(" WHERE instr(' & ' || authors || ' & ', ' & ' || ")
+                .push_bind(author)
+                .push(" || ' & ') > 0");
+            query.push(" ORDER BY title COLLATE NOCASE");
+        }
+        BookFilter::Series(series) => {
+            query.push(" WHERE series = ").push_bind(series);
+            query.push(" ORDER BY series_index, title COLLATE NOCASE");
+        }
+        BookFilter::Tag(tag) => {
+            query
+                .push(" WHERE id IN (SELECT book_id FROM book_tags WHERE tag = ")
+                .push_bind(tag)
+                .push(")");
+            query.push(" ORDER BY title COLLATE NOCASE");
+        }
+        BookFilter::Search(terms) => {
+            let pattern = format!("%{}%", escape_like(terms));
+            query.push(" WHERE title LIKE ").push_bind(pattern.clone());
+            query
+                .push(" ESCAPE '\\' OR authors LIKE ")
+                .push_bind(pattern.clone());
+            query
+                .push(" ESCAPE '\\' OR series LIKE ")
+                .push_bind(pattern);
+            query.push(" ESCAPE '\\'");
+            query.push(" ORDER BY title COLLATE NOCASE");
+        }
+    }
+    query.push(" LIMIT ").push_bind(PAGE_SIZE + 1);
+    query.push(" OFFSET ").push_bind(page * PAGE_SIZE);
+
+    query.build_query_as::<Book>().fetch_all(get_db()).await
+}
+
+/// Serves the epub itself or its cached cover
+async fn book_resource(id: &str, resource: &str) -> Reply {
+    let Ok(id) = id.parse::<i64>() else {
+        return Reply::NotFound;
+    };
+
+    let book = match sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
+        .bind(id)
+        .fetch_optional(get_db())
+        .await
+    {
+        Ok(Some(book)) => book,
+        Ok(None) => return Reply::NotFound,
+        Err(err) => return Reply::Failed(err.to_string()),
+    };
+
+    match resource {
+        "file" => match File::open(book.get_book_location()) {
+            Ok(file) => {
+                // Header values need to be plain ascii
+                let file_name: String = Book::sanitize_windows_filename(book.get_title().clone())
+                    .chars()
+                    .filter(|c| c.is_ascii() && *c != '"')
+                    .collect();
+                Reply::File(EPUB_TYPE, file, Some(format!("{}.epub", file_name.trim())))
+            }
+            Err(_) => Reply::NotFound,
+        },
+        "cover" => {
+            let cover_location = book.get_cover_location();
+            match File::open(Path::new(&cover_location)) {
+                Ok(file) => Reply::File("image/jpeg", file, None),
+                Err(_) => Reply::NotFound,
+            }
+        }
+        _ => Reply::NotFound,
+    }
+}
+
+fn page_href(format: FeedFormat, href: &str, page: i64) -> String {
+    let separator = if href.contains('?') { '&' } else { '?' };
+
+    if href.is_empty() {
+        format.prefix().to_string()
+    } else if page == 0 {
+        format!("{}/{}", format.prefix(), href)
+    } else {
+        format!("{}/{}{}page={}", format.prefix(), href, separator, page)
+    }
+}
+
+fn split_authors(book: &Book) -> Vec<&str> {
+    book.get_authors()
+        .map(|authors| authors.split(" & ").collect())
+        .unwrap_or_default()
+}
+
+fn text_element(name: &str, text: &str) -> Element {
+    let mut element = Element::new(name);
+    element.children.push(XMLNode::Text(text.to_string()));
+    element
+}
+
+fn link_element(rel: &str, href: &str, media_type: &str) -> Element {
+    let mut link = Element::new("link");
+    link.attributes.insert("rel".to_string(), rel.to_string());
+    link.attributes.insert("href".to_string(), href.to_string());
+    link.attributes
+        .insert("type".to_string(), media_type.to_string());
+    link
+}
+
+fn push_element(parent: &mut Element, child: Element) {
+    parent.children.push(XMLNode::Element(child));
+}
+
+fn write_document(root: &Element) -> Vec<u8> {
+    let mut document = Vec::new();
+    if let Err(err) = root.write_with_config(&mut document, EmitterConfig::new()) {
+        error!("Failed to write opds feed {:?}", err);
+    }
+    document
+}
+
+fn render_atom(feed: &Feed) -> Vec<u8> {
+    let format = FeedFormat::Atom;
+    let updated = now();
+
+    let mut namespaces = Namespace::empty();
+    namespaces.put("", ATOM_NAMESPACE);
+    namespaces.put("dc", DUBLIN_CORE_NAMESPACE);
+    namespaces.put("opds", OPDS_NAMESPACE);
+
+    let mut root = Element::new("feed");
+    root.namespaces = Some(namespaces);
+
+    push_element(&mut root, text_element("id", &feed.id));
+    push_element(&mut root, text_element("title", &feed.title));
+    push_element(&mut root, text_element("updated", &updated));
+
+    let mut author = Element::new("author");
+    push_element(&mut author, text_element("name", "Shelf"));
+    push_element(&mut root, author);
+
+    let self_type = if feed.navigation {
+        NAVIGATION_FEED_TYPE
+    } else {
+        ACQUISITION_FEED_TYPE
+    };
+    push_element(
+        &mut root,
+        link_element("self", &page_href(format, &feed.href, feed.page), self_type),
+    );
+    push_element(
+        &mut root,
+        link_element("start", format.prefix(), NAVIGATION_FEED_TYPE),
+    );
+    push_element(
+        &mut root,
+        link_element("search", "/opds/opensearch.xml", OPENSEARCH_TYPE),
+    );
+    if feed.page > 0 {
+        push_element(
+            &mut root,
+            link_element(
+                "previous",
+                &page_href(format, &feed.href, feed.page - 1),
+                self_type,
+            ),
+        );
+    }
+    if feed.has_next {
+        push_element(
+            &mut root,
+            link_element(
+                "next",
+                &page_href(format, &feed.href, feed.page + 1),
+                self_type,
+            ),
+        );
+    }
+
+    for entry in &feed.entries {
+        let mut entry_element = Element::new("entry");
+
+        match entry {
+            FeedEntry::Navigation { title, href, count } => {
+                push_element(&mut entry_element, text_element("title", title));
+                push_element(
+                    &mut entry_element,
+                    text_element("id", &format!("urn:shelf:{}", href)),
+                );
+                push_element(&mut entry_element, text_element("updated", &updated));
+                if let Some(count) = count {
+                    let mut content = text_element(
+                        "content",
+                        &format!("{} book{}", count, if *count == 1 { "" } else { "s" }),
+                    );
+                    content
+                        .attributes
+                        .insert("type".to_string(), "text".to_string());
+                    push_element(&mut entry_element, content);
+                }
+
+                // Group listings lead to navigation feeds, everything else is a list of books
+                let media_type = if matches!(href.as_str(), "authors" | "series" | "tags") {
+                    NAVIGATION_FEED_TYPE
+                } else {
+                    ACQUISITION_FEED_TYPE
+                };
+                push_element(
+                    &mut entry_element,
+                    link_element("subsection", &page_href(format, href, 0), media_type),
+                );
+            }
+            FeedEntry::Publication(book) => {
+                let id = book.get_id().unwrap_or_default();
+
+                push_element(&mut entry_element, text_element("title", book.get_title()));
+                push_element(
+                    &mut entry_element,
+                    text_element("id", &format!("urn:shelf:book:{}", id)),
+                );
+                push_element(&mut entry_element, text_element("updated", &updated));
+
+                for name in split_authors(book) {
+                    let mut author = Element::new("author");
+                    push_element(&mut author, text_element("name", name));
+                    push_element(&mut entry_element, author);
+                }
+
+                if let Some(description) = book.get_description() {
+                    let mut content = text_element("content", description);
+                    content
+                        .attributes
+                        .insert("type".to_string(), "html".to_string());
+                    push_element(&mut entry_element, content);
+                }
+
+                let cover_href = format!("/opds/books/{}/cover", id);
+                push_element(
+                    &mut entry_element,
+                    link_element(IMAGE_REL, &cover_href, "image/jpeg"),
+                );
+                push_element(
+                    &mut entry_element,
+                    link_element(THUMBNAIL_REL, &cover_href, "image/jpeg"),
+                );
+                push_element(
+                    &mut entry_element,
+                    link_element(
+                        ACQUISITION_REL,
+                        &format!("/opds/books/{}/file", id),
+                        EPUB_TYPE,
+                    ),
+                );
+            }
+        }
+
+        push_element(&mut root, entry_element);
+    }
+
+    write_document(&root)
+}
+
+fn render_json(feed: &Feed) -> Vec<u8> {
+    let format = FeedFormat::Json;
+
+    let mut links = vec![
+        json!({ "rel": "self", "href": page_href(format, &feed.href, feed.page), "type": OPDS_JSON_TYPE }),
+        json!({ "rel": "start", "href": format.prefix(), "type": OPDS_JSON_TYPE }),
+        json!({ "rel": "search", "href": "/opds/v2/search{?query}", "type": OPDS_JSON_TYPE, "templated": true }),
+    ];
+    if feed.page > 0 {
+        links.push(json!({ "rel": "previous", "href": page_href(format, &feed.href, feed.page - 1), "type": OPDS_JSON_TYPE }));
+    }
+    if feed.has_next {
+        links.push(json!({ "rel": "next", "href": page_href(format, &feed.href, feed.page + 1), "type": OPDS_JSON_TYPE }));
+    }
+
+    let mut navigation = Vec::new();
+    let mut publications = Vec::new();
+
+    for entry in &feed.entries {
+        match entry {
+            FeedEntry::Navigation { title, href, count } => {
+                let mut item = json!({
+                    "href": page_href(format, href, 0),
+                    "title": title,
+                    "type": OPDS_JSON_TYPE,
+                    "rel": "subsection",
+                });
+                if let Some(count) = count {
+                    item["properties"] = json!({ "numberOfItems": count });
+                }
+                navigation.push(item);
+            }
+            FeedEntry::Publication(book) => {
+                let id = book.get_id().unwrap_or_default();
+                let cover_href = format!("/opds/books/{}/cover", id);
+
+                let mut metadata = json!({
+                    "@type": "http://schema.org/Book",
+                    "identifier": format!("urn:shelf:book:{}", id),
+                    "title": book.get_title(),
+                    "author": split_authors(book),
+                });
+                if let Some(description) = book.get_description() {
+                    metadata["description"] = Value::from(description);
+                }
+                if let Some(series) = book.get_series() {
+                    metadata["belongsTo"] = json!({
+                        "series": [{ "name": series, "position": book.get_series_index() }]
+                    });
+                }
+
+                publications.push(json!({
+                    "metadata": metadata,
+                    "links": [
+                        { "rel": ACQUISITION_REL, "href": format!("/opds/books/{}/file", id), "type": EPUB_TYPE }
+                    ],
+                    "images": [
+                        { "href": cover_href, "type": "image/jpeg" }
+                    ],
+                }));
+            }
+        }
+    }
+
+    let mut document = json!({
+        "metadata": { "title": feed.title, "modified": now() },
+        "links": links,
+    });
+    if feed.navigation {
+        document["navigation"] = Value::from(navigation);
+    } else {
+        document["metadata"]["itemsPerPage"] = Value::from(PAGE_SIZE);
+        document["metadata"]["currentPage"] = Value::from(feed.page + 1);
+        document["publications"] = Value::from(publications);
+    }
+
+    serde_json::to_vec(&document).unwrap_or_default()
+}
+
+fn render_opensearch() -> Vec<u8> {
+    let mut namespaces = Namespace::empty();
+    namespaces.put("", OPENSEARCH_NAMESPACE);
+
+    let mut root = Element::new("OpenSearchDescription");
+    root.namespaces = Some(namespaces);
+    push_element(&mut root, text_element("ShortName", "Shelf"));
+    push_element(
+        &mut root,
+        text_element("Description", "Search the books on this Shelf"),
+    );
+
+    let mut url = Element::new("Url");
+    url.attributes
+        .insert("type".to_string(), ACQUISITION_FEED_TYPE.to_string());
+    url.attributes.insert(
+        "template".to_string(),
+        "/opds/search?q={searchTerms}".to_string(),
+    );
+    push_element(&mut root, url);
+
+    write_document(&root)
+}
+
+/// Starts the catalog server, remembering to start it again next launch
+#[tauri::command]
+pub fn start_opds_server(
+    worker_state: State<'_, Mutex<BookWorker>>,
+    server_state: State<'_, Mutex<OpdsServer>>,
+) -> Result<u16, ShelfError> {
+    let mut book_worker = worker_state.lock().unwrap();
+    let port = get_opds_port(book_worker.get_application_settings());
+    let lan_access = get_opds_lan_access(book_worker.get_application_settings());
+
+    server_state.lock().unwrap().start(port, lan_access)?;
+    book_worker.update_application_setting("opds_enabled".to_string(), "true".to_string())?;
+
+    Ok(port)
+}
+
+#[tauri::command]
+pub fn stop_opds_server(
+    worker_state: State<'_, Mutex<BookWorker>>,
+    server_state: State<'_, Mutex<OpdsServer>>,
+) -> Result<(), ShelfError> {
+    server_state.lock().unwrap().stop();
+    worker_state
+        .lock()
+        .unwrap()
+        .update_application_setting("opds_enabled".to_string(), "false".to_string())
+}
+
+/// Returns the port the catalog is being served on, if it is running
+#[tauri::command]
+pub fn get_opds_server_port(server_state: State<'_, Mutex<OpdsServer>>) -> Option<u16> {
+    server_state.lock().unwrap().get_port()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book_feed_with(book: Book, page: i64, has_next: bool) -> Feed {
+        Feed {
+            id: "urn:shelf:all".to_string(),
+            title: "All books".to_string(),
+            href: "all".to_string(),
+            navigation: false,
+            entries: vec![FeedEntry::Publication(Box::new(book))],
+            page,
+            has_next,
+        }
+    }
+
+    fn dune() -> Book {
+        Book::new(
+            Some("dune.jpg".to_string()),
+            "/books/dune.epub".to_string(),
+            "Dune".to_string(),
+        )
+        .with_authors(Some("Frank Herbert & Brian Herbert".to_string()))
+        .with_series(Some("Dune".to_string()), Some(1.0))
+    }
+
+    fn links(element: &Element) -> Vec<(String, String)> {
+        element
+            .children
+            .iter()
+            .filter_map(|child| child.as_element())
+            .filter(|child| child.name == "link")
+            .map(|link| {
+                (
+                    link.attributes["rel"].clone(),
+                    link.attributes["href"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn root_feed_links_every_navigation_feed() {
+        let root = Element::parse(render_atom(&root_feed()).as_slice()).unwrap();
+        let entries: Vec<&Element> = root
+            .children
+            .iter()
+            .filter_map(|child| child.as_element())
+            .filter(|child| child.name == "entry")
+            .collect();
+
+        let subsections: Vec<(String, String)> =
+            entries.iter().flat_map(|entry| links(entry)).collect();
+        assert_eq!(
+            subsections,
+            [
+                ("subsection".to_string(), "/opds/recent".to_string()),
+                ("subsection".to_string(), "/opds/authors".to_string()),
+                ("subsection".to_string(), "/opds/series".to_string()),
+                ("subsection".to_string(), "/opds/tags".to_string()),
+                ("subsection".to_string(), "/opds/all".to_string()),
+            ]
+        );
+        // Group listings are navigation feeds, the rest are books
+        let tags_link = entries[3].get_child("link").unwrap();
+        assert_eq!(tags_link.attributes["type"], NAVIGATION_FEED_TYPE);
+        let all_link = entries[4].get_child("link").unwrap();
+        assert_eq!(all_link.attributes["type"], ACQUISITION_FEED_TYPE);
+    }
+
+    #[test]
+    fn atom_entries_split_authors_and_link_the_file() {
+        let feed =
+            Element::parse(render_atom(&book_feed_with(dune(), 1, true)).as_slice()).unwrap();
+
+        let feed_links = links(&feed);
+        assert!(feed_links.contains(&("previous".to_string(), "/opds/all".to_string())));
+        assert!(feed_links.contains(&("next".to_string(), "/opds/all?page=2".to_string())));
+
+        let entry = feed.get_child("entry").unwrap();
+        let authors: Vec<String> = entry
+            .children
+            .iter()
+            .filter_map(|child| child.as_element())
+            .filter(|child| child.name == "author")
+            .filter_map(|author| author.get_child("name")?.get_text())
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(authors, ["Frank Herbert", "Brian Herbert"]);
+        assert!(links(entry).contains(&(
+            ACQUISITION_REL.to_string(),
+            "/opds/books/0/file".to_string()
+        )));
+    }
+
+    #[test]
+    fn json_feed_lists_publications_with_their_series() {
+        let document: Value =
+            serde_json::from_slice(&render_json(&book_feed_with(dune(), 0, false))).unwrap();
+
+        assert_eq!(document["metadata"]["currentPage"], 1);
+        assert!(document["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|link| link["rel"] != "next" && link["rel"] != "previous"));
+
+        let publication = &document["publications"][0];
+        assert_eq!(publication["metadata"]["title"], "Dune");
+        assert_eq!(
+            publication["metadata"]["author"],
+            json!(["Frank Herbert", "Brian Herbert"])
+        );
+        assert_eq!(
+            publication["metadata"]["belongsTo"]["series"][0],
+            json!({ "name": "Dune", "position": 1.0 })
+        );
+        assert_eq!(publication["links"][0]["href"], "/opds/books/0/file");
+    }
+
+    #[test]
+    fn search_terms_are_matched_literally() {
+        assert_eq!(escape_like(r"100%_\"), r"100\%\_\\");
+        assert_eq!(
+            page_href(FeedFormat::Json, "search?q=dune", 2),
+            "/opds/v2/search?q=dune&page=2"
+        );
+    }
+
+    #[test]
+    fn the_catalog_stays_local_unless_lan_access_is_on() {
+        let mut settings = HashMap::new();
+        assert!(!get_opds_lan_access(&settings));
+
+        settings.insert("opds_lan_access".to_string(), "true".to_string());
+        assert!(get_opds_lan_access(&settings));
+    }
+}
@@ -2,7 +2,10 @@ use std::{collections::HashMap, sync::Mutex};
 
 use tauri::State;
 
-use crate::book_worker::BookWorker;
+use crate::{
+    book_item::drop_books_from_table, book_worker::BookWorker, error::ShelfError,
+    logging::set_log_level,
+};
 
 ///This is how we get out settings back over to nextjs.
 ///TODO: Use enums throughout backend, lazy guy :|
@@ -13,6 +16,17 @@ pub fn shelf_settings_values() -> HashMap<String, (String, String)> {
         ("BOOK_LOCATION".to_string(), "unset"),
         ("ENDLESS_SCROLL".to_string(), "false"),
         ("COVER_BACKGROUND".to_string(), "false"),
+        ("OPDS_ENABLED".to_string(), "false"),
+        ("OPDS_PORT".to_string(), "8080"),
+        ("OPDS_LAN_ACCESS".to_string(), "false"),
+        (
+            "ORGANIZE_TEMPLATE".to_string(),
+            "{author_sort}/{series}/{series_index} - {title}.{ext}",
+        ),
+        ("TRASH_RETENTION_DAYS".to_string(), "30"),
+        ("LOG_LEVEL".to_string(), "info"),
+        ("WORDS_PER_MINUTE".to_string(), "250"),
+        ("DICTIONARY_LOCATION".to_string(), "unset"),
     ]
     .iter()
     .cloned()
@@ -34,13 +48,15 @@ pub fn shelf_settings_values() -> HashMap<String, (String, String)> {
 pub fn get_configuration_option(
     option_name: String,
     state: State<'_, Mutex<BookWorker>>,
-) -> Option<String> {
+) -> Result<String, ShelfError> {
     let book_worker = state.lock().unwrap();
     let application_settings = book_worker.get_application_settings();
 
     let settings_value = application_settings.get(&option_name);
 
-    settings_value.cloned()
+    settings_value
+        .cloned()
+        .ok_or_else(|| ShelfError::settings(option_name, "doesn't exist"))
 }
 
 /// Changes the value of a settings item
@@ -55,17 +71,22 @@ pub fn change_configuration_option(
     option_name: String,
     value: String,
     state: State<'_, Mutex<BookWorker>>,
-) {
+) -> Result<(), ShelfError> {
+    // The new level applies straight away, a level that isn't one is refused before it's saved
+    if option_name == "log_level" {
+        set_log_level(&value)?;
+    }
+
     let mut book_worker = state.lock().unwrap();
-    book_worker.update_application_setting(option_name, value);
+    book_worker.update_application_setting(option_name, value)
 }
 
 //Delete config files and call the create file method
 #[tauri::command(rename_all = "snake_case")]
-pub fn reset_configuration(state: State<'_, Mutex<BookWorker>>) -> Result<(), String> {
-    let mut book_worker = state.lock().unwrap();
-    // TODO use array for errors
-    book_worker.reset();
+pub async fn reset_configuration(state: State<'_, Mutex<BookWorker>>) -> Result<(), ShelfError> {
+    state.lock().unwrap().reset()?;
 
-    Ok(())
+    drop_books_from_table()
+        .await
+        .map_err(|err| ShelfError::database("clear the library", err))
 }
@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{database::get_db, error::ShelfError};
+
+// Progress at or past this counts as finishing the book, the last page rarely reports exactly 1
+static FINISHED_PROGRESS: f64 = 0.99;
+// A book needs this much reading of its own before its speed is trusted over the overall speed
+static MIN_BOOK_SECONDS_FOR_SPEED: i64 = 15 * 60;
+// Reading of counted books needed before the measured words per minute replaces the setting
+static MIN_SECONDS_FOR_WORD_SPEED: i64 = 30 * 60;
+
+// Sessions store text timestamps, this gives their length in seconds
+static SESSION_SECONDS: &str =
+    "(CAST(strftime('%s', ended_at) AS INTEGER) - CAST(strftime('%s', started_at) AS INTEGER))";
+
+/// A finished stretch of reading as reported by the reader
+#[derive(Deserialize, Debug)]
+pub struct ReadingSession {
+    book_location: String,
+    /// Unix time in milliseconds
+    started_at: i64,
+    ended_at: i64,
+    /// 0 to 1
+    start_progress: Option<f64>,
+    end_progress: Option<f64>,
+    pages_read: Option<i64>,
+}
+
+#[derive(Serialize, FromRow, Debug)]
+pub struct DailyReadingTime {
+    /// Local date, YYYY-MM-DD
+    day: String,
+    seconds: i64,
+}
+
+#[derive(Serialize, FromRow, Debug)]
+pub struct MonthlyFinishedBooks {
+    /// Local month, YYYY-MM
+    month: String,
+    books: i64,
+}
+
+/// Reading speed over every session that reported progress
+#[derive(Serialize, FromRow, Debug)]
+pub struct ReadingSpeed {
+    total_seconds: i64,
+    pages_per_hour: Option<f64>,
+    /// Fraction of a book per hour
+    progress_per_hour: Option<f64>,
+}
+
+async fn insert_reading_session(session: &ReadingSession) -> Result<(), sqlx::Error> {
+    let mut transaction = get_db().begin().await?;
+
+    let book_id = sqlx::query_scalar::<_, i64>("SELECT id FROM books WHERE book_location = $1")
+        .bind(&session.book_location)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    sqlx::query(
+        "INSERT INTO reading_sessions (book_id, started_at, ended_at, start_progress, end_progress, pages_read)
+         VALUES ($1, datetime($2 / 1000, 'unixepoch'), datetime($3 / 1000, 'unixepoch'), $4, $5, $6)",
+    )
+    .bind(book_id)
+    .bind(session.started_at)
+    .bind(session.ended_at)
+    .bind(session.start_progress)
+    .bind(session.end_progress)
+    .bind(session.pages_read)
+    .execute(&mut *transaction)
+    .await?;
+
+    // Picking a book up again means it's being read, reaching the end finishes it
+    sqlx::query(
+        "UPDATE books SET last_read = datetime($1 / 1000, 'unixepoch'),
+         progress = coalesce($2, progress),
+         read_status = CASE
+             WHEN $2 >= $3 THEN 'finished'
+             WHEN read_status IN ('unread', 'abandoned') THEN 'reading'
+             ELSE read_status END,
+         date_started = coalesce(date_started, datetime($5 / 1000, 'unixepoch')),
+         date_finished = CASE
+             WHEN $2 >= $3 THEN coalesce(date_finished, datetime($1 / 1000, 'unixepoch'))
+             ELSE date_finished END
+         WHERE id = $4",
+    )
+    .bind(session.ended_at)
+    .bind(session.end_progress)
+    .bind(FINISHED_PROGRESS)
+    .bind(book_id)
+    .bind(session.started_at)
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+/// Records a reading session and moves the books progress along with it
+///
+/// # Arguments
+///
+/// * `session` - The session sent by the reader
+///
+#[tauri::command]
+pub async fn record_reading_session(session: ReadingSession) -> Result<(), ShelfError> {
+    if session.ended_at < session.started_at {
+        return Err(ShelfError::invalid(
+            "A reading session can't end before it starts",
+        ));
+    }
+
+    insert_reading_session(&session)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => ShelfError::not_found(&session.book_location),
+            err => ShelfError::database("record the reading session", err),
+        })
+}
+
+/// Time spent reading on each day, days without reading are left out
+///
+/// # Arguments
+///
+/// * `days` - How many days back to look, defaults to 30
+///
+#[tauri::command]
+pub async fn get_reading_time_per_day(
+    days: Option<i64>,
+) -> Result<Vec<DailyReadingTime>, ShelfError> {
+    sqlx::query_as::<_, DailyReadingTime>(&format!(
+        "SELECT date(started_at, 'localtime') AS day, SUM({SESSION_SECONDS}) AS seconds
+         FROM reading_sessions
+         WHERE started_at >= datetime('now', '-' || $1 || ' days')
+         GROUP BY day ORDER BY day"
+    ))
+    .bind(days.unwrap_or(30).max(1))
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load reading time", err))
+}
+
+/// How many books were finished each month. A book counts in the month of its date_finished, which covers
+/// books marked finished by hand or imported, and otherwise in the month of its first session reaching the end
+#[tauri::command]
+pub async fn get_books_finished_per_month() -> Result<Vec<MonthlyFinishedBooks>, ShelfError> {
+    sqlx::query_as::<_, MonthlyFinishedBooks>(
+        "SELECT strftime('%Y-%m', finished_at, 'localtime') AS month, COUNT(*) AS books
+         FROM (SELECT coalesce(
+                   b.date_finished,
+                   (SELECT MIN(s.ended_at) FROM reading_sessions s
+                    WHERE s.book_id = b.id AND s.end_progress >= $1)
+               ) AS finished_at
+               FROM books b)
+         WHERE finished_at IS NOT NULL
+         GROUP BY month ORDER BY month",
+    )
+    .bind(FINISHED_PROGRESS)
+    .fetch_all(get_db())
+    .await
+    .map_err(|err| ShelfError::database("load finished books", err))
+}
+
+/// Reading speed across sessions, optionally limited to one book
+async fn reading_speed(book_location: Option<&str>) -> Result<ReadingSpeed, sqlx::Error> {
+    sqlx::query_as::<_, ReadingSpeed>(&format!(
+        "SELECT coalesce(SUM({SESSION_SECONDS}), 0) AS total_seconds,
+         SUM(pages_read) * 3600.0 / NULLIF(SUM(CASE WHEN pages_read IS NOT NULL THEN {SESSION_SECONDS} END), 0) AS pages_per_hour,
+         SUM(CASE WHEN end_progress > start_progress THEN end_progress - start_progress END) * 3600.0
+             / NULLIF(SUM(CASE WHEN end_progress > start_progress THEN {SESSION_SECONDS} END), 0) AS progress_per_hour
+         FROM reading_sessions
+         WHERE $1 IS NULL OR book_id = (SELECT id FROM books WHERE book_location = $1)"
+    ))
+    .bind(book_location)
+    .fetch_one(get_db())
+    .await
+}
+
+/// Average reading speed over all sessions
+#[tauri::command]
+pub async fn get_reading_speed() -> Result<ReadingSpeed, ShelfError> {
+    reading_speed(None)
+        .await
+        .map_err(|err| ShelfError::database("load reading speed", err))
+}
+
+/// Words per minute over the sessions of books with a word count, none until there's enough reading to trust it
+pub async fn measured_words_per_minute() -> Result<Option<f64>, sqlx::Error> {
+    let (seconds, words_per_minute) = sqlx::query_as::<_, (Option<i64>, Option<f64>)>(&format!(
+        "SELECT SUM({SESSION_SECONDS}),
+         SUM((s.end_progress - s.start_progress) * b.word_count) * 60.0 / NULLIF(SUM({SESSION_SECONDS}), 0)
+         FROM reading_sessions s JOIN books b ON b.id = s.book_id
+         WHERE b.word_count > 0 AND s.end_progress > s.start_progress"
+    ))
+    .fetch_one(get_db())
+    .await?;
+
+    Ok(words_per_minute.filter(|words_per_minute| {
+        *words_per_minute > 0.0 && seconds.unwrap_or_default() >= MIN_SECONDS_FOR_WORD_SPEED
+    }))
+}
+
+async fn estimate_time_left(book_location: &str) -> Result<Option<i64>, sqlx::Error> {
+    let progress =
+        sqlx::query_scalar::<_, Option<f64>>("SELECT progress FROM books WHERE book_location = $1")
+            .bind(book_location)
+            .fetch_optional(get_db())
+            .await?
+            .flatten()
+            .unwrap_or(0.0);
+
+    let book_speed = reading_speed(Some(book_location)).await?;
+    let speed = if book_speed.total_seconds >= MIN_BOOK_SECONDS_FOR_SPEED
+        && book_speed.progress_per_hour.is_some()
+    {
+        book_speed
+    } else {
+        reading_speed(None).await?
+    };
+
+    Ok(speed
+        .progress_per_hour
+        .filter(|per_hour| *per_hour > 0.0)
+        .map(|per_hour| ((1.0 - progress).max(0.0) / per_hour * 3600.0).round() as i64))
+}
+
+/// Estimates how long a book will take to finish in seconds, none if there isn't enough reading to go on
+/// The books own speed is used once there's enough of it, before that the overall speed stands in
+///
+/// # Arguments
+///
+/// * `book_location` - The book being read
+///
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_estimated_time_left(book_location: String) -> Result<Option<i64>, ShelfError> {
+    estimate_time_left(&book_location)
+        .await
+        .map_err(|err| ShelfError::database("estimate the time left", err))
+}
@@ -1,4 +1,5 @@
-use xmltree::Element;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+use xmltree::{Element, ParseError, XMLNode};
 
 /// Recursivley looks for a image element in an xml file
 ///
@@ -37,3 +38,158 @@ pub fn extract_image_source(element: &Element) -> Option<String> {
 
     None
 }
+
+/// Iterates the child elements with a name, skipping text and comments
+///
+/// # Arguments
+///
+/// * `element` - The parent element
+/// * `name` - The local name of the children to keep
+pub fn child_elements<'a>(
+    element: &'a Element,
+    name: &'a str,
+) -> impl Iterator<Item = &'a Element> {
+    element
+        .children
+        .iter()
+        .filter_map(|child| child.as_element())
+        .filter(move |child| child.name == name)
+}
+
+/// Joins the text of an element and everything inside it
+///
+/// # Arguments
+///
+/// * `element` - The element to get the text of
+pub fn element_text(element: &Element) -> String {
+    let mut text = String::new();
+    for child in &element.children {
+        match child {
+            XMLNode::Text(content) | XMLNode::CData(content) => text.push_str(content),
+            XMLNode::Element(child) => text.push_str(&element_text(child)),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+// Elements whose text isn't shown to the reader
+static HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "template"];
+// Elements that put their text on a line of its own, words either side of them are kept apart
+static BLOCK_ELEMENTS: [&str; 30] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "tr",
+    "ul",
+];
+
+pub fn is_hidden_element(element: &Element) -> bool {
+    HIDDEN_ELEMENTS.contains(&element.name.as_str())
+}
+
+pub fn is_block_element(element: &Element) -> bool {
+    BLOCK_ELEMENTS.contains(&element.name.as_str())
+}
+
+/// The text a reader sees, block elements are separated by a newline
+///
+/// # Arguments
+///
+/// * `element` - The element to get the text of, usually the whole document
+pub fn visible_text(element: &Element) -> String {
+    let mut text = String::new();
+    push_visible_text(element, &mut text);
+
+    text
+}
+
+fn push_visible_text(element: &Element, text: &mut String) {
+    for child in &element.children {
+        match child {
+            XMLNode::Text(content) | XMLNode::CData(content) => text.push_str(content),
+            XMLNode::Element(child) if is_hidden_element(child) => {}
+            XMLNode::Element(child) => {
+                push_visible_text(child, text);
+                if is_block_element(child) {
+                    text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses xml like `Element::parse`, but keeps text that's only whitespace, like the space in
+/// "<i>two</i> <i>words</i>". `Element::parse` drops it, which runs words together and moves offsets
+/// away from what a browser counts. Attributes are keyed by their local name the same way
+///
+/// # Arguments
+///
+/// * `content` - The xml to parse
+pub fn parse_preserving_whitespace(content: &[u8]) -> Result<Element, ParseError> {
+    let config = ParserConfig::new()
+        .whitespace_to_characters(true)
+        .cdata_to_characters(true)
+        .ignore_comments(true);
+    let mut open_elements: Vec<Element> = Vec::new();
+
+    for event in EventReader::new_with_config(content, config) {
+        match event.map_err(ParseError::MalformedXml)? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let mut element = Element::new(&name.local_name);
+                element.prefix = name.prefix;
+                element.namespace = name.namespace;
+                for attribute in attributes {
+                    element
+                        .attributes
+                        .insert(attribute.name.local_name, attribute.value);
+                }
+                open_elements.push(element);
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = open_elements.pop().ok_or(ParseError::CannotParse)?;
+                match open_elements.last_mut() {
+                    Some(parent) => parent.children.push(XMLNode::Element(element)),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) => {
+                if let Some(parent) = open_elements.last_mut() {
+                    parent.children.push(XMLNode::Text(text));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(ParseError::CannotParse)
+}